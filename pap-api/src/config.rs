@@ -1,7 +1,15 @@
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+use crate::PapError;
 
 /// A Config defines how to preform some analysis. The config has two sections:
 /// projects and jobs.
@@ -11,19 +19,45 @@ use serde::{Deserialize, Serialize};
 /// that is just MMIO, but in the future it could include operating system
 /// configuration.
 ///
-/// Jobs define the steps to take to analyze the projects. Currently, these
-/// steps have to be built in to the executor. In the future, they could be
-/// dynamically loaded, scripted, as a "module", similar to github actions,
-/// "actions", or written directly in the config for short routines.
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+/// Jobs define the steps to take to analyze the projects. Steps are built in
+/// to the executor, or for short routines, written directly in the config as
+/// rhai code run by the `script` step. In the future, they could be
+/// dynamically loaded, or as a "module", similar to github actions,
+/// "actions".
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
+    /// Other config files, relative to this one, whose projects, jobs, and
+    /// variables are merged into this config. Definitions here override
+    /// included ones with the same name, as do later includes earlier ones.
+    /// Only resolved by [`load_config_file`].
+    #[serde(default)]
+    pub include: Vec<String>,
     /// This defines the projects that will be used by jobs.
+    #[serde(default)]
     pub projects: Vec<Project>,
     /// This defines the jobs that will be run.
+    #[serde(default)]
     pub jobs: Vec<Job>,
+    /// Arbitrary key/value labels used to group and filter pipelines.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Most bytes of objects the pipeline's steps may store, unlimited if unset.
+    #[serde(default)]
+    pub object_quota: Option<u64>,
+    /// Most seconds the whole pipeline may run for, unlimited if unset.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Accept a config without jobs, or with jobs without steps, for
+    /// pipelines that are meant to do nothing.
+    #[serde(default)]
+    pub allow_empty: bool,
+    /// Values substituted for `${name}` in project fields and step arguments.
+    /// Write `$${` for a literal `${`, such as in a rhai template string.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Project {
     /// The name of the project. This is used to reference the project in jobs.
     pub name: String,
@@ -36,15 +70,28 @@ pub struct Project {
     pub loader: Option<LoaderConfig>,
     /// The MMIO configuration for the project.
     pub mmio: Vec<MMIOEntry>,
+    /// Emulator settings for the project.
+    #[serde(default)]
+    pub vm: VmConfig,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct LoaderConfig {
     pub base_address: u64,
     pub stack_address: u64,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+/// Overrides for how the emulator runs a project. Unset options are off, which
+/// is slower but easier to debug than translating guest code.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct VmConfig {
+    pub enable_jit: Option<bool>,
+    pub enable_jit_mem: Option<bool>,
+    pub enable_recompilation: Option<bool>,
+    pub enable_shadow_stack: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct MMIOEntry {
     pub address: u64,
     #[serde(default = "one")]
@@ -52,23 +99,580 @@ pub struct MMIOEntry {
     pub handler: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Job {
     pub name: String,
     pub steps: Vec<Step>,
+    /// Most seconds the job may run for, unlimited if unset.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+/// Shown in place of each `env` value anywhere but the step itself
+pub const REDACTED_ENV: &str = "<redacted>";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Step {
     pub name: String,
     pub call: String,
     pub args: HashMap<String, String>,
+    /// Where the step reads and writes its data, by IO field name. Values
+    /// are object store namespaces, or `step://<step-name>/output` to read
+    /// an earlier step's output. Fields the executor writes to that are left
+    /// unset get a namespace of the step's own.
     #[serde(default)]
     pub io: HashMap<String, String>,
+    /// Values made available to the step, such as secrets that shouldn't be
+    /// committed to the config. `${NAME}` references to the submitter's
+    /// environment are resolved by [`Config::resolve_env`] before submission.
+    /// The values are left out of the step's debug output, and are redacted
+    /// in the statuses the server returns.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Delete the objects this step wrote if it fails, including keys it
+    /// overwrote. Overwritten values are deleted, not restored.
+    #[serde(default)]
+    pub cleanup_on_failure: bool,
+}
+
+impl std::fmt::Debug for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let env: HashMap<_, _> = self.env.keys().map(|key| (key, REDACTED_ENV)).collect();
+        f.debug_struct("Step")
+            .field("name", &self.name)
+            .field("call", &self.call)
+            .field("args", &self.args)
+            .field("io", &self.io)
+            .field("env", &env)
+            .field("cleanup_on_failure", &self.cleanup_on_failure)
+            .finish()
+    }
+}
+
+/// The languages a config can be written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Json,
+    /// Step arguments are strings, so in TOML they must be quoted even when
+    /// they hold a number or boolean, as in `function = "0x8074e50"`. Numeric
+    /// fields such as addresses may use TOML's own hex integers, but TOML
+    /// integers are signed, so values above `i64::MAX` can't be written.
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Picks the format of a config file by its extension, with files other
+    /// than `.json` or `.toml` read as YAML
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    fn parse(self, mut reader: impl Read) -> Result<Config, String> {
+        match self {
+            ConfigFormat::Yaml => serde_yaml::from_reader(reader).map_err(|e| e.to_string()),
+            #[cfg(feature = "serde_json")]
+            ConfigFormat::Json => serde_json::from_reader(reader).map_err(|e| e.to_string()),
+            #[cfg(not(feature = "serde_json"))]
+            ConfigFormat::Json => {
+                drop(reader);
+                Err("JSON configs need the serde_json feature".to_string())
+            }
+            ConfigFormat::Toml => {
+                let mut text = String::new();
+                reader
+                    .read_to_string(&mut text)
+                    .map_err(|e| e.to_string())?;
+                toml::from_str(&text).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Parses a YAML config and substitutes its variables. Referencing a variable
+/// that isn't defined in `vars`, or reusing a name, is a configuration error.
+pub fn load_config(reader: impl Read) -> Result<Config, PapError> {
+    load_config_as(reader, ConfigFormat::Yaml)
+}
+
+/// Parses a config in the given format, as [`load_config`] does for YAML.
+/// Includes are relative to a file, so a config that has any is a
+/// configuration error; load it with [`load_config_file`] instead.
+pub fn load_config_as(reader: impl Read, format: ConfigFormat) -> Result<Config, PapError> {
+    let mut config = format.parse(reader).map_err(PapError::Configuration)?;
+    if !config.include.is_empty() {
+        return Err(PapError::Configuration(
+            "includes are only resolved when loading a config file".to_string(),
+        ));
+    }
+    config.interpolate_vars()?;
+    config.check_unique_names()?;
+    Ok(config)
+}
+
+/// Loads a config from a file, merging in the configs it includes, then
+/// substitutes its variables. Include cycles are a configuration error. Each
+/// file's format is picked by its extension.
+pub fn load_config_file(path: &Path) -> Result<Config, PapError> {
+    load_config_file_as(path, ConfigFormat::from_path(path))
+}
+
+/// Loads a config file in the given format, as [`load_config_file`] does. The
+/// files it includes are still read in the format of their extension.
+pub fn load_config_file_as(path: &Path, format: ConfigFormat) -> Result<Config, PapError> {
+    let mut config = load_with_includes(path, format, &mut Vec::new())?;
+    config.interpolate_vars()?;
+    config.check_unique_names()?;
+    Ok(config)
+}
+
+/// Parses a config file with its includes merged in, tracking the files being
+/// included to detect cycles
+fn load_with_includes(
+    path: &Path,
+    format: ConfigFormat,
+    including: &mut Vec<PathBuf>,
+) -> Result<Config, PapError> {
+    let read_error = |e: std::io::Error| {
+        PapError::Configuration(format!("failed to read {}: {}", path.display(), e))
+    };
+    let path = path.canonicalize().map_err(read_error)?;
+    if including.contains(&path) {
+        let cycle: Vec<_> = including
+            .iter()
+            .chain([&path])
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(PapError::Configuration(format!(
+            "include cycle: {}",
+            cycle.join(" -> ")
+        )));
+    }
+
+    let file = File::open(&path).map_err(read_error)?;
+    let mut config = format
+        .parse(file)
+        .map_err(|e| PapError::Configuration(format!("{}: {}", path.display(), e)))?;
+
+    let base_path = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    including.push(path);
+    let mut merged: Option<Config> = None;
+    for include in std::mem::take(&mut config.include) {
+        let include_path = base_path.join(&include);
+        let include_format = ConfigFormat::from_path(&include_path);
+        let mut included = load_with_includes(&include_path, include_format, including)?;
+
+        // Keep binaries relative to the file that names them
+        let include_dir = Path::new(&include).parent().unwrap_or(Path::new(""));
+        for project in &mut included.projects {
+            if Path::new(&project.binary).is_relative() {
+                project.binary = include_dir
+                    .join(&project.binary)
+                    .to_string_lossy()
+                    .into_owned();
+            }
+        }
+
+        merged = Some(match merged {
+            Some(mut merged) => {
+                merged.merge(included);
+                merged
+            }
+            None => included,
+        });
+    }
+    including.pop();
+
+    Ok(match merged {
+        Some(mut merged) => {
+            merged.merge(config);
+            merged
+        }
+        None => config,
+    })
+}
+
+impl Config {
+    /// Starts building a config in code rather than parsing one
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Merges another config into this one, with its definitions replacing
+    /// this config's projects and jobs of the same name
+    fn merge(&mut self, other: Config) {
+        for project in other.projects {
+            match self.projects.iter_mut().find(|p| p.name == project.name) {
+                Some(existing) => *existing = project,
+                None => self.projects.push(project),
+            }
+        }
+        for job in other.jobs {
+            match self.jobs.iter_mut().find(|j| j.name == job.name) {
+                Some(existing) => *existing = job,
+                None => self.jobs.push(job),
+            }
+        }
+        self.labels.extend(other.labels);
+        self.vars.extend(other.vars);
+        self.object_quota = other.object_quota.or(self.object_quota);
+        self.timeout_secs = other.timeout_secs.or(self.timeout_secs);
+        self.allow_empty |= other.allow_empty;
+    }
+
+    fn interpolate_vars(&mut self) -> Result<(), PapError> {
+        let vars = &self.vars;
+        let lookup = |name: &str| {
+            vars.get(name)
+                .cloned()
+                .ok_or_else(|| PapError::Configuration(format!("undefined variable: {}", name)))
+        };
+
+        for project in &mut self.projects {
+            project.name = interpolate(&project.name, lookup)?;
+            project.binary = interpolate(&project.binary, lookup)?;
+            project.arch = interpolate(&project.arch, lookup)?;
+            for entry in &mut project.mmio {
+                entry.handler = interpolate(&entry.handler, lookup)?;
+            }
+        }
+
+        for job in &mut self.jobs {
+            for step in &mut job.steps {
+                for value in step.args.values_mut() {
+                    *value = interpolate(value, lookup)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Substitutes `${NAME}` references in each step's `env` using `lookup`,
+    /// normally the submitter's environment. A reference `lookup` can't
+    /// resolve is a configuration error.
+    pub fn resolve_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), PapError> {
+        let lookup = |name: &str| {
+            lookup(name).ok_or_else(|| {
+                PapError::Configuration(format!("environment variable not set: {}", name))
+            })
+        };
+
+        for job in &mut self.jobs {
+            for step in &mut job.steps {
+                for value in step.env.values_mut() {
+                    *value = interpolate(value, lookup)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces every step's `env` values with [`REDACTED_ENV`], keeping the
+    /// names
+    pub fn redact_env(&mut self) {
+        for job in &mut self.jobs {
+            job.redact_env();
+        }
+    }
+
+    /// Checks that no two projects or jobs share a name, and no two steps
+    /// within a job do, since lookups by name would only ever find the first
+    pub fn check_unique_names(&self) -> Result<(), PapError> {
+        let duplicate = |what: String| Err(PapError::Configuration(format!("duplicate {}", what)));
+
+        if let Some(name) = find_duplicate(self.projects.iter().map(|p| p.name.as_str())) {
+            return duplicate(format!("project name: {}", name));
+        }
+        if let Some(name) = find_duplicate(self.jobs.iter().map(|j| j.name.as_str())) {
+            return duplicate(format!("job name: {}", name));
+        }
+        for job in &self.jobs {
+            if let Some(name) = find_duplicate(job.steps.iter().map(|s| s.name.as_str())) {
+                return duplicate(format!("step name in job {}: {}", job.name, name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`Config`] in code. Projects, jobs, and steps can be given as their
+/// builders, which are finished when added.
+///
+/// ```
+/// use pap_api::{Config, Job, Project, Step};
+///
+/// let config = Config::builder()
+///     .project(
+///         Project::builder("fw", "fw.bin", "thumbv7m-none-eabi").loader(0x8000, 0x2001_0000),
+///     )
+///     .job(
+///         Job::builder("fuzz").step(
+///             Step::builder("fuzz", "icicle-fuzzer")
+///                 .arg("project", "fw")
+///                 .arg("function", "0x8000")
+///                 .io("input", "seeds"),
+///         ),
+///     )
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.jobs[0].steps[0].args["function"], "0x8000");
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn project(mut self, project: impl Into<Project>) -> Self {
+        self.config.projects.push(project.into());
+        self
+    }
+
+    pub fn job(mut self, job: impl Into<Job>) -> Self {
+        self.config.jobs.push(job.into());
+        self
+    }
+
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.vars.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn object_quota(mut self, bytes: u64) -> Self {
+        self.config.object_quota = Some(bytes);
+        self
+    }
+
+    pub fn timeout_secs(mut self, secs: u64) -> Self {
+        self.config.timeout_secs = Some(secs);
+        self
+    }
+
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.config.allow_empty = allow_empty;
+        self
+    }
+
+    /// Substitutes variables and checks names like [`load_config`] does
+    pub fn build(self) -> Result<Config, PapError> {
+        let mut config = self.config;
+        config.interpolate_vars()?;
+        config.check_unique_names()?;
+        Ok(config)
+    }
+}
+
+impl Project {
+    pub fn builder(
+        name: impl Into<String>,
+        binary: impl Into<String>,
+        arch: impl Into<String>,
+    ) -> ProjectBuilder {
+        ProjectBuilder {
+            project: Project {
+                name: name.into(),
+                binary: binary.into(),
+                arch: arch.into(),
+                loader: None,
+                mmio: Vec::new(),
+                vm: VmConfig::default(),
+            },
+        }
+    }
+}
+
+/// Builds a [`Project`] for a [`ConfigBuilder`]
+#[derive(Debug)]
+pub struct ProjectBuilder {
+    project: Project,
+}
+
+impl ProjectBuilder {
+    pub fn loader(mut self, base_address: u64, stack_address: u64) -> Self {
+        self.project.loader = Some(LoaderConfig {
+            base_address,
+            stack_address,
+        });
+        self
+    }
+
+    pub fn mmio(mut self, address: u64, size: u64, handler: impl Into<String>) -> Self {
+        self.project.mmio.push(MMIOEntry {
+            address,
+            size,
+            handler: handler.into(),
+        });
+        self
+    }
+
+    pub fn vm(mut self, vm: VmConfig) -> Self {
+        self.project.vm = vm;
+        self
+    }
+
+    pub fn build(self) -> Project {
+        self.project
+    }
+}
+
+impl From<ProjectBuilder> for Project {
+    fn from(builder: ProjectBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl Job {
+    pub fn builder(name: impl Into<String>) -> JobBuilder {
+        JobBuilder {
+            job: Job {
+                name: name.into(),
+                steps: Vec::new(),
+                timeout_secs: None,
+            },
+        }
+    }
+
+    /// Replaces every step's `env` values with [`REDACTED_ENV`], keeping the
+    /// names
+    pub fn redact_env(&mut self) {
+        for step in &mut self.steps {
+            step.redact_env();
+        }
+    }
+}
+
+/// Builds a [`Job`] for a [`ConfigBuilder`]
+#[derive(Debug)]
+pub struct JobBuilder {
+    job: Job,
+}
+
+impl JobBuilder {
+    pub fn step(mut self, step: impl Into<Step>) -> Self {
+        self.job.steps.push(step.into());
+        self
+    }
+
+    pub fn timeout_secs(mut self, secs: u64) -> Self {
+        self.job.timeout_secs = Some(secs);
+        self
+    }
+
+    pub fn build(self) -> Job {
+        self.job
+    }
+}
+
+impl From<JobBuilder> for Job {
+    fn from(builder: JobBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl Step {
+    /// Starts building a step that calls the executor named `call`
+    pub fn builder(name: impl Into<String>, call: impl Into<String>) -> StepBuilder {
+        StepBuilder {
+            step: Step {
+                name: name.into(),
+                call: call.into(),
+                args: HashMap::new(),
+                io: HashMap::new(),
+                env: HashMap::new(),
+                cleanup_on_failure: false,
+            },
+        }
+    }
+
+    /// Replaces the `env` values with [`REDACTED_ENV`], keeping the names
+    pub fn redact_env(&mut self) {
+        for value in self.env.values_mut() {
+            *value = REDACTED_ENV.to_string();
+        }
+    }
+}
+
+/// Builds a [`Step`] for a [`JobBuilder`]
+#[derive(Debug)]
+pub struct StepBuilder {
+    step: Step,
+}
+
+impl StepBuilder {
+    pub fn arg(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.step.args.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn io(mut self, name: impl Into<String>, namespace: impl Into<String>) -> Self {
+        self.step.io.insert(name.into(), namespace.into());
+        self
+    }
+
+    pub fn env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.step.env.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn cleanup_on_failure(mut self, cleanup_on_failure: bool) -> Self {
+        self.step.cleanup_on_failure = cleanup_on_failure;
+        self
+    }
+
+    pub fn build(self) -> Step {
+        self.step
+    }
+}
+
+impl From<StepBuilder> for Step {
+    fn from(builder: StepBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Returns the first name that appears more than once
+fn find_duplicate<'a>(mut names: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    names.find(|name| !seen.insert(*name))
 }
 
-pub fn load_config(reader: impl Read) -> Result<Config, serde_yaml::Error> {
-    serde_yaml::from_reader(reader)
+/// Replaces each `${name}` in a value with what `lookup` gives for the name.
+/// `$${` is left as a literal `${`.
+fn interpolate(
+    value: &str,
+    lookup: impl Fn(&str) -> Result<String, PapError>,
+) -> Result<String, PapError> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        if let Some(before) = rest[..start].strip_suffix('$') {
+            result.push_str(before);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            PapError::Configuration(format!("unterminated variable reference in: {}", value))
+        })?;
+        result.push_str(&lookup(&rest[start + 2..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
 }
 
 fn one() -> u64 {
@@ -1,6 +1,10 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::Config;
@@ -12,9 +16,12 @@ pub struct Context {
 }
 
 impl Context {
+    pub fn builder(config: Config, path: PathBuf) -> ContextBuilder {
+        ContextBuilder::new(config, path)
+    }
+
     pub fn build_with_config(config: Config, path: PathBuf) -> Result<Self> {
-        let files = find_files_in_config(&config, path)?;
-        Ok(Self { config, files })
+        Self::builder(config, path).build()
     }
 
     pub fn config(&self) -> &Config {
@@ -26,15 +33,63 @@ impl Context {
     }
 }
 
-fn find_files_in_config(config: &Config, base_path: PathBuf) -> Result<HashMap<String, Vec<u8>>> {
-    let mut files = HashMap::new();
+/// Builds a [`Context`] from a config, reading each project's binary relative
+/// to a base path. Binaries that don't exist are left out, and only fail the
+/// steps that load them. Extra files can be attached for steps to read by name.
+#[derive(Debug)]
+pub struct ContextBuilder {
+    config: Config,
+    base_path: PathBuf,
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl ContextBuilder {
+    pub fn new(config: Config, base_path: PathBuf) -> Self {
+        Self {
+            config,
+            base_path,
+            files: HashMap::new(),
+        }
+    }
 
+    /// Attach an extra file to the context. A file added with the same name as
+    /// a project binary takes precedence over reading the binary from disk.
+    pub fn add_file(mut self, name: impl Into<String>, data: Vec<u8>) -> Self {
+        self.files.insert(name.into(), data);
+        self
+    }
+
+    pub fn build(self) -> Result<Context> {
+        let mut files = self.files;
+        find_files_in_config(&self.config, &self.base_path, &mut files)?;
+        Ok(Context {
+            config: self.config,
+            files,
+        })
+    }
+}
+
+fn find_files_in_config(
+    config: &Config,
+    base_path: &Path,
+    files: &mut HashMap<String, Vec<u8>>,
+) -> Result<()> {
     for project in &config.projects {
+        // Projects may share a binary, only read it once
+        if files.contains_key(&project.binary) {
+            continue;
+        }
+
+        // A project no step uses shouldn't block submission, so a missing
+        // binary is left for the steps that need it to report
         let full_path = base_path.join(&project.binary);
-        let data = std::fs::read(&full_path)
-            .map_err(|e| anyhow!("Failed to open {}: {}", full_path.to_string_lossy(), e))?;
+        let data = match std::fs::read(&full_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => bail!("Failed to open {}: {}", full_path.to_string_lossy(), e),
+        };
         files.insert(project.binary.clone(), data);
     }
 
-    Ok(files)
+    Ok(())
 }
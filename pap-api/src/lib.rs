@@ -3,8 +3,15 @@ mod context;
 #[cfg(test)]
 mod test;
 
-pub use config::{load_config, Config, Job, LoaderConfig, MMIOEntry, Project, Step};
-pub use context::Context;
+pub use config::{
+    load_config, load_config_as, load_config_file, load_config_file_as, Config, ConfigBuilder,
+    ConfigFormat, Job, JobBuilder, LoaderConfig, MMIOEntry, Project, ProjectBuilder, Step,
+    StepBuilder, VmConfig, REDACTED_ENV,
+};
+pub use context::{Context, ContextBuilder};
+
+use std::path::PathBuf;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use strum::EnumString;
@@ -19,13 +26,39 @@ pub enum ExecutionStatus {
     Cancelled,
 }
 
+impl ExecutionStatus {
+    /// Whether this is a final status that won't change without a resume
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            ExecutionStatus::Completed | ExecutionStatus::Failed | ExecutionStatus::Cancelled
+        )
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PipelineStatus {
     pub id: u32,
     pub config: Config,
     pub status: ExecutionStatus,
     pub jobs: Vec<u32>,
-    pub error: Option<String>,
+    /// Why the pipeline stopped early, if it failed or was cancelled
+    pub error: Option<PapError>,
+    /// The job containing the step that failed, if a step failure stopped the pipeline
+    pub failed_job: Option<u32>,
+    /// The step that failed, if a step failure stopped the pipeline
+    pub failed_step: Option<u32>,
+    /// When the pipeline was submitted, as a UTC `YYYY-MM-DD HH:MM:SS` timestamp
+    pub created_at: Option<String>,
+    /// Whether steps only perform their setup instead of running for real
+    pub dry_run: bool,
+    /// Steps that have completed, across all jobs
+    pub completed_steps: u32,
+    /// Steps in the pipeline, across all jobs
+    pub total_steps: u32,
+    /// Fraction of the pipeline's steps that have completed, from 0 to 1. A
+    /// pipeline without steps reports 0.
+    pub progress: f32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -42,10 +75,161 @@ pub struct StepStatus {
     pub id: u32,
     pub config: Step,
     pub status: ExecutionStatus,
-    pub output: Option<Vec<u8>>,
+    /// What the step logged, once it has finished
+    pub output: Option<StepOutput>,
+    /// Statistics the step reported as JSON, such as the fuzzer's execution
+    /// count and corpus size. Once the fuzzer finishes, its `solution_keys`
+    /// lists the hex keys of the solutions it stored during the run.
+    pub stats: Option<String>,
+    /// When the step started running, as a UTC `YYYY-MM-DD HH:MM:SS.SSS` timestamp
+    pub started_at: Option<String>,
+    /// When the step reached a terminal state, in the same format as `started_at`
+    pub finished_at: Option<String>,
+    /// How long the step ran, if it has both started and finished
+    pub duration: Option<Duration>,
+}
+
+/// How the bytes of a step's output should be read
+#[derive(
+    Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq, EnumString, strum::Display,
+)]
+pub enum OutputKind {
+    /// UTF-8 text, usually log lines
+    #[default]
+    Text,
+    /// A JSON document
+    Json,
+    /// Arbitrary bytes
+    Binary,
+}
+
+/// The output of a step, along with how to read it
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct StepOutput {
+    pub kind: OutputKind,
+    pub data: Vec<u8>,
+}
+
+/// The kind of value a step argument expects. Arguments are always passed as
+/// strings; this describes how the executor will interpret them.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, strum::Display)]
+pub enum ArgType {
+    String,
+    Integer,
+    Boolean,
+    /// An integer address, written in hex with an optional `0x` prefix
+    Address,
+    /// Comma-separated values
+    List,
+}
+
+/// Describes a single argument accepted by a step executor.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ArgSchema {
+    pub name: String,
+    pub arg_type: ArgType,
+    pub required: bool,
+    /// The value used when the argument is omitted, if any
+    pub default: Option<String>,
+    pub description: String,
+}
+
+/// Describes a step executor registered with a server.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ExecutorInfo {
+    /// The name steps use in their `call` field
+    pub name: String,
+    pub args: Vec<ArgSchema>,
+    /// The IO fields the executor requires
+    pub io: Vec<String>,
+}
+
+/// The outcome of submitting a pipeline.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SubmitResult {
+    /// The ID of the submitted pipeline
+    pub id: u32,
+    /// Advisories about the config that didn't stop it being accepted, such
+    /// as a project that no step uses
+    pub warnings: Vec<String>,
+}
+
+/// Reports whether a server is able to do work.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct HealthStatus {
+    /// The server's version
+    pub version: String,
+    /// Whether the server can reach its database
+    pub db_ok: bool,
+    /// Number of pipelines that are pending or running
+    pub active_pipelines: u32,
+}
+
+/// A change in the lifecycle of a pipeline, job, or step.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum PipelineEvent {
+    /// The pipeline was queued to run, on submission or when resumed
+    PipelineSubmitted {
+        pipeline_id: u32,
+    },
+    JobStarted {
+        pipeline_id: u32,
+        job_id: u32,
+    },
+    StepStarted {
+        pipeline_id: u32,
+        job_id: u32,
+        step_id: u32,
+    },
+    /// The step completed, failed, or was cancelled
+    StepFinished {
+        pipeline_id: u32,
+        job_id: u32,
+        step_id: u32,
+        status: ExecutionStatus,
+    },
+    /// The job completed, failed, or was cancelled
+    JobFinished {
+        pipeline_id: u32,
+        job_id: u32,
+        status: ExecutionStatus,
+    },
+    /// The pipeline completed, failed, or was cancelled, and any error has
+    /// been recorded
+    PipelineFinished {
+        pipeline_id: u32,
+        status: ExecutionStatus,
+    },
+}
+
+/// The kind of thing whose status changed in a [`StatusChange`].
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, EnumString, strum::Display)]
+pub enum StatusEntity {
+    Pipeline,
+    Job,
+    Step,
+}
+
+/// A pipeline, job, or step moving from one status to another.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct StatusChange {
+    pub entity_type: StatusEntity,
+    pub entity_id: u32,
+    pub old_status: ExecutionStatus,
+    pub new_status: ExecutionStatus,
+    /// When the status changed, as a UTC `YYYY-MM-DD HH:MM:SS.SSS` timestamp
+    pub timestamp: String,
+}
+
+/// A pipeline event numbered in the order the server published it.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct EventRecord {
+    /// Increases by one with each event, so clients can ask for what follows
+    pub id: u64,
+    pub event: PipelineEvent,
 }
 
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Clone, Debug, Serialize, Deserialize)]
 pub enum PapError {
     #[error("Resource not found: {0}")]
     NotFound(String),
@@ -55,6 +239,12 @@ pub enum PapError {
     Configuration(String),
     #[error("Execution error: {0}")]
     Execution(String),
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -78,7 +268,9 @@ impl From<sqlx::Error> for PapError {
 
 impl From<anyhow::Error> for PapError {
     fn from(err: anyhow::Error) -> Self {
-        PapError::Internal(err.to_string())
+        // Keep errors that were raised as a PapError intact
+        err.downcast::<PapError>()
+            .unwrap_or_else(|err| PapError::Internal(err.to_string()))
     }
 }
 
@@ -94,9 +286,74 @@ impl From<strum::ParseError> for PapError {
     }
 }
 
+/// Where a server listens and clients connect: a TCP address, or a unix domain
+/// socket given as `unix:<path>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for ServerAddr {
+    type Err = PapError;
+
+    fn from_str(addr: &str) -> Result<Self, Self::Err> {
+        match addr.strip_prefix("unix:") {
+            Some("") => Err(PapError::Configuration(
+                "unix socket address has no path".to_string(),
+            )),
+            Some(path) => Ok(ServerAddr::Unix(PathBuf::from(path))),
+            None => Ok(ServerAddr::Tcp(addr.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for ServerAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerAddr::Tcp(addr) => write!(f, "{}", addr),
+            ServerAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Version of the `PapApi` protocol. Bump this whenever an RPC or a type sent
+/// over it changes incompatibly.
+pub const API_VERSION: u32 = 28;
+
+/// Checks that a peer speaks the same protocol version as this crate.
+pub fn check_api_version(version: u32) -> Result<(), PapError> {
+    if version != API_VERSION {
+        return Err(PapError::Configuration(format!(
+            "server speaks API version {}, but this client speaks version {}",
+            version, API_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Decodes an object key written as hex bytes, e.g. `000000000000002a` for a
+/// corpus entry, the way keys that aren't UTF-8 are passed around as text.
+pub fn parse_hex_key(key: &str) -> Result<Vec<u8>, PapError> {
+    if !key.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(PapError::Configuration(format!("Invalid hex key: {}", key)));
+    }
+    if !key.len().is_multiple_of(2) {
+        return Err(PapError::Configuration(format!(
+            "Hex key must have an even number of digits: {}",
+            key
+        )));
+    }
+    Ok((0..key.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&key[i..i + 2], 16).expect("checked hex digits"))
+        .collect())
+}
+
 /// PapApi represents the public functionality of Program Analysis Pipelines.
-/// Functionality is split into three categories: pipeline management, job
-/// management, and object storage.
+/// Functionality is split into five categories: pipeline management, job
+/// management, executor discovery, server status and events, and object
+/// storage.
 #[tarpc::service]
 #[allow(async_fn_in_trait)]
 pub trait PapApi {
@@ -108,8 +365,35 @@ pub trait PapApi {
     /// * `pipeline_context` - The pipeline context containing configuration and execution details
     ///
     /// # Returns
-    /// The unique ID of the submitted pipeline
-    async fn submit_pipeline(pipeline_context: Context) -> Result<u32, PapError>;
+    /// The unique ID of the submitted pipeline and any warnings about its config
+    async fn submit_pipeline(pipeline_context: Context) -> Result<SubmitResult, PapError>;
+
+    /// Submits a new pipeline whose steps only validate and set themselves up,
+    /// skipping their expensive work. Useful for checking a pipeline's wiring.
+    ///
+    /// # Arguments
+    /// * `pipeline_context` - The pipeline context containing configuration and execution details
+    ///
+    /// # Returns
+    /// The unique ID of the submitted pipeline and any warnings about its config
+    async fn dry_run_pipeline(pipeline_context: Context) -> Result<SubmitResult, PapError>;
+
+    /// Submits a copy of an existing pipeline for execution, reusing its stored
+    /// configuration and files.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the pipeline to resubmit
+    ///
+    /// # Returns
+    /// The unique ID of the newly submitted pipeline
+    async fn resubmit_pipeline(id: u32) -> Result<u32, PapError>;
+
+    /// Runs a failed or cancelled pipeline again, starting from its first step
+    /// that didn't complete. Completed steps are not re-executed.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the pipeline to resume
+    async fn resume_pipeline(id: u32) -> Result<(), PapError>;
 
     /// Retrieves information about a specific pipeline.
     ///
@@ -120,18 +404,65 @@ pub trait PapApi {
     /// Pipeline information if found, None otherwise
     async fn get_pipeline(id: u32) -> Result<PipelineStatus, PapError>;
 
+    /// Retrieves information about several pipelines at once, which is
+    /// cheaper than asking for each in turn.
+    ///
+    /// # Arguments
+    /// * `ids` - The unique identifiers of the pipelines
+    ///
+    /// # Returns
+    /// Information about each pipeline that exists, in the order requested
+    async fn get_pipeline_statuses(ids: Vec<u32>) -> Result<Vec<PipelineStatus>, PapError>;
+
+    /// Exports the config a pipeline ran with as YAML, for comparing with the
+    /// config it was submitted from. IO fields the config left unset show the
+    /// namespaces the server gave them, and `env` values show [`REDACTED_ENV`].
+    ///
+    /// # Arguments
+    /// * `id` - The unique identifier of the pipeline
+    ///
+    /// # Returns
+    /// The pipeline's config as YAML
+    async fn export_config(id: u32) -> Result<String, PapError>;
+
     /// Retrieves a list of all pipeline IDs in the system.
     ///
     /// # Returns
-    /// A vector containing IDs of all pipelines
+    /// A vector containing IDs of all pipelines, most recently submitted first
     async fn get_pipelines() -> Result<Vec<u32>, PapError>;
 
-    /// Cancels the execution of a running pipeline.
+    /// Retrieves the IDs of all pipelines carrying a given label.
+    ///
+    /// # Arguments
+    /// * `key` - The label key to match
+    /// * `value` - The value the label must have
+    ///
+    /// # Returns
+    /// A vector containing IDs of matching pipelines, most recently submitted first
+    async fn get_pipelines_by_label(key: String, value: String) -> Result<Vec<u32>, PapError>;
+
+    /// Counts the pipelines in the system.
+    ///
+    /// # Arguments
+    /// * `status` - Only count pipelines with this status, if set
+    ///
+    /// # Returns
+    /// The number of matching pipelines
+    async fn count_pipelines(status: Option<ExecutionStatus>) -> Result<u64, PapError>;
+
+    /// Cancels the execution of a pending or running pipeline.
     ///
     /// # Arguments
     /// * `id` - The unique ID of the pipeline to cancel
     async fn cancel_pipeline(id: u32) -> Result<(), PapError>;
 
+    /// Cancels every pipeline that is pending or running, such as before
+    /// maintenance.
+    ///
+    /// # Returns
+    /// The number of pipelines cancelled
+    async fn cancel_all_pipelines() -> Result<u64, PapError>;
+
     /// Deletes a pipeline and its associated data from the system.
     ///
     /// # Arguments
@@ -148,6 +479,15 @@ pub trait PapApi {
     /// Job information including name, status, and current step
     async fn get_job(id: u32) -> Result<JobStatus, PapError>;
 
+    /// Retrieves information about a specific step.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the step
+    ///
+    /// # Returns
+    /// Step information including its configuration, status, and output
+    async fn get_step(id: u32) -> Result<StepStatus, PapError>;
+
     /// Retrieves the log output of a specific step.
     ///
     /// # Arguments
@@ -157,18 +497,111 @@ pub trait PapApi {
     /// The complete log output as a byte vector
     async fn get_step_log(id: u32) -> Result<Vec<u8>, PapError>;
 
+    /// Retrieves the log output of every step of a pipeline that has one.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the pipeline
+    ///
+    /// # Returns
+    /// Each step's ID and log, in the order the steps run
+    async fn get_pipeline_logs(id: u32) -> Result<Vec<(u32, Vec<u8>)>, PapError>;
+
+    /// Looks up a step of a pipeline by name, such as to fetch its log without
+    /// knowing its ID.
+    ///
+    /// # Arguments
+    /// * `pipeline_id` - The unique ID of the pipeline
+    /// * `job` - The name of the job the step belongs to, needed if several
+    ///   jobs have a step of that name
+    /// * `step` - The name of the step
+    ///
+    /// # Returns
+    /// The unique ID of the step
+    async fn find_step(
+        pipeline_id: u32,
+        job: Option<String>,
+        step: String,
+    ) -> Result<u32, PapError>;
+
     /// Retrieves a list of all job IDs in the system.
     ///
     /// # Returns
     /// A vector containing IDs of all jobs
     async fn get_jobs() -> Result<Vec<u32>, PapError>;
 
-    /// Cancels the execution of a running job.
+    /// Counts the jobs in the system.
+    ///
+    /// # Arguments
+    /// * `status` - Only count jobs with this status, if set
+    ///
+    /// # Returns
+    /// The number of matching jobs
+    async fn count_jobs(status: Option<ExecutionStatus>) -> Result<u64, PapError>;
+
+    /// Retrieves the IDs of the jobs belonging to a pipeline.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the pipeline
+    ///
+    /// # Returns
+    /// A vector containing IDs of the pipeline's jobs, in the order they run
+    async fn get_pipeline_jobs(id: u32) -> Result<Vec<u32>, PapError>;
+
+    /// Retrieves every status change of a pipeline and its jobs and steps.
+    ///
+    /// # Arguments
+    /// * `id` - The unique ID of the pipeline
+    ///
+    /// # Returns
+    /// The changes in the order they happened
+    async fn get_pipeline_history(id: u32) -> Result<Vec<StatusChange>, PapError>;
+
+    /// Cancels the execution of a pending or running job.
     ///
     /// # Arguments
     /// * `id` - The unique identifier of the job to cancel
     async fn cancel_job(id: u32) -> Result<(), PapError>;
 
+    /// Cancels a single pending or running step. The rest of its job still
+    /// runs.
+    ///
+    /// # Arguments
+    /// * `id` - The unique identifier of the step to cancel
+    async fn cancel_step(id: u32) -> Result<(), PapError>;
+
+    // Executor discovery
+    /// Lists the step executors available on the server.
+    ///
+    /// # Returns
+    /// The name, argument schema, and required IO fields of each executor
+    async fn list_executors() -> Vec<ExecutorInfo>;
+
+    // Server status and events
+    /// Checks that the server is up and its database is reachable.
+    ///
+    /// # Returns
+    /// The server version, database reachability, and number of active pipelines
+    async fn health() -> Result<HealthStatus, PapError>;
+
+    /// Reports the protocol version the server speaks, so clients can detect
+    /// incompatibilities before making other calls.
+    ///
+    /// # Returns
+    /// The server's `API_VERSION`
+    async fn api_version() -> Result<u32, PapError>;
+
+    /// Retrieves pipeline lifecycle events. tarpc has no streaming responses,
+    /// so clients long-poll: if no events newer than `after` exist yet, the
+    /// server waits a few seconds for one before answering.
+    ///
+    /// # Arguments
+    /// * `after` - The id of the last event already seen, or None for every
+    ///   event the server still remembers
+    ///
+    /// # Returns
+    /// The newer events in the order they happened, possibly none
+    async fn events(after: Option<u64>) -> Result<Vec<EventRecord>, PapError>;
+
     // Object storage
     /// Retrieves an object from the storage system.
     ///
@@ -180,6 +613,22 @@ pub trait PapApi {
     /// The object's data as a byte vector
     async fn get_object(namespace: String, key: Vec<u8>) -> Result<Vec<u8>, PapError>;
 
+    /// Checks whether an object is stored, without transferring its value.
+    ///
+    /// # Arguments
+    /// * `namespace` - The namespace where the object would be stored
+    /// * `key` - The unique key identifying the object
+    ///
+    /// # Returns
+    /// Whether the object exists
+    async fn object_exists(namespace: String, key: Vec<u8>) -> Result<bool, PapError>;
+
+    /// Retrieves the names of all namespaces holding at least one object.
+    ///
+    /// # Returns
+    /// The namespace names in alphabetical order
+    async fn list_namespaces() -> Result<Vec<String>, PapError>;
+
     /// Stores an object in the storage system.
     ///
     /// # Arguments
@@ -15,3 +15,594 @@ fn test_load_sample_config() {
     assert_eq!(config.jobs.len(), 1);
     assert_eq!(config.jobs[0].steps[0].args["function"], "0x8074e50");
 }
+
+const SAMPLE_YAML: &str = r#"
+projects:
+  - name: testbin
+    binary: test.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: 0x8000000
+      stack_address: 0x20010000
+    mmio:
+      - address: 0x40000000
+        size: 0x400
+        handler: uart
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz-parser
+        call: icicle-fuzzer
+        args:
+          project: testbin
+          function: "0x8074e50"
+        io:
+          output: crashes
+labels:
+  team: firmware
+"#;
+
+const SAMPLE_JSON: &str = r#"{
+  "projects": [
+    {
+      "name": "testbin",
+      "binary": "test.bin",
+      "arch": "thumbv7m-none-eabi",
+      "loader": { "base_address": 134217728, "stack_address": 536936448 },
+      "mmio": [{ "address": 1073741824, "size": 1024, "handler": "uart" }]
+    }
+  ],
+  "jobs": [
+    {
+      "name": "fuzz",
+      "steps": [
+        {
+          "name": "fuzz-parser",
+          "call": "icicle-fuzzer",
+          "args": { "project": "testbin", "function": "0x8074e50" },
+          "io": { "output": "crashes" }
+        }
+      ]
+    }
+  ],
+  "labels": { "team": "firmware" }
+}"#;
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_load_json_config() {
+    let yaml = load_config(SAMPLE_YAML.as_bytes()).expect("Failed to parse YAML");
+    let json =
+        load_config_as(SAMPLE_JSON.as_bytes(), ConfigFormat::Json).expect("Failed to parse JSON");
+    assert_eq!(json, yaml);
+
+    // Files are read in the format of their extension
+    let dir = std::env::temp_dir().join(format!("pap-api-test-json-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Could not create temp dir");
+    std::fs::write(dir.join("sample.json"), SAMPLE_JSON).expect("Could not write config");
+    let file = load_config_file(&dir.join("sample.json")).expect("Failed to load JSON file");
+    assert_eq!(file, yaml);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        ConfigFormat::from_path(std::path::Path::new("a.JSON")),
+        ConfigFormat::Json
+    );
+    assert_eq!(
+        ConfigFormat::from_path(std::path::Path::new("a.yml")),
+        ConfigFormat::Yaml
+    );
+    assert_eq!("json".parse::<ConfigFormat>().unwrap(), ConfigFormat::Json);
+}
+
+const SAMPLE_TOML: &str = r#"
+[[projects]]
+name = "testbin"
+binary = "test.bin"
+arch = "thumbv7m-none-eabi"
+loader = { base_address = 0x8000000, stack_address = 0x20010000 }
+mmio = [{ address = 0x40000000, size = 0x400, handler = "uart" }]
+
+[[jobs]]
+name = "fuzz"
+
+[[jobs.steps]]
+name = "fuzz-parser"
+call = "icicle-fuzzer"
+args = { project = "testbin", function = "0x8074e50" }
+io = { output = "crashes" }
+
+[labels]
+team = "firmware"
+"#;
+
+#[test]
+fn test_load_toml_config() {
+    let yaml = load_config(SAMPLE_YAML.as_bytes()).expect("Failed to parse YAML");
+    let toml =
+        load_config_as(SAMPLE_TOML.as_bytes(), ConfigFormat::Toml).expect("Failed to parse TOML");
+    assert_eq!(toml, yaml);
+    assert_eq!(
+        ConfigFormat::from_path(std::path::Path::new("pipeline.toml")),
+        ConfigFormat::Toml
+    );
+
+    // Arguments must be quoted, since they're passed to steps as strings
+    let unquoted = SAMPLE_TOML.replace(r#""0x8074e50""#, "0x8074e50");
+    assert!(load_config_as(unquoted.as_bytes(), ConfigFormat::Toml).is_err());
+}
+
+const SHARED_BINARY_CONFIG: &str = r#"
+projects:
+  - name: first
+    binary: shared.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+  - name: second
+    binary: shared.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs: []
+"#;
+
+fn temp_dir_with_binary(name: &str, data: &[u8]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pap-api-test-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Could not create temp dir");
+    std::fs::write(dir.join("shared.bin"), data).expect("Could not write binary");
+    dir
+}
+
+#[test]
+fn test_context_dedups_shared_binary() {
+    let dir = temp_dir_with_binary("dedup", b"binary");
+    let config = load_config(SHARED_BINARY_CONFIG.as_bytes()).expect("Failed to parse config");
+
+    let context = Context::builder(config, dir.clone())
+        .build()
+        .expect("Failed to build context");
+
+    assert_eq!(context.files().len(), 1);
+    assert_eq!(context.files()["shared.bin"], b"binary");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_context_extra_file() {
+    let dir = temp_dir_with_binary("extra", b"binary");
+    let config = load_config(SHARED_BINARY_CONFIG.as_bytes()).expect("Failed to parse config");
+
+    let context = Context::builder(config, dir.clone())
+        .add_file("seeds/first", b"seed".to_vec())
+        .build()
+        .expect("Failed to build context");
+
+    assert_eq!(context.files().len(), 2);
+    assert_eq!(context.files()["seeds/first"], b"seed");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_context_missing_binary() {
+    let dir = temp_dir_with_binary("missing", b"binary");
+    let config = load_config(
+        r#"
+projects:
+  - name: used
+    binary: shared.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+  - name: unused
+    binary: absent.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs: []
+"#
+        .as_bytes(),
+    )
+    .expect("Failed to parse config");
+
+    let context = Context::builder(config, dir.clone())
+        .build()
+        .expect("Failed to build context");
+
+    assert_eq!(context.files().len(), 1);
+    assert!(!context.files().contains_key("absent.bin"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_api_version() {
+    assert!(check_api_version(API_VERSION).is_ok());
+    assert!(matches!(
+        check_api_version(API_VERSION + 1),
+        Err(PapError::Configuration(_))
+    ));
+}
+
+#[test]
+fn test_parse_hex_key() {
+    assert_eq!(parse_hex_key("00ff2a").unwrap(), vec![0x00, 0xff, 0x2a]);
+    assert_eq!(parse_hex_key("00FF2A").unwrap(), vec![0x00, 0xff, 0x2a]);
+    assert!(parse_hex_key("").unwrap().is_empty());
+    assert!(matches!(
+        parse_hex_key("abc"),
+        Err(PapError::Configuration(_))
+    ));
+    assert!(parse_hex_key("zz").is_err());
+    assert!(parse_hex_key("+1").is_err());
+}
+
+const VARS_CONFIG: &str = r#"
+vars:
+  base: "0x8000"
+  bin: firmware
+projects:
+  - name: ${bin}
+    binary: ${bin}.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: ${bin}
+          function: "${base}"
+          harness: "vm.write_reg('r0', ${base})"
+"#;
+
+#[test]
+fn test_config_vars() {
+    let config = load_config(VARS_CONFIG.as_bytes()).expect("Failed to parse config");
+
+    assert_eq!(config.projects[0].name, "firmware");
+    assert_eq!(config.projects[0].binary, "firmware.bin");
+    let args = &config.jobs[0].steps[0].args;
+    assert_eq!(args["project"], "firmware");
+    assert_eq!(args["function"], "0x8000");
+    assert_eq!(args["harness"], "vm.write_reg('r0', 0x8000)");
+}
+
+#[test]
+fn test_config_undefined_var() {
+    let config = VARS_CONFIG.replace("${base})", "${stack})");
+
+    match load_config(config.as_bytes()) {
+        Err(PapError::Configuration(message)) => assert!(message.contains("stack"), "{}", message),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_config_escaped_var() {
+    let config = VARS_CONFIG.replace(
+        "harness: \"vm.write_reg('r0', ${base})\"",
+        "script: \"print(`r0 = $${base}`)\"",
+    );
+    let config = load_config(config.as_bytes()).expect("Failed to parse config");
+
+    // The rhai template is left for the script, with nothing substituted
+    let args = &config.jobs[0].steps[0].args;
+    assert_eq!(args["script"], "print(`r0 = ${base}`)");
+    assert_eq!(args["function"], "0x8000");
+}
+
+fn temp_dir_with_configs(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pap-api-test-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(dir.join("common")).expect("Could not create temp dir");
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).expect("Could not write config");
+    }
+    dir
+}
+
+#[test]
+fn test_config_include() {
+    let dir = temp_dir_with_configs(
+        "include",
+        &[
+            (
+                "common/base.yaml",
+                r#"
+projects:
+  - name: firmware
+    binary: firmware.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+  - name: bootloader
+    binary: boot.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs:
+  - name: fuzz
+    steps: []
+"#,
+            ),
+            (
+                "main.yaml",
+                r#"
+include:
+  - common/base.yaml
+projects:
+  - name: firmware
+    binary: firmware-v2.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs:
+  - name: triage
+    steps: []
+"#,
+            ),
+        ],
+    );
+
+    let config = load_config_file(&dir.join("main.yaml")).expect("Failed to load config");
+
+    let projects: Vec<_> = config
+        .projects
+        .iter()
+        .map(|p| (p.name.as_str(), p.binary.as_str()))
+        .collect();
+    assert_eq!(
+        projects,
+        [
+            ("firmware", "firmware-v2.bin"),
+            ("bootloader", "common/boot.bin")
+        ]
+    );
+    let jobs: Vec<_> = config.jobs.iter().map(|j| j.name.as_str()).collect();
+    assert_eq!(jobs, ["fuzz", "triage"]);
+    assert!(config.include.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_config_include_without_file() {
+    let config = "include: [common/base.yaml]\njobs: []\n";
+
+    match load_config(config.as_bytes()) {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("include"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_config_include_cycle() {
+    let dir = temp_dir_with_configs(
+        "include-cycle",
+        &[
+            ("a.yaml", "include: [b.yaml]\n"),
+            ("b.yaml", "include: [a.yaml]\n"),
+        ],
+    );
+
+    let result = load_config_file(&dir.join("a.yaml"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    match result {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("include cycle"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+const UNIQUE_NAMES_CONFIG: &str = r#"
+projects:
+  - name: firmware
+    binary: firmware.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+  - name: bootloader
+    binary: boot.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs:
+  - name: fuzz
+    steps:
+      - name: hello
+        call: hello
+        args: {}
+      - name: fuzz
+        call: hello
+        args: {}
+  - name: triage
+    steps:
+      - name: hello
+        call: hello
+        args: {}
+"#;
+
+fn assert_duplicate(config: &str, expected: &str) {
+    match load_config(config.as_bytes()) {
+        Err(PapError::Configuration(message)) => assert!(message.contains(expected), "{}", message),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_config_unique_names() {
+    // Steps in different jobs may share a name
+    let config = load_config(UNIQUE_NAMES_CONFIG.as_bytes()).expect("Failed to parse config");
+    assert!(config.check_unique_names().is_ok());
+}
+
+#[test]
+fn test_config_duplicate_project() {
+    let config = UNIQUE_NAMES_CONFIG.replace("name: bootloader", "name: firmware");
+    assert_duplicate(&config, "duplicate project name: firmware");
+}
+
+#[test]
+fn test_config_duplicate_job() {
+    let config = UNIQUE_NAMES_CONFIG.replace("name: triage", "name: fuzz");
+    assert_duplicate(&config, "duplicate job name: fuzz");
+}
+
+#[test]
+fn test_config_duplicate_step() {
+    let config = UNIQUE_NAMES_CONFIG.replace("      - name: fuzz", "      - name: hello");
+    assert_duplicate(&config, "duplicate step name in job fuzz: hello");
+}
+
+const ENV_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz
+        call: hello
+        args:
+          greeting: "${name}"
+        env:
+          LICENSE: "${PAP_TEST_LICENSE}"
+          ENDPOINT: "https://${PAP_TEST_HOST}/api"
+vars:
+  name: world
+"#;
+
+fn test_env(name: &str) -> Option<String> {
+    match name {
+        "PAP_TEST_LICENSE" => Some("secret-token".to_string()),
+        "PAP_TEST_HOST" => Some("example.com".to_string()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_config_resolve_env() {
+    let mut config = load_config(ENV_CONFIG.as_bytes()).expect("Failed to parse config");
+    // Variables don't apply to env, which is left for the submitter
+    let step = &config.jobs[0].steps[0];
+    assert_eq!(step.env["LICENSE"], "${PAP_TEST_LICENSE}");
+
+    config.resolve_env(test_env).expect("Failed to resolve env");
+
+    let step = &config.jobs[0].steps[0];
+    assert_eq!(step.args["greeting"], "world");
+    assert_eq!(step.env["LICENSE"], "secret-token");
+    assert_eq!(step.env["ENDPOINT"], "https://example.com/api");
+    assert!(!format!("{:?}", step).contains("secret-token"));
+}
+
+#[test]
+fn test_config_missing_env() {
+    let config = ENV_CONFIG.replace("PAP_TEST_HOST", "PAP_TEST_MISSING");
+    let mut config = load_config(config.as_bytes()).expect("Failed to parse config");
+
+    match config.resolve_env(test_env) {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("PAP_TEST_MISSING"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+const IO_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz
+        call: icicle-fuzzer
+        args: {}
+        io:
+          input: seeds
+          output: corpus
+      - name: triage
+        call: triage
+        args: {}
+        io:
+          solutions: step://fuzz/output
+"#;
+
+#[test]
+fn test_config_step_io() {
+    let config = load_config(IO_CONFIG.as_bytes()).expect("Failed to parse config");
+    let steps = &config.jobs[0].steps;
+    assert_eq!(steps[0].io.len(), 2);
+    assert_eq!(steps[0].io["input"], "seeds");
+    assert_eq!(steps[0].io["output"], "corpus");
+    assert_eq!(steps[1].io["solutions"], "step://fuzz/output");
+
+    let yaml = serde_yaml::to_string(&config).expect("Failed to serialize config");
+    let reloaded = load_config(yaml.as_bytes()).expect("Failed to reload config");
+    assert_eq!(reloaded, config);
+
+    // Steps without an io block have no fields set
+    let config = load_config(ENV_CONFIG.as_bytes()).expect("Failed to parse config");
+    assert!(config.jobs[0].steps[0].io.is_empty());
+
+    let schema = schemars::schema_for!(Config);
+    let io = &schema.as_value()["$defs"]["Step"]["properties"]["io"];
+    assert_eq!(io["type"], "object");
+    assert!(io["description"].as_str().unwrap().contains("namespaces"));
+}
+
+#[test]
+fn test_config_builder() {
+    let config = Config::builder()
+        .project(
+            Project::builder("testbin", "test.bin", "thumbv7m-none-eabi")
+                .loader(0x800_0000, 0x2001_0000)
+                .mmio(0x4000_0000, 0x400, "uart"),
+        )
+        .job(
+            Job::builder("fuzz").step(
+                Step::builder("fuzz-parser", "icicle-fuzzer")
+                    .arg("project", "testbin")
+                    .arg("function", "0x8074e50")
+                    .io("output", "crashes"),
+            ),
+        )
+        .label("team", "firmware")
+        .build()
+        .expect("Failed to build config");
+    assert_eq!(config, load_config(SAMPLE_YAML.as_bytes()).unwrap());
+
+    // Settings the sample leaves out
+    let config = Config::builder()
+        .var("function", "0x8074e50")
+        .job(
+            Job::builder("fuzz").timeout_secs(600).step(
+                Step::builder("triage", "triage")
+                    .arg("function", "${function}")
+                    .cleanup_on_failure(true),
+            ),
+        )
+        .build()
+        .expect("Failed to build config");
+    assert_eq!(config.jobs[0].timeout_secs, Some(600));
+    assert_eq!(config.jobs[0].steps[0].args["function"], "0x8074e50");
+    assert!(config.jobs[0].steps[0].cleanup_on_failure);
+
+    // Built configs are checked like loaded ones
+    let result = Config::builder()
+        .job(Job::builder("fuzz"))
+        .job(Job::builder("fuzz"))
+        .build();
+    match result {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("duplicate job name"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_server_addr() {
+    let addr: ServerAddr = "127.0.0.1:9090".parse().unwrap();
+    assert_eq!(addr, ServerAddr::Tcp("127.0.0.1:9090".to_string()));
+    assert_eq!(addr.to_string(), "127.0.0.1:9090");
+
+    let addr: ServerAddr = "unix:/run/pap.sock".parse().unwrap();
+    assert_eq!(addr, ServerAddr::Unix("/run/pap.sock".into()));
+    assert_eq!(addr.to_string(), "unix:/run/pap.sock");
+
+    assert!(matches!(
+        "unix:".parse::<ServerAddr>(),
+        Err(PapError::Configuration(_))
+    ));
+}
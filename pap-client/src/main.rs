@@ -1,23 +1,43 @@
 use colored::*;
 use std::env;
-use std::io::{stdout, Write};
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::io::{stdin, stdout, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
-use pap_api::{load_config, Context};
-use pap_api::{ExecutionStatus, PapApiClient};
+use pap_api::{load_config_as, load_config_file_as, Config, ConfigFormat, Context};
+use pap_api::{
+    ExecutionStatus, OutputKind, PapApiClient, PapError, PipelineStatus, ServerAddr, StepOutput,
+};
 use tarpc::{client, context, tokio_serde::formats::Json};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 
+#[cfg(test)]
+mod test;
+
+/// How often `pipeline wait` checks whether the pipeline has finished
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Host address for PapApi server (default: 127.0.0.1:9090)
+    /// Host address for PapApi server (default: 127.0.0.1:9090), or
+    /// `unix:<path>` for a unix socket.
     /// Can also be set using PAP_HOST environment variable
     #[arg(short = 'H', long)]
     host: Option<String>,
 
+    /// Fail instead of warning when the server speaks a different API version
+    #[arg(long)]
+    strict: bool,
+
+    /// Never color output. Also set by a non-empty NO_COLOR environment
+    /// variable, and implied when stdout isn't a terminal.
+    #[arg(long)]
+    no_color: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -34,6 +54,11 @@ enum Commands {
         #[command(subcommand)]
         command: JobCommands,
     },
+    /// Step management commands
+    Step {
+        #[command(subcommand)]
+        command: StepCommands,
+    },
     /// Log access commands
     Log {
         #[command(subcommand)]
@@ -44,14 +69,47 @@ enum Commands {
         #[command(subcommand)]
         command: ObjectCommands,
     },
+    /// List the step executors available on the server
+    Executors,
+    /// Check that the server is up and its database is reachable
+    Health,
+    /// Follow pipeline lifecycle events, printing each as a line of JSON
+    Events,
 }
 
 #[derive(Subcommand)]
 enum PipelineCommands {
     /// Submit a new pipeline
     Submit {
-        /// Path to the pipeline configuration file
+        /// Path to the pipeline configuration file, or `-` to read it from stdin
         config: PathBuf,
+        /// Directory that project binaries are relative to. Defaults to the
+        /// config file's directory, or the working directory for stdin.
+        #[arg(long)]
+        base_dir: Option<PathBuf>,
+        /// Language the config is written in, `yaml`, `json`, or `toml`. Defaults to
+        /// the config file's extension, or YAML for stdin.
+        #[arg(long)]
+        format: Option<ConfigFormat>,
+        /// Only validate and set up each step, skipping the actual work
+        #[arg(long)]
+        dry_run: bool,
+        /// Most bytes of objects the pipeline may store, overriding the config
+        #[arg(long)]
+        quota: Option<u64>,
+        /// Most seconds the pipeline may run for, overriding the config
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+    /// Submit a copy of an existing pipeline
+    Resubmit {
+        /// Pipeline ID
+        id: u32,
+    },
+    /// Run a failed or cancelled pipeline again from its first unfinished step
+    Resume {
+        /// Pipeline ID
+        id: u32,
     },
     /// Get pipeline information
     Get {
@@ -59,11 +117,25 @@ enum PipelineCommands {
         id: u32,
     },
     /// List all pipelines
-    List,
+    List {
+        /// Only list pipelines with this label, given as `key=value`
+        #[arg(short, long)]
+        label: Option<String>,
+        /// Show at most this many pipelines
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Skip this many of the most recent pipelines
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
     /// Cancel a pipeline
     Cancel {
         /// Pipeline ID
-        id: u32,
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        id: Option<u32>,
+        /// Cancel every pending or running pipeline instead
+        #[arg(long)]
+        all: bool,
     },
     /// Delete a pipeline
     Delete {
@@ -75,6 +147,37 @@ enum PipelineCommands {
         /// Pipeline ID
         id: u32,
     },
+    /// Show a one-line summary of each step in a pipeline
+    Summary {
+        /// Pipeline ID
+        id: u32,
+    },
+    /// Wait for a pipeline to finish
+    Wait {
+        /// Pipeline ID
+        id: u32,
+    },
+    /// Show every status change of a pipeline and its jobs and steps
+    History {
+        /// Pipeline ID
+        id: u32,
+    },
+    /// Print the log of every step in a pipeline, or of one step by name
+    Logs {
+        /// Pipeline ID
+        id: u32,
+        /// Only print the log of the step with this name
+        #[arg(long)]
+        step: Option<String>,
+        /// Job the step belongs to, if several jobs have a step of that name
+        #[arg(long, requires = "step")]
+        job: Option<String>,
+    },
+    /// Print the config a pipeline ran with as YAML
+    Export {
+        /// Pipeline ID
+        id: u32,
+    },
 }
 
 #[derive(Subcommand)]
@@ -85,7 +188,11 @@ enum JobCommands {
         id: u32,
     },
     /// List all jobs
-    List,
+    List {
+        /// Only list the jobs of this pipeline
+        #[arg(short, long)]
+        pipeline: Option<u32>,
+    },
     /// Cancel a job
     Cancel {
         /// Job ID
@@ -93,6 +200,15 @@ enum JobCommands {
     },
 }
 
+#[derive(Subcommand)]
+enum StepCommands {
+    /// Cancel a step, letting the rest of its job run
+    Cancel {
+        /// Step ID
+        id: u32,
+    },
+}
+
 #[derive(Subcommand)]
 enum LogCommands {
     /// Get log output for a step
@@ -110,6 +226,9 @@ enum ObjectCommands {
         namespace: String,
         /// Object key
         key: String,
+        /// Read the key as hex bytes, e.g. `000000000000002a` for a corpus entry
+        #[arg(long)]
+        key_hex: bool,
     },
     /// Put an object
     Put {
@@ -117,54 +236,215 @@ enum ObjectCommands {
         namespace: String,
         /// Object key
         key: String,
+        /// Read the key as hex bytes
+        #[arg(long)]
+        key_hex: bool,
         /// Path to file containing object data
         #[arg(short, long)]
         file: PathBuf,
     },
+    /// Check whether an object exists, exiting with 1 if it doesn't
+    Exists {
+        /// Object namespace
+        namespace: String,
+        /// Object key
+        key: String,
+        /// Read the key as hex bytes
+        #[arg(long)]
+        key_hex: bool,
+    },
+    /// List the namespaces holding objects
+    Namespaces,
 }
 
 async fn handle_pipeline_command(
     command: PipelineCommands,
     client: &PapApiClient,
+    color: bool,
 ) -> anyhow::Result<()> {
     match command {
-        PipelineCommands::Submit { config } => {
-            let base_path = config
-                .parent()
-                .ok_or_else(|| anyhow::anyhow!("Config file must have a parent directory"))?
-                .to_path_buf();
-
-            let config_file = File::open(&config).await?;
-            let config = load_config(config_file.into_std().await)?;
+        PipelineCommands::Submit {
+            config,
+            base_dir,
+            format,
+            dry_run,
+            quota,
+            timeout,
+        } => {
+            let (mut config, base_path) = read_config(&config, base_dir, format, stdin())?;
+            config.resolve_env(|name| env::var(name).ok())?;
+            if quota.is_some() {
+                config.object_quota = quota;
+            }
+            if timeout.is_some() {
+                config.timeout_secs = timeout;
+            }
             let context = Context::build_with_config(config, base_path)?;
-            let id = client
-                .submit_pipeline(context::current(), context)
-                .await??;
-            println!("Submitted pipeline with ID: {}", id);
+            let result = if dry_run {
+                client
+                    .dry_run_pipeline(context::current(), context)
+                    .await??
+            } else {
+                client
+                    .submit_pipeline(context::current(), context)
+                    .await??
+            };
+            println!("Submitted pipeline with ID: {}", result.id);
+            for warning in result.warnings {
+                eprintln!("{} {}", "Warning:".yellow(), warning);
+            }
+        }
+        PipelineCommands::Resubmit { id } => {
+            let new_id = client.resubmit_pipeline(context::current(), id).await??;
+            println!("Resubmitted pipeline {} with ID: {}", id, new_id);
+        }
+        PipelineCommands::Resume { id } => {
+            client.resume_pipeline(context::current(), id).await??;
+            println!("Resumed pipeline {}", id);
         }
         PipelineCommands::Get { id } => {
-            let info = client.get_pipeline(context::current(), id).await?;
+            let info = client.get_pipeline(context::current(), id).await??;
             println!("{:#?}", info);
+            check_outcome(id, &info.status)?;
         }
-        PipelineCommands::List => {
-            let pipelines = client.get_pipelines(context::current()).await?;
-            println!("Pipelines: {:?}", pipelines);
+        PipelineCommands::List {
+            label,
+            limit,
+            offset,
+        } => {
+            let pipelines = match label {
+                Some(label) => {
+                    let (key, value) = label
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("Label must be given as key=value"))?;
+                    client
+                        .get_pipelines_by_label(
+                            context::current(),
+                            key.to_string(),
+                            value.to_string(),
+                        )
+                        .await??
+                }
+                None => client.get_pipelines(context::current()).await??,
+            };
+            let ids = pipelines
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+            let rows: Vec<_> = client
+                .get_pipeline_statuses(context::current(), ids)
+                .await??
+                .iter()
+                .map(pipeline_row)
+                .collect();
+            print!(
+                "{}",
+                format_table(&["ID", "NAME", "STATUS", "CREATED"], &rows)
+            );
         }
-        PipelineCommands::Cancel { id } => {
+        PipelineCommands::Cancel { id: Some(id), .. } => {
             client.cancel_pipeline(context::current(), id).await??;
             println!("Cancelled pipeline {}", id);
         }
+        PipelineCommands::Cancel { id: None, .. } => {
+            let count = client.cancel_all_pipelines(context::current()).await??;
+            println!("Cancelled {} pipelines", count);
+        }
         PipelineCommands::Delete { id } => {
             client.delete_pipeline(context::current(), id).await??;
             println!("Deleted pipeline {}", id);
         }
         PipelineCommands::Status { id } => {
-            print_status(client, id).await?;
+            print_status(client, id, color).await?;
+        }
+        PipelineCommands::Summary { id } => {
+            print_summary(client, id).await?;
+        }
+        PipelineCommands::Wait { id } => {
+            let status = loop {
+                let pipeline = client.get_pipeline(context::current(), id).await??;
+                if pipeline.status.is_finished() {
+                    break pipeline.status;
+                }
+                tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+            };
+            println!("Pipeline {} {}", id, status_text(&status, color));
+            check_outcome(id, &status)?;
+        }
+        PipelineCommands::History { id } => {
+            let rows: Vec<_> = client
+                .get_pipeline_history(context::current(), id)
+                .await??
+                .into_iter()
+                .map(|change| {
+                    vec![
+                        change.timestamp,
+                        format!("{} {}", change.entity_type, change.entity_id),
+                        format!("{:?}", change.old_status),
+                        format!("{:?}", change.new_status),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&["TIME", "ENTITY", "FROM", "TO"], &rows));
+        }
+        PipelineCommands::Logs {
+            id,
+            step: Some(step),
+            job,
+        } => {
+            let step_id = client
+                .find_step(context::current(), id, job, step)
+                .await??;
+            let log = client.get_step_log(context::current(), step_id).await??;
+            stdout().write_all(&log)?;
+        }
+        PipelineCommands::Logs { id, step: None, .. } => {
+            let mut out = stdout();
+            for (step_id, log) in client.get_pipeline_logs(context::current(), id).await?? {
+                writeln!(out, "==> Step {} <==", step_id)?;
+                out.write_all(&log)?;
+                if !log.ends_with(b"\n") {
+                    writeln!(out)?;
+                }
+            }
+        }
+        PipelineCommands::Export { id } => {
+            print!("{}", client.export_config(context::current(), id).await??);
         }
     }
     Ok(())
 }
 
+/// Reads a pipeline config from `path`, or from `stdin` if it is `-`, along
+/// with the directory its project binaries are relative to. `base_dir`
+/// overrides that directory.
+fn read_config(
+    path: &Path,
+    base_dir: Option<PathBuf>,
+    format: Option<ConfigFormat>,
+    stdin: impl Read,
+) -> anyhow::Result<(Config, PathBuf)> {
+    if path == Path::new("-") {
+        let config = load_config_as(stdin, format.unwrap_or_default())?;
+        // Includes are relative to the file that names them, which stdin has none of
+        if !config.include.is_empty() {
+            anyhow::bail!("Configs read from stdin can't include other configs");
+        }
+        return Ok((config, base_dir.unwrap_or_else(|| PathBuf::from("."))));
+    }
+
+    let base_path = match base_dir {
+        Some(base_dir) => base_dir,
+        None => path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Config file must have a parent directory"))?
+            .to_path_buf(),
+    };
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+    Ok((load_config_file_as(path, format)?, base_path))
+}
+
 async fn handle_job_command(command: JobCommands, client: &PapApiClient) -> anyhow::Result<()> {
     match command {
         JobCommands::Get { id } => {
@@ -177,9 +457,25 @@ async fn handle_job_command(command: JobCommands, client: &PapApiClient) -> anyh
                 println!("  - {} ({}): {:?}", step.id, step.config.name, step.status);
             }
         }
-        JobCommands::List => {
-            let jobs = client.get_jobs(context::current()).await?;
-            println!("Jobs: {:?}", jobs);
+        JobCommands::List { pipeline } => {
+            let ids = match pipeline {
+                Some(pipeline) => {
+                    client
+                        .get_pipeline_jobs(context::current(), pipeline)
+                        .await??
+                }
+                None => client.get_jobs(context::current()).await??,
+            };
+            let mut rows = Vec::new();
+            for id in ids {
+                let job = client.get_job(context::current(), id).await??;
+                rows.push(vec![
+                    job.id.to_string(),
+                    job.config.name,
+                    job.status.to_string(),
+                ]);
+            }
+            print!("{}", format_table(&["ID", "NAME", "STATUS"], &rows));
         }
         JobCommands::Cancel { id } => {
             client.cancel_job(context::current(), id).await??;
@@ -189,6 +485,16 @@ async fn handle_job_command(command: JobCommands, client: &PapApiClient) -> anyh
     Ok(())
 }
 
+async fn handle_step_command(command: StepCommands, client: &PapApiClient) -> anyhow::Result<()> {
+    match command {
+        StepCommands::Cancel { id } => {
+            client.cancel_step(context::current(), id).await??;
+            println!("Cancelled step {}", id);
+        }
+    }
+    Ok(())
+}
+
 async fn handle_log_command(command: LogCommands, client: &PapApiClient) -> anyhow::Result<()> {
     match command {
         LogCommands::Get { id } => {
@@ -204,43 +510,214 @@ async fn handle_object_command(
     client: &PapApiClient,
 ) -> anyhow::Result<()> {
     match command {
-        ObjectCommands::Get { namespace, key } => {
+        ObjectCommands::Get {
+            namespace,
+            key,
+            key_hex,
+        } => {
+            let key = object_key(key, key_hex)?;
             let data = client
-                .get_object(context::current(), namespace, key.into_bytes())
+                .get_object(context::current(), namespace, key)
                 .await??;
             std::io::stdout().write_all(&data)?;
         }
         ObjectCommands::Put {
             namespace,
             key,
+            key_hex,
             file,
         } => {
+            let key = object_key(key, key_hex)?;
             let mut file = File::open(file).await?;
             let mut data = Vec::new();
             file.read_to_end(&mut data).await?;
             client
-                .put_object(context::current(), namespace, key.into_bytes(), data)
+                .put_object(context::current(), namespace, key, data)
                 .await??;
             println!("Object stored successfully");
         }
+        ObjectCommands::Exists {
+            namespace,
+            key,
+            key_hex,
+        } => {
+            let key = object_key(key, key_hex)?;
+            if !client
+                .object_exists(context::current(), namespace.clone(), key)
+                .await??
+            {
+                return Err(ObjectMissing { namespace }.into());
+            }
+            println!("Object exists");
+        }
+        ObjectCommands::Namespaces => {
+            let namespaces = client.list_namespaces(context::current()).await??;
+            for namespace in namespaces {
+                println!("{}", namespace);
+            }
+        }
     }
     Ok(())
 }
 
-async fn print_status(client: &PapApiClient, pipeline_id: u32) -> anyhow::Result<()> {
+/// Converts an object key argument to the key's bytes, decoding it as hex if
+/// `hex` is set
+fn object_key(key: String, hex: bool) -> anyhow::Result<Vec<u8>> {
+    if !hex {
+        return Ok(key.into_bytes());
+    }
+    Ok(pap_api::parse_hex_key(&key)?)
+}
+
+async fn follow_events(client: &PapApiClient) -> anyhow::Result<()> {
+    let mut after = None;
+    loop {
+        for record in client.events(context::current(), after).await?? {
+            after = Some(record.id);
+            println!("{}", serde_json::to_string(&record)?);
+        }
+    }
+}
+
+async fn print_executors(client: &PapApiClient) -> anyhow::Result<()> {
+    let executors = client.list_executors(context::current()).await?;
+
+    for executor in executors {
+        println!("{}", executor.name.bold());
+        for arg in executor.args {
+            let mut details = arg.arg_type.to_string().to_lowercase();
+            if arg.required {
+                details.push_str(", required");
+            }
+            if let Some(default) = arg.default {
+                details.push_str(&format!(", default {}", default));
+            }
+            println!("  {} ({}): {}", arg.name, details, arg.description);
+        }
+        if !executor.io.is_empty() {
+            println!("  io: {}", executor.io.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+async fn print_health(client: &PapApiClient) -> anyhow::Result<()> {
+    let health = client.health(context::current()).await??;
+
+    println!("version: {}", health.version);
+    let database = if health.db_ok { "ok" } else { "unreachable" };
+    println!("database: {}", database);
+    println!("active pipelines: {}", health.active_pipelines);
+
+    if !health.db_ok {
+        anyhow::bail!("server cannot reach its database");
+    }
+    Ok(())
+}
+
+/// A `pipeline list` row. Pipelines have no name of their own, so they are
+/// named after their jobs.
+fn pipeline_row(pipeline: &PipelineStatus) -> Vec<String> {
+    let jobs: Vec<_> = pipeline
+        .config
+        .jobs
+        .iter()
+        .map(|job| job.name.as_str())
+        .collect();
+    vec![
+        pipeline.id.to_string(),
+        jobs.join(","),
+        pipeline.status.to_string(),
+        pipeline
+            .created_at
+            .clone()
+            .unwrap_or_else(|| "-".to_string()),
+    ]
+}
+
+/// Lays out rows under a header, padding each column to its widest cell
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    let header = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&header).chain(rows) {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Whether output should be colored, following https://no-color.org
+fn color_enabled(no_color: bool, no_color_env: Option<OsString>, is_terminal: bool) -> bool {
+    !no_color && no_color_env.is_none_or(|value| value.is_empty()) && is_terminal
+}
+
+/// A status, colored by how it turned out if `color` is set
+fn status_text(status: &ExecutionStatus, color: bool) -> ColoredString {
+    let text = status.to_string();
+    if !color {
+        return text.normal();
+    }
+    text.color(match status {
+        ExecutionStatus::Completed => "green",
+        ExecutionStatus::Failed => "red",
+        ExecutionStatus::Cancelled => "yellow",
+        _ => "blue",
+    })
+}
+
+/// Renders a step's output for reading: JSON pretty-printed, and binary as a
+/// hex dump of 16 bytes per line
+fn render_output(output: &StepOutput) -> String {
+    match output.kind {
+        OutputKind::Text => String::from_utf8_lossy(&output.data).into_owned(),
+        OutputKind::Json => serde_json::from_slice::<serde_json::Value>(&output.data)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .unwrap_or_else(|_| String::from_utf8_lossy(&output.data).into_owned()),
+        OutputKind::Binary => output
+            .data
+            .chunks(16)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let bytes: Vec<_> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("{:08x}  {}\n", i * 16, bytes.join(" "))
+            })
+            .collect(),
+    }
+}
+
+/// Describes how far along a pipeline is, such as `3/7 steps (43%)`
+fn progress_text(pipeline: &PipelineStatus) -> String {
+    format!(
+        "{}/{} steps ({:.0}%)",
+        pipeline.completed_steps,
+        pipeline.total_steps,
+        pipeline.progress * 100.0
+    )
+}
+
+async fn print_status(client: &PapApiClient, pipeline_id: u32, color: bool) -> anyhow::Result<()> {
     let pipeline = client
         .get_pipeline(context::current(), pipeline_id)
         .await??;
 
     println!(
-        "\nPipeline {} ({})",
+        "\nPipeline {} ({}) {}",
         pipeline_id,
-        pipeline.status.to_string().color(match pipeline.status {
-            ExecutionStatus::Completed => "green",
-            ExecutionStatus::Failed => "red",
-            ExecutionStatus::Cancelled => "yellow",
-            _ => "blue",
-        })
+        status_text(&pipeline.status, color),
+        progress_text(&pipeline)
     );
 
     for job_id in pipeline.jobs {
@@ -249,12 +726,7 @@ async fn print_status(client: &PapApiClient, pipeline_id: u32) -> anyhow::Result
             "\n  Job {} - {} ({})",
             job_id,
             job.config.name,
-            job.status.to_string().color(match job.status {
-                ExecutionStatus::Completed => "green",
-                ExecutionStatus::Failed => "red",
-                ExecutionStatus::Cancelled => "yellow",
-                _ => "blue",
-            })
+            status_text(&job.status, color)
         );
 
         for step in job.steps {
@@ -262,19 +734,14 @@ async fn print_status(client: &PapApiClient, pipeline_id: u32) -> anyhow::Result
                 "\n    Step {} - {} ({})",
                 step.id,
                 step.config.name,
-                step.status.to_string().color(match step.status {
-                    ExecutionStatus::Completed => "green",
-                    ExecutionStatus::Failed => "red",
-                    ExecutionStatus::Cancelled => "yellow",
-                    _ => "blue",
-                })
+                status_text(&step.status, color)
             );
 
             // If there's log output, display it indented
-            if let Ok(Ok(log)) = client.get_step_log(context::current(), step.id).await {
-                if !log.is_empty() {
+            if let Some(output) = step.output {
+                if !output.data.is_empty() {
                     println!("\n      Log output:");
-                    for line in String::from_utf8_lossy(&log).lines() {
+                    for line in render_output(&output).lines() {
                         println!("        {}", line);
                     }
                 }
@@ -286,31 +753,159 @@ async fn print_status(client: &PapApiClient, pipeline_id: u32) -> anyhow::Result
     if let Some(error) = pipeline.error {
         println!("\n  {}", "Pipeline Error:".red());
         println!("    {}", error);
+        if let (Some(job_id), Some(step_id)) = (pipeline.failed_job, pipeline.failed_step) {
+            println!("    Failed at job {}, step {}", job_id, step_id);
+        }
     }
 
     stdout().flush()?;
-    Ok(())
+    check_outcome(pipeline_id, &pipeline.status)
+}
+
+async fn print_summary(client: &PapApiClient, pipeline_id: u32) -> anyhow::Result<()> {
+    let pipeline = client
+        .get_pipeline(context::current(), pipeline_id)
+        .await??;
+
+    println!(
+        "Pipeline {} ({}) {}",
+        pipeline_id,
+        pipeline.status,
+        progress_text(&pipeline)
+    );
+
+    for job_id in pipeline.jobs {
+        let job = client.get_job(context::current(), job_id).await??;
+        println!("  Job {} - {} ({})", job_id, job.config.name, job.status);
+
+        for step in job.steps {
+            let duration = match step.duration {
+                Some(duration) => format!("{:.2?}", duration),
+                None => "-".to_string(),
+            };
+            println!(
+                "    Step {} - {} ({}) {}",
+                step.id, step.config.name, step.status, duration
+            );
+        }
+    }
+
+    check_outcome(pipeline_id, &pipeline.status)
+}
+
+/// A pipeline that finished without completing, returned by the commands that
+/// report a pipeline's status so that the CLI exits non-zero
+#[derive(Debug, thiserror::Error)]
+#[error("Pipeline {id} did not complete: {status}")]
+struct PipelineOutcome {
+    id: u32,
+    status: ExecutionStatus,
+}
+
+/// An object that `object exists` didn't find, so that the CLI exits non-zero
+#[derive(Debug, thiserror::Error)]
+#[error("Object does not exist in namespace {namespace}")]
+struct ObjectMissing {
+    namespace: String,
+}
+
+/// Fails with a [`PipelineOutcome`] if the pipeline failed or was cancelled
+fn check_outcome(id: u32, status: &ExecutionStatus) -> anyhow::Result<()> {
+    match status {
+        ExecutionStatus::Failed | ExecutionStatus::Cancelled => Err(PipelineOutcome {
+            id,
+            status: status.clone(),
+        }
+        .into()),
+        _ => Ok(()),
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let host = cli
+    let color = color_enabled(
+        cli.no_color,
+        env::var_os("NO_COLOR"),
+        stdout().is_terminal(),
+    );
+    colored::control::set_override(color);
+
+    let host: ServerAddr = cli
         .host
         .or_else(|| env::var("PAP_HOST").ok())
-        .unwrap_or_else(|| "127.0.0.1:9090".to_string());
+        .unwrap_or_else(|| "127.0.0.1:9090".to_string())
+        .parse()?;
+
+    let client = match host {
+        ServerAddr::Tcp(addr) => {
+            let transport = tarpc::serde_transport::tcp::connect(addr, Json::default).await?;
+            PapApiClient::new(client::Config::default(), transport).spawn()
+        }
+        ServerAddr::Unix(path) => {
+            let transport = tarpc::serde_transport::unix::connect(path, Json::default).await?;
+            PapApiClient::new(client::Config::default(), transport).spawn()
+        }
+    };
 
-    let transport = tarpc::serde_transport::tcp::connect(host, Json::default).await?;
+    if let Err(e) = check_api_version(&client, cli.strict).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(exit_code(&e));
+    }
 
-    let client = PapApiClient::new(client::Config::default(), transport).spawn();
+    let result = match cli.command {
+        Commands::Pipeline { command } => handle_pipeline_command(command, &client, color).await,
+        Commands::Job { command } => handle_job_command(command, &client).await,
+        Commands::Step { command } => handle_step_command(command, &client).await,
+        Commands::Log { command } => handle_log_command(command, &client).await,
+        Commands::Object { command } => handle_object_command(command, &client).await,
+        Commands::Executors => print_executors(&client).await,
+        Commands::Health => print_health(&client).await,
+        Commands::Events => follow_events(&client).await,
+    };
 
-    match cli.command {
-        Commands::Pipeline { command } => handle_pipeline_command(command, &client).await?,
-        Commands::Job { command } => handle_job_command(command, &client).await?,
-        Commands::Log { command } => handle_log_command(command, &client).await?,
-        Commands::Object { command } => handle_object_command(command, &client).await?,
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(exit_code(&e));
     }
 
     Ok(())
 }
+
+/// Warns, or fails when `strict`, if the server speaks a different API version
+async fn check_api_version(client: &PapApiClient, strict: bool) -> anyhow::Result<()> {
+    let result = match client.api_version(context::current()).await {
+        Ok(Ok(version)) => pap_api::check_api_version(version).map_err(Into::into),
+        Ok(Err(e)) => Err(e.into()),
+        // Servers predating the version check can't answer at all
+        Err(e) => Err(anyhow::anyhow!("could not get server API version: {}", e)),
+    };
+
+    match result {
+        Err(e) if !strict => {
+            eprintln!("{} {}", "Warning:".yellow(), e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Exit code for a failed command, so scripts can tell failed and cancelled
+/// pipelines, timeouts, and cancellations apart from other errors
+fn exit_code(error: &anyhow::Error) -> i32 {
+    if let Some(outcome) = error.downcast_ref::<PipelineOutcome>() {
+        return match outcome.status {
+            ExecutionStatus::Cancelled => 2,
+            _ => 1,
+        };
+    }
+    if error.is::<ObjectMissing>() {
+        return 1;
+    }
+    match error.downcast_ref::<PapError>() {
+        Some(PapError::Timeout(_)) => 3,
+        Some(PapError::Cancelled(_)) => 4,
+        _ => 1,
+    }
+}
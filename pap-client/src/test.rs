@@ -0,0 +1,210 @@
+use crate::*;
+use pap_api::load_config;
+
+#[test]
+fn test_color_enabled() {
+    assert!(color_enabled(false, None, true));
+    assert!(!color_enabled(true, None, true));
+    assert!(!color_enabled(false, None, false));
+    assert!(!color_enabled(false, Some("1".into()), true));
+    // An empty NO_COLOR doesn't count as set
+    assert!(color_enabled(false, Some("".into()), true));
+}
+
+#[test]
+fn test_no_color_plain_output() {
+    let color = color_enabled(false, Some("1".into()), true);
+
+    let text = status_text(&ExecutionStatus::Failed, color).to_string();
+    assert!(!text.contains('\x1b'), "{:?}", text);
+    assert_eq!(text, "Failed");
+}
+
+#[test]
+fn test_pipeline_table() {
+    let config = load_config(
+        r#"
+projects: []
+jobs:
+  - name: fuzz
+    steps: []
+  - name: triage
+    steps: []
+"#
+        .as_bytes(),
+    )
+    .unwrap();
+    let pipeline = |id, status| PipelineStatus {
+        id,
+        config: config.clone(),
+        status,
+        jobs: vec![],
+        error: None,
+        failed_job: None,
+        failed_step: None,
+        created_at: Some("2024-01-01 00:00:00".to_string()),
+        dry_run: false,
+        completed_steps: 0,
+        total_steps: 0,
+        progress: 0.0,
+    };
+
+    let rows = vec![
+        pipeline_row(&pipeline(7, ExecutionStatus::Completed)),
+        pipeline_row(&pipeline(12, ExecutionStatus::Failed)),
+    ];
+    let table = format_table(&["ID", "NAME", "STATUS", "CREATED"], &rows);
+    let lines: Vec<_> = table.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "ID  NAME         STATUS     CREATED");
+    assert_eq!(lines[1], "7   fuzz,triage  Completed  2024-01-01 00:00:00");
+    assert_eq!(lines[2], "12  fuzz,triage  Failed     2024-01-01 00:00:00");
+}
+
+#[test]
+fn test_progress_text() {
+    let pipeline = PipelineStatus {
+        id: 1,
+        config: load_config("projects: []\njobs: []\n".as_bytes()).unwrap(),
+        status: ExecutionStatus::Running,
+        jobs: vec![],
+        error: None,
+        failed_job: None,
+        failed_step: None,
+        created_at: None,
+        dry_run: false,
+        completed_steps: 3,
+        total_steps: 7,
+        progress: 3.0 / 7.0,
+    };
+    assert_eq!(progress_text(&pipeline), "3/7 steps (43%)");
+}
+
+#[test]
+fn test_object_key_hex() {
+    // Corpus entries are keyed by their big-endian ID
+    let key = 42usize.to_be_bytes().to_vec();
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    assert_eq!(object_key(hex.clone(), true).unwrap(), key);
+    assert_eq!(object_key(hex.to_uppercase(), true).unwrap(), key);
+
+    assert_eq!(object_key("ab".into(), false).unwrap(), b"ab");
+    assert!(object_key("abc".into(), true).is_err());
+    assert!(object_key("zz".into(), true).is_err());
+    assert!(object_key("+1".into(), true).is_err());
+}
+
+#[test]
+fn test_read_config_stdin() {
+    let dir = env::temp_dir().join(format!("pap-client-test-stdin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("fw.bin"), b"firmware").unwrap();
+
+    let yaml = r#"
+projects:
+  - name: fw
+    binary: fw.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs: []
+"#;
+    let (config, base_path) =
+        read_config(Path::new("-"), Some(dir.clone()), None, yaml.as_bytes()).unwrap();
+    assert_eq!(base_path, dir);
+    assert_eq!(config.projects[0].name, "fw");
+
+    // Binaries are read relative to the base dir
+    let context = Context::build_with_config(config, base_path).unwrap();
+    assert_eq!(context.files().get("fw.bin").unwrap(), b"firmware");
+
+    let (_, base_path) = read_config(Path::new("-"), None, None, yaml.as_bytes()).unwrap();
+    assert_eq!(base_path, PathBuf::from("."));
+
+    let json = r#"{"projects": [], "jobs": [{"name": "scan", "steps": []}]}"#;
+    let (config, _) = read_config(
+        Path::new("-"),
+        None,
+        Some(ConfigFormat::Json),
+        json.as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(config.jobs[0].name, "scan");
+
+    let include = "include: [other.yaml]\njobs: []\n";
+    assert!(read_config(Path::new("-"), Some(dir.clone()), None, include.as_bytes()).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pipeline_outcome_exit_code() {
+    let code = |status| match check_outcome(7, &status) {
+        Ok(()) => 0,
+        Err(e) => exit_code(&e),
+    };
+    assert_eq!(code(ExecutionStatus::Completed), 0);
+    assert_eq!(code(ExecutionStatus::Failed), 1);
+    assert_eq!(code(ExecutionStatus::Cancelled), 2);
+    // Still running, so nothing to report yet
+    assert_eq!(code(ExecutionStatus::Running), 0);
+
+    let err = check_outcome(7, &ExecutionStatus::Failed).unwrap_err();
+    assert_eq!(err.to_string(), "Pipeline 7 did not complete: Failed");
+    // Errors from the server keep their own codes
+    assert_eq!(exit_code(&PapError::Timeout("slow".into()).into()), 3);
+}
+
+#[test]
+fn test_object_missing_exit_code() {
+    let err = anyhow::Error::from(ObjectMissing {
+        namespace: "crashes".to_string(),
+    });
+    assert_eq!(
+        err.to_string(),
+        "Object does not exist in namespace crashes"
+    );
+    assert_eq!(exit_code(&err), 1);
+}
+
+#[test]
+fn test_render_output() {
+    let output = |kind, data: &[u8]| StepOutput {
+        kind,
+        data: data.to_vec(),
+    };
+    assert_eq!(
+        render_output(&output(OutputKind::Text, b"line one\nline two\n")),
+        "line one\nline two\n"
+    );
+    assert_eq!(
+        render_output(&output(OutputKind::Json, br#"{"found":2}"#)),
+        "{\n  \"found\": 2\n}"
+    );
+    // Output that isn't valid JSON is shown as is
+    assert_eq!(render_output(&output(OutputKind::Json, b"{oops")), "{oops");
+
+    let data: Vec<u8> = (0..20).collect();
+    assert_eq!(
+        render_output(&output(OutputKind::Binary, &data)),
+        "00000000  00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n\
+         00000010  10 11 12 13\n"
+    );
+}
+
+#[test]
+fn test_cancel_args() {
+    let cancel = |args: &[&str]| -> Result<_, clap::Error> {
+        let cli = Cli::try_parse_from([&["pap-client", "pipeline", "cancel"], args].concat())?;
+        match cli.command {
+            Commands::Pipeline {
+                command: PipelineCommands::Cancel { id, all },
+            } => Ok((id, all)),
+            _ => unreachable!(),
+        }
+    };
+    assert_eq!(cancel(&["7"]).unwrap(), (Some(7), false));
+    assert_eq!(cancel(&["--all"]).unwrap(), (None, true));
+    // Exactly one of them is needed
+    assert!(cancel(&[]).is_err());
+    assert!(cancel(&["7", "--all"]).is_err());
+}
@@ -1,97 +1,87 @@
-use std::fs::File;
-use std::path::Path;
-use std::time::Duration;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use anyhow::Result;
-use futures_util::stream::StreamExt;
-use pap_api::{load_config, Config, Context, ExecutionStatus, PapApi, PapApiClient};
-use pap_server::{server::PipelineServer, step::builtin_executors};
+use clap::Parser;
+use pap_api::{load_config_file, Config, ExecutionStatus, PipelineEvent};
 use sqlx::SqlitePool;
-use tarpc::{client, context, server::Channel};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let file = "../sample.yaml";
+#[cfg(test)]
+mod test;
+
+/// Runs a pipeline in-process, without a server
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the pipeline config
+    #[arg(default_value = "../sample.yaml")]
+    config: PathBuf,
+
+    /// Path to SQLite database file to keep the pipeline's state in, instead
+    /// of an in-memory one that is gone once the run ends.
+    /// A database URL can also be set using DATABASE_URL environment variable
+    #[arg(short, long)]
+    database: Option<PathBuf>,
+}
+
+impl Args {
+    fn database_url(&self) -> String {
+        match &self.database {
+            Some(path) => format!("sqlite://{}?mode=rwc", path.display()),
+            None => std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite::memory:".to_string()),
+        }
+    }
+}
 
-    // Load config and create context
-    let config_file = File::open(file).expect("Could not open file");
-    let config: Config = load_config(config_file).expect("Failed to parse config");
-    let config_dir = Path::new(file)
+/// Runs the pipeline in `file`, writing each step's output to `out` as it
+/// finishes. Returns the pipeline's final status.
+async fn run(file: &Path, database_url: &str, out: &mut impl Write) -> Result<ExecutionStatus> {
+    // Load config
+    let mut config: Config = load_config_file(file)?;
+    config.resolve_env(|name| std::env::var(name).ok())?;
+    let config_dir = file
         .parent()
         .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
-    let context = Context::build_with_config(config, config_dir.to_path_buf())?;
-
-    // Setup server with database URL from environment or fallback to in-memory
-    let database_url =
-        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite::memory:".to_string());
-
-    let db = SqlitePool::connect(&database_url).await?;
-    let service = PipelineServer::new(db, builtin_executors()).await?;
-
-    // Create channel-based transport
-    let (client_transport, server_transport) = tarpc::transport::channel::unbounded();
-
-    // Spawn server
-    let server = tarpc::server::BaseChannel::with_defaults(server_transport);
-    tokio::spawn(
-        server
-            .execute(service.serve())
-            // Handle all requests concurrently.
-            .for_each(|response| async move {
-                tokio::spawn(response);
-            }),
-    );
-
-    // Create client
-    let client = PapApiClient::new(client::Config::default(), client_transport).spawn();
-
-    // Submit pipeline
-    let pipeline_id = client
-        .submit_pipeline(context::current(), context)
-        .await??;
 
-    // Wait for pipeline completion
-    loop {
-        let pipeline = client
-            .get_pipeline(context::current(), pipeline_id)
-            .await??;
-
-        match pipeline.status {
-            ExecutionStatus::Completed | ExecutionStatus::Failed | ExecutionStatus::Cancelled => {
-                break
-            }
-            _ => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                continue;
+    let db = SqlitePool::connect(database_url).await?;
+
+    // Run the pipeline, printing each step's output as it finishes
+    let mut run = pap_server::spawn_pipeline(config, config_dir.to_path_buf(), db).await?;
+    while let Some(event) = run.next_event().await {
+        if let PipelineEvent::StepFinished {
+            step_id, status, ..
+        } = event
+        {
+            let step = run.step(step_id).await?;
+            writeln!(out, "==> {} ({}) <==", step.config.name, status)?;
+            if let Some(output) = step.output {
+                out.write_all(&output.data)?;
             }
         }
     }
 
     // Print execution results
-    println!("\nPipeline {} execution results:", pipeline_id);
-    let pipeline = client
-        .get_pipeline(context::current(), pipeline_id)
-        .await??;
-
-    println!("\nPipeline {}: {:?}", pipeline_id, pipeline.status);
+    let pipeline_id = run.id;
+    let pipeline = run.wait().await?;
+    writeln!(out, "\nPipeline {}: {}", pipeline_id, pipeline.status)?;
     if let Some(error) = pipeline.error {
-        println!("\nPipeline Error:\n{}", error);
+        writeln!(out, "\nPipeline Error:\n{}", error)?;
     }
 
-    for job_id in pipeline.jobs {
-        let job = client.get_job(context::current(), job_id).await??;
-        println!("\nJob {} ({}): {:?}", job_id, job.config.name, job.status);
+    Ok(pipeline.status)
+}
 
-        for step in job.steps {
-            println!(
-                "\n  Step {} ({}): {:?}",
-                step.id, step.config.name, step.status
-            );
-            if let Some(output) = step.output {
-                println!("  Output:\n    {}", String::from_utf8_lossy(&output));
-            }
-        }
-    }
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+
+    let status = run(&args.config, &args.database_url(), &mut std::io::stdout()).await?;
 
-    Ok(())
+    // Match the exit codes of `pap-client pipeline wait`
+    Ok(match status {
+        ExecutionStatus::Completed => ExitCode::SUCCESS,
+        ExecutionStatus::Cancelled => ExitCode::from(2),
+        _ => ExitCode::FAILURE,
+    })
 }
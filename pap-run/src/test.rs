@@ -0,0 +1,73 @@
+use crate::*;
+
+// The server's database pool is global, so runs must not interleave
+static RUN_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+const HELLO_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: greet
+    steps:
+      - name: say-hello
+        call: hello
+        args:
+          name: world
+          count: "2"
+"#;
+
+fn write_config(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("pap-run-test-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("hello.yaml");
+    std::fs::write(&file, HELLO_CONFIG).unwrap();
+    (dir, file)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_run_offline() {
+    let _guard = RUN_LOCK.lock().await;
+    let (dir, file) = write_config("offline");
+
+    let mut out = Vec::new();
+    let status = run(&file, "sqlite::memory:", &mut out).await.unwrap();
+    assert_eq!(status, ExecutionStatus::Completed);
+
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.starts_with("==> say-hello (Completed) <==\nHello, world!\nHello, world!\n"),
+        "{}",
+        out
+    );
+    assert!(out.ends_with("Pipeline 1: Completed\n"), "{}", out);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_run_args() {
+    let _guard = RUN_LOCK.lock().await;
+
+    let args = Args::try_parse_from(["pap-run"]).unwrap();
+    assert_eq!(args.config, Path::new("../sample.yaml"));
+    assert!(args.database.is_none());
+
+    let (dir, file) = write_config("args");
+    let database = dir.join("pap.db");
+    let args = Args::try_parse_from([
+        "pap-run".as_ref(),
+        "--database".as_ref(),
+        database.as_os_str(),
+        file.as_os_str(),
+    ])
+    .unwrap();
+    assert_eq!(args.config, file);
+
+    let status = run(&args.config, &args.database_url(), &mut std::io::sink())
+        .await
+        .unwrap();
+    assert_eq!(status, ExecutionStatus::Completed);
+    // The run's state is kept in the given file
+    assert!(database.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,34 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+
+static COMPRESS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether step logs and objects are compressed when stored. Stored data
+/// is flagged with whether it is compressed, so data written under either
+/// setting, including data from before compression existed, reads back.
+pub fn set_compression(enabled: bool) {
+    COMPRESS.store(enabled, Ordering::Relaxed);
+}
+
+/// Prepares data for storage, returning the bytes to store and whether they
+/// are compressed
+pub(crate) fn encode(data: &[u8]) -> Result<(Cow<'_, [u8]>, bool)> {
+    if !COMPRESS.load(Ordering::Relaxed) {
+        return Ok((Cow::Borrowed(data), false));
+    }
+
+    let compressed = zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+        .context("failed to compress data")?;
+    Ok((Cow::Owned(compressed), true))
+}
+
+/// Recovers data stored by `encode`
+pub(crate) fn decode(data: Vec<u8>, compressed: bool) -> Result<Vec<u8>> {
+    if !compressed {
+        return Ok(data);
+    }
+
+    zstd::decode_all(data.as_slice()).context("failed to decompress stored data")
+}
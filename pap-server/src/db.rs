@@ -1,9 +1,74 @@
 use anyhow::{anyhow, Result};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::SqlitePool;
+use std::future::Future;
+use std::str::FromStr;
 use std::sync::RwLock;
+use std::time::Duration;
 
 static DB_POOL: RwLock<Option<SqlitePool>> = RwLock::new(None);
 
+/// Attempts made at an operation that keeps finding the database locked
+const RETRY_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, doubled before each one after it
+const RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// SQLite's primary result codes for a database another connection is using
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
+/// Connection pool settings and the pragmas applied to every new connection
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Maximum number of connections kept in the pool
+    pub max_connections: u32,
+    /// How long a connection waits on a locked database before erroring
+    pub busy_timeout: Duration,
+    /// Whether to use write-ahead logging, which lets readers proceed
+    /// alongside a writer
+    pub wal: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            busy_timeout: Duration::from_secs(5),
+            wal: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub async fn connect(&self, url: &str) -> Result<SqlitePool> {
+        let busy_timeout = self.busy_timeout;
+        let wal = self.wal;
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .after_connect(move |conn, _| {
+                Box::pin(async move {
+                    sqlx::query(&format!(
+                        "PRAGMA busy_timeout = {}",
+                        busy_timeout.as_millis()
+                    ))
+                    .execute(&mut *conn)
+                    .await?;
+                    if wal {
+                        sqlx::query("PRAGMA journal_mode = WAL")
+                            .execute(&mut *conn)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with(SqliteConnectOptions::from_str(url)?.foreign_keys(true))
+            .await?;
+
+        Ok(pool)
+    }
+}
+
 pub fn init_pool(pool: SqlitePool) -> Result<()> {
     DB_POOL.write().map_err(|e| anyhow!("{}", e))?.replace(pool);
     Ok(())
@@ -18,3 +83,39 @@ pub fn with_pool() -> Result<SqlitePool> {
         .clone()
     )
 }
+
+/// Whether `e` is SQLite finding the database busy or locked by another
+/// connection, which may well succeed if tried again. Running out of pooled
+/// connections isn't: the pool already waited for one, and retrying would
+/// only pile more waiters onto it.
+pub(crate) fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(db)) => db
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            // Extended result codes keep the primary code in the low byte
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+        _ => false,
+    }
+}
+
+/// Runs `op`, retrying with backoff while it fails with a transient error.
+/// Every attempt must be safe to repeat, such as a single transaction.
+pub(crate) async fn retry_transient<T, F, Fut>(mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = RETRY_BACKOFF;
+    for _ in 1..RETRY_ATTEMPTS {
+        match op().await {
+            Err(e) if is_transient(&e) => {
+                log::warn!("Retrying in {:?} after database error: {}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    op().await
+}
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pap_api::{EventRecord, PipelineEvent};
+use tokio::sync::broadcast;
+
+/// Number of recent events kept for clients that poll
+const HISTORY_LEN: usize = 1024;
+
+/// Publishes pipeline lifecycle events, numbering them in order. Recent events
+/// are kept so that polling clients can catch up, and each one is broadcast so
+/// that waiting clients wake up.
+pub(crate) struct EventBus {
+    sender: broadcast::Sender<EventRecord>,
+    /// The id of the next event, and the most recent events
+    history: Mutex<(u64, VecDeque<EventRecord>)>,
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY_LEN);
+        Self {
+            sender,
+            history: Mutex::new((0, VecDeque::new())),
+        }
+    }
+
+    pub(crate) fn publish(&self, event: PipelineEvent) {
+        let mut history = self.history.lock().expect("event history lock poisoned");
+        let (next_id, events) = &mut *history;
+        let record = EventRecord {
+            id: *next_id,
+            event,
+        };
+        *next_id += 1;
+
+        if events.len() == HISTORY_LEN {
+            events.pop_front();
+        }
+        events.push_back(record.clone());
+
+        // Sending under the lock keeps subscribers in publication order. Having
+        // no subscribers is fine.
+        let _ = self.sender.send(record);
+    }
+
+    /// Receives every event published from now on
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.sender.subscribe()
+    }
+
+    /// Events newer than `after`, or every remembered event if `after` is
+    /// `None`. If there are none, waits up to `timeout` for the next one.
+    pub(crate) async fn poll(&self, after: Option<u64>, timeout: Duration) -> Vec<EventRecord> {
+        // Subscribe before looking at the history so no event slips between
+        let mut receiver = self.sender.subscribe();
+        let events = self.since(after);
+        if !events.is_empty() {
+            return events;
+        }
+
+        let _ = tokio::time::timeout(timeout, receiver.recv()).await;
+        self.since(after)
+    }
+
+    fn since(&self, after: Option<u64>) -> Vec<EventRecord> {
+        let history = self.history.lock().expect("event history lock poisoned");
+        history
+            .1
+            .iter()
+            .filter(|record| after.is_none_or(|after| record.id > after))
+            .cloned()
+            .collect()
+    }
+}
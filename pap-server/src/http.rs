@@ -0,0 +1,195 @@
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use pap_api::{Context, JobStatus, PapApi, PapError, PipelineStatus, StepStatus, SubmitResult};
+use serde::Deserialize;
+
+use crate::server::PipelineServer;
+
+/// A [`PapError`] sent back as JSON with a matching HTTP status
+struct HttpError(PapError);
+
+impl From<PapError> for HttpError {
+    fn from(err: PapError) -> Self {
+        HttpError(err)
+    }
+}
+
+/// The HTTP status sent back with `err`
+pub(crate) fn status_code(err: &PapError) -> StatusCode {
+    match err {
+        PapError::NotFound(_) => StatusCode::NOT_FOUND,
+        PapError::Configuration(_) => StatusCode::BAD_REQUEST,
+        PapError::QuotaExceeded(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        // The request's deadline passed before the server finished it
+        PapError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        // The pipeline was cancelled while the request needed it
+        PapError::Cancelled(_) => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (status_code(&self.0), Json(self.0)).into_response()
+    }
+}
+
+type HttpResult<T> = std::result::Result<T, HttpError>;
+
+/// Routes HTTP requests to the same operations as the tarpc service. Bodies
+/// are JSON, except for logs and object values, which are raw bytes. Object
+/// keys are UTF-8 path segments, or hex bytes with `?key_hex=true`.
+pub fn router(server: PipelineServer) -> Router {
+    Router::new()
+        .route("/pipelines", get(get_pipelines).post(submit_pipeline))
+        .route("/pipelines/{id}", get(get_pipeline).delete(delete_pipeline))
+        .route("/pipelines/{id}/cancel", post(cancel_pipeline))
+        .route("/jobs/{id}", get(get_job))
+        .route("/jobs/{id}/cancel", post(cancel_job))
+        .route("/steps/{id}", get(get_step))
+        .route("/steps/{id}/log", get(get_step_log))
+        .route("/steps/{id}/cancel", post(cancel_step))
+        .route(
+            "/objects/{namespace}/{key}",
+            get(get_object).put(put_object),
+        )
+        .with_state(server)
+}
+
+/// Serves the HTTP gateway on `addr` until the process exits
+pub async fn serve(addr: SocketAddr, server: PipelineServer) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log::info!("HTTP gateway listening on {}", addr);
+    axum::serve(listener, router(server)).await?;
+    Ok(())
+}
+
+async fn submit_pipeline(
+    State(server): State<PipelineServer>,
+    Json(context): Json<Context>,
+) -> HttpResult<(StatusCode, Json<SubmitResult>)> {
+    let result = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await?;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+async fn get_pipelines(State(server): State<PipelineServer>) -> HttpResult<Json<Vec<u32>>> {
+    Ok(Json(server.get_pipelines(tarpc::context::current()).await?))
+}
+
+async fn get_pipeline(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<Json<PipelineStatus>> {
+    Ok(Json(
+        server.get_pipeline(tarpc::context::current(), id).await?,
+    ))
+}
+
+async fn cancel_pipeline(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<StatusCode> {
+    server
+        .cancel_pipeline(tarpc::context::current(), id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_pipeline(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<StatusCode> {
+    server
+        .delete_pipeline(tarpc::context::current(), id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_job(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<Json<JobStatus>> {
+    Ok(Json(server.get_job(tarpc::context::current(), id).await?))
+}
+
+async fn cancel_job(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<StatusCode> {
+    server.cancel_job(tarpc::context::current(), id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_step(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<Json<StepStatus>> {
+    Ok(Json(server.get_step(tarpc::context::current(), id).await?))
+}
+
+async fn get_step_log(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<Vec<u8>> {
+    Ok(server.get_step_log(tarpc::context::current(), id).await?)
+}
+
+async fn cancel_step(
+    State(server): State<PipelineServer>,
+    Path(id): Path<u32>,
+) -> HttpResult<StatusCode> {
+    server.cancel_step(tarpc::context::current(), id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Query parameters of the object routes
+#[derive(Deserialize)]
+struct ObjectQuery {
+    /// The key in the path is hex bytes, as with the CLI's `--key-hex`, for
+    /// keys that aren't UTF-8 such as corpus entries
+    #[serde(default)]
+    key_hex: bool,
+}
+
+impl ObjectQuery {
+    /// The bytes of an object key as written in the path
+    fn key(&self, key: String) -> HttpResult<Vec<u8>> {
+        if !self.key_hex {
+            return Ok(key.into_bytes());
+        }
+        Ok(pap_api::parse_hex_key(&key)?)
+    }
+}
+
+async fn get_object(
+    State(server): State<PipelineServer>,
+    Path((namespace, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+) -> HttpResult<Vec<u8>> {
+    let key = query.key(key)?;
+    Ok(server
+        .get_object(tarpc::context::current(), namespace, key)
+        .await?)
+}
+
+async fn put_object(
+    State(server): State<PipelineServer>,
+    Path((namespace, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+    value: Bytes,
+) -> HttpResult<StatusCode> {
+    let key = query.key(key)?;
+    server
+        .put_object(tarpc::context::current(), namespace, key, value.to_vec())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
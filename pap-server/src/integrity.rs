@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sha2::{Digest, Sha256};
+
+static VERIFY: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether objects are stored with a checksum that is verified when they
+/// are read back. Objects stored without one, such as those written while
+/// verification was off, read back unchecked.
+pub fn set_integrity_checks(enabled: bool) {
+    VERIFY.store(enabled, Ordering::Relaxed);
+}
+
+/// Computes the checksum to store alongside an object value, if checks are on
+pub(crate) fn checksum(value: &[u8]) -> Option<Vec<u8>> {
+    if !VERIFY.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    Some(Sha256::digest(value).to_vec())
+}
+
+/// Whether a value read back matches the checksum stored with it. Values
+/// without a checksum, or read while checks are off, are taken as intact.
+pub(crate) fn is_intact(value: &[u8], expected: Option<&[u8]>) -> bool {
+    match expected {
+        Some(expected) if VERIFY.load(Ordering::Relaxed) => {
+            Sha256::digest(value).as_slice() == expected
+        }
+        _ => true,
+    }
+}
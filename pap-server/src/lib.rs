@@ -1,7 +1,20 @@
+pub(crate) mod compression;
 pub(crate) mod db;
+pub(crate) mod events;
+pub mod http;
+pub(crate) mod integrity;
 pub(crate) mod queries;
+pub(crate) mod run;
 pub mod server;
 pub mod step;
+#[cfg(test)]
+mod test;
+
+pub use compression::set_compression;
+pub use db::PoolConfig;
+pub use integrity::set_integrity_checks;
+pub use queries::{set_exclusive_namespaces, set_max_object_size, DEFAULT_MAX_OBJECT_SIZE};
+pub use run::{run_pipeline, spawn_pipeline, PipelineRun};
 
 use thiserror::Error;
 
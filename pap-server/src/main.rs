@@ -1,29 +1,73 @@
 use anyhow::Result;
-use clap::Parser;
-use futures::{future, prelude::*};
-use pap_api::PapApi;
-use pap_server::{server::PipelineServer, step::builtin_executors};
-use sqlx::sqlite::SqlitePoolOptions;
+use clap::{ArgAction, Parser};
+use pap_api::ServerAddr;
+use pap_server::server::{PipelineServer, DEFAULT_MAX_CONCURRENT_REQUESTS};
+use pap_server::{
+    http, set_compression, set_exclusive_namespaces, set_integrity_checks, set_max_object_size,
+    step::builtin_executors, PoolConfig, DEFAULT_MAX_OBJECT_SIZE,
+};
 use std::net::SocketAddr;
-use tarpc::{server::Channel, tokio_serde::formats::Json};
+use std::time::Duration;
 use tokio::spawn;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Config {
-    /// Address to bind the server to
+    /// Address to bind the server to, or `unix:<path>` for a unix socket
     #[arg(short, long, default_value = "127.0.0.1:9090")]
-    bind_addr: String,
+    bind_addr: ServerAddr,
 
     /// Path to SQLite database file
     #[arg(short, long, default_value = "sqlite::memory:")]
     database: String,
+
+    /// Maximum number of database connections
+    #[arg(long, default_value_t = 10)]
+    max_connections: u32,
+
+    /// How long to wait on a locked database before failing, in milliseconds
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout_ms: u64,
+
+    /// Use SQLite write-ahead logging to reduce lock contention
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    wal: bool,
+
+    /// Compress step logs and objects stored in the database
+    #[arg(long)]
+    compress: bool,
+
+    /// Store a checksum with each object and verify it when the object is read
+    #[arg(long)]
+    verify_objects: bool,
+
+    /// Largest object value that may be stored, in bytes
+    #[arg(long, default_value_t = DEFAULT_MAX_OBJECT_SIZE)]
+    max_object_size: usize,
+
+    /// Reject a step's object writes to a namespace that another pending or
+    /// running pipeline has written to, rather than letting them share it
+    #[arg(long)]
+    exclusive_namespaces: bool,
+
+    /// Also serve an HTTP/JSON gateway on this address
+    #[arg(long)]
+    http_addr: Option<String>,
+
+    /// Most client connections served at once. Later connections wait for
+    /// one to close. Submitted pipelines run in the background and don't
+    /// count against this.
+    #[arg(long, default_value_t = DEFAULT_MAX_CONCURRENT_REQUESTS)]
+    max_concurrent_requests: usize,
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
     // Parse command line arguments
     let config = Config::parse();
+    if config.max_concurrent_requests == 0 {
+        anyhow::bail!("--max-concurrent-requests must be at least 1");
+    }
 
     // Initialize logging
     env_logger::init();
@@ -33,34 +77,38 @@ async fn main() -> Result<()> {
     // Initialize the step executor registry
     let registry = builtin_executors();
 
-    // Create SQLite connection pool with default settings
-    let pool = SqlitePoolOptions::new()
+    // Create SQLite connection pool
+    let pool_config = PoolConfig {
+        max_connections: config.max_connections,
+        busy_timeout: Duration::from_millis(config.busy_timeout_ms),
+        wal: config.wal,
+    };
+    let pool = pool_config
         .connect(&format!("sqlite:{}", config.database))
         .await?;
 
     log::info!("Connected to database");
 
+    set_compression(config.compress);
+    set_integrity_checks(config.verify_objects);
+    set_max_object_size(config.max_object_size);
+    set_exclusive_namespaces(config.exclusive_namespaces);
+
     // Create server instance
     let server = PipelineServer::new(pool, registry).await?;
 
-    // Set up transport
-    let addr: SocketAddr = config.bind_addr.parse()?;
-    let listener = tarpc::serde_transport::tcp::listen(addr, Json::default).await?;
-
-    log::info!("Server listening on {}", addr);
+    if let Some(http_addr) = config.http_addr {
+        let http_addr: SocketAddr = http_addr.parse()?;
+        let server = server.clone();
+        spawn(async move {
+            if let Err(e) = http::serve(http_addr, server).await {
+                log::error!("HTTP gateway failed: {}", e);
+            }
+        });
+    }
 
     // Start serving
-    listener
-        .filter_map(|r| future::ready(r.ok()))
-        .map(tarpc::server::BaseChannel::with_defaults)
-        .map(|channel| {
-            channel.execute(server.clone().serve()).for_each(|x| async {
-                spawn(x);
-            })
-        })
-        .buffer_unordered(10)
-        .for_each(|_| async {})
-        .await;
+    pap_server::server::serve(&config.bind_addr, server, config.max_concurrent_requests).await?;
 
 
     // Keep the main thread running
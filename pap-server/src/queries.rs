@@ -1,9 +1,17 @@
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
-use anyhow::Result;
+use crate::compression;
 use crate::db::with_pool;
-use pap_api::{ExecutionStatus, JobStatus, PapError, PipelineStatus, Step, StepStatus};
-use sqlx::Row;
+use crate::integrity;
+use anyhow::Result;
+use pap_api::{
+    Config, ExecutionStatus, JobStatus, OutputKind, PapError, PipelineStatus, StatusChange,
+    StatusEntity, Step, StepOutput, StepStatus,
+};
+use sqlx::{Row, Sqlite, Transaction};
 
 pub(crate) async fn init_tables() -> Result<()> {
     sqlx::query(
@@ -12,7 +20,11 @@ pub(crate) async fn init_tables() -> Result<()> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             config TEXT,
             context BLOB,
-            execution_status TEXT DEFAULT 'Pending'
+            execution_status TEXT DEFAULT 'Pending',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            dry_run BOOLEAN DEFAULT 0,
+            object_quota INTEGER,
+            object_bytes INTEGER DEFAULT 0
         )
         "#,
     )
@@ -46,6 +58,13 @@ pub(crate) async fn init_tables() -> Result<()> {
                 io TEXT,
                 status TEXT DEFAULT 'Pending',
                 log_data BLOB,
+                started_at DATETIME,
+                finished_at DATETIME,
+                cleanup_on_failure BOOLEAN DEFAULT 0,
+                log_compressed BOOLEAN DEFAULT 0,
+                env TEXT DEFAULT '{}',
+                stats TEXT,
+                output_kind TEXT DEFAULT 'Text',
                 FOREIGN KEY(job_id) REFERENCES jobs(id),
                 FOREIGN KEY(pipeline_id) REFERENCES pipelines(id)
             )
@@ -54,6 +73,18 @@ pub(crate) async fn init_tables() -> Result<()> {
     .execute(&with_pool()?)
     .await?;
 
+    // Columns added after the initial schema, for databases created before them
+    add_column_if_missing("pipelines", "created_at", "DATETIME").await?;
+    add_column_if_missing("pipelines", "dry_run", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing("pipelines", "object_quota", "INTEGER").await?;
+    add_column_if_missing("steps", "started_at", "DATETIME").await?;
+    add_column_if_missing("steps", "finished_at", "DATETIME").await?;
+    add_column_if_missing("steps", "cleanup_on_failure", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing("steps", "log_compressed", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing("steps", "env", "TEXT DEFAULT '{}'").await?;
+    add_column_if_missing("steps", "stats", "TEXT").await?;
+    add_column_if_missing("steps", "output_kind", "TEXT DEFAULT 'Text'").await?;
+
     sqlx::query(
         r#"
             CREATE TABLE IF NOT EXISTS objects (
@@ -61,6 +92,10 @@ pub(crate) async fn init_tables() -> Result<()> {
                 key BLOB,
                 value BLOB,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                step_id INTEGER,
+                compressed BOOLEAN DEFAULT 0,
+                pipeline_id INTEGER,
+                checksum BLOB,
                 PRIMARY KEY (namespace, key)
             )
             "#,
@@ -68,6 +103,69 @@ pub(crate) async fn init_tables() -> Result<()> {
     .execute(&with_pool()?)
     .await?;
 
+    add_column_if_missing("objects", "step_id", "INTEGER").await?;
+    add_column_if_missing("objects", "compressed", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing("objects", "pipeline_id", "INTEGER").await?;
+    add_column_if_missing("objects", "checksum", "BLOB").await?;
+
+    // Each pipeline keeps a running total of the bytes its objects take up, so
+    // that checking its quota doesn't add up every object on each write
+    if add_column_if_missing("pipelines", "object_bytes", "INTEGER DEFAULT 0").await? {
+        sqlx::query(
+            r#"
+            UPDATE pipelines SET object_bytes = (
+                SELECT COALESCE(SUM(LENGTH(value)), 0) FROM objects WHERE pipeline_id = pipelines.id
+            )
+            "#,
+        )
+        .execute(&with_pool()?)
+        .await?;
+    }
+    for trigger in [
+        r#"
+        CREATE TRIGGER IF NOT EXISTS count_inserted_object_bytes
+        AFTER INSERT ON objects
+        BEGIN
+            UPDATE pipelines SET object_bytes = object_bytes + LENGTH(NEW.value)
+            WHERE id = NEW.pipeline_id;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS count_updated_object_bytes
+        AFTER UPDATE OF value ON objects
+        BEGIN
+            UPDATE pipelines SET object_bytes = object_bytes - LENGTH(OLD.value)
+            WHERE id = OLD.pipeline_id;
+            UPDATE pipelines SET object_bytes = object_bytes + LENGTH(NEW.value)
+            WHERE id = NEW.pipeline_id;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS count_deleted_object_bytes
+        AFTER DELETE ON objects
+        BEGIN
+            UPDATE pipelines SET object_bytes = object_bytes - LENGTH(OLD.value)
+            WHERE id = OLD.pipeline_id;
+        END
+        "#,
+    ] {
+        sqlx::query(trigger).execute(&with_pool()?).await?;
+    }
+
+    sqlx::query(
+        r#"
+            CREATE TABLE IF NOT EXISTS pipeline_labels (
+                pipeline_id INTEGER,
+                key TEXT,
+                value TEXT,
+                PRIMARY KEY (pipeline_id, key),
+                FOREIGN KEY(pipeline_id) REFERENCES pipelines(id)
+            )
+            "#,
+    )
+    .execute(&with_pool()?)
+    .await?;
+
     sqlx::query(
         r#"
             CREATE TABLE IF NOT EXISTS global_errors (
@@ -75,6 +173,32 @@ pub(crate) async fn init_tables() -> Result<()> {
                 pipeline_id INTEGER,
                 timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
                 error_message TEXT,
+                error TEXT,
+                job_id INTEGER,
+                step_id INTEGER,
+                FOREIGN KEY(pipeline_id) REFERENCES pipelines(id),
+                FOREIGN KEY(job_id) REFERENCES jobs(id),
+                FOREIGN KEY(step_id) REFERENCES steps(id)
+            )
+            "#,
+    )
+    .execute(&with_pool()?)
+    .await?;
+
+    add_column_if_missing("global_errors", "error", "TEXT").await?;
+    add_column_if_missing("global_errors", "job_id", "INTEGER").await?;
+    add_column_if_missing("global_errors", "step_id", "INTEGER").await?;
+
+    sqlx::query(
+        r#"
+            CREATE TABLE IF NOT EXISTS status_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                pipeline_id INTEGER,
+                entity_type TEXT,
+                entity_id INTEGER,
+                old_status TEXT,
+                new_status TEXT,
+                timestamp TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
                 FOREIGN KEY(pipeline_id) REFERENCES pipelines(id)
             )
             "#,
@@ -82,9 +206,83 @@ pub(crate) async fn init_tables() -> Result<()> {
     .execute(&with_pool()?)
     .await?;
 
+    // Statuses are changed by many queries, some of them for a whole pipeline
+    // at once, so every change is recorded where it happens
+    for (entity, table, column) in [
+        (StatusEntity::Pipeline, "pipelines", "execution_status"),
+        (StatusEntity::Job, "jobs", "status"),
+        (StatusEntity::Step, "steps", "status"),
+    ] {
+        let pipeline_column = match entity {
+            StatusEntity::Pipeline => "id",
+            _ => "pipeline_id",
+        };
+        sqlx::query(&format!(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS record_{table}_status
+            AFTER UPDATE OF {column} ON {table}
+            WHEN OLD.{column} IS NOT NEW.{column}
+            BEGIN
+                INSERT INTO status_history (pipeline_id, entity_type, entity_id, old_status, new_status)
+                VALUES (NEW.{pipeline_column}, '{entity}', NEW.id, OLD.{column}, NEW.{column});
+            END
+            "#
+        ))
+        .execute(&with_pool()?)
+        .await?;
+    }
+
+    // Rows are mostly looked up by their owner, which would otherwise scan the
+    // whole table. Objects by namespace and key use their primary key.
+    for (table, column) in [
+        ("jobs", "pipeline_id"),
+        ("steps", "job_id"),
+        ("steps", "pipeline_id"),
+        ("objects", "step_id"),
+        ("objects", "pipeline_id"),
+        ("global_errors", "pipeline_id"),
+        ("status_history", "pipeline_id"),
+    ] {
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS idx_{0}_{1} ON {0}({1})",
+            table, column
+        ))
+        .execute(&with_pool()?)
+        .await?;
+    }
+
     Ok(())
 }
 
+/// Adds a column to a table created before it existed, returning whether it
+/// was missing
+async fn add_column_if_missing(table: &str, column: &str, definition: &str) -> Result<bool> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pragma_table_info(?) WHERE name = ?)")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&with_pool()?)
+            .await?;
+
+    if !exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(&with_pool()?)
+        .await?;
+    }
+
+    Ok(!exists)
+}
+
+/// Parses a status read from the database, naming the offending value and row
+/// if it isn't one we know about
+fn parse_status(value: &str, kind: &str, id: u32) -> Result<ExecutionStatus, PapError> {
+    ExecutionStatus::from_str(value)
+        .map_err(|_| PapError::Database(format!("invalid status '{}' for {} {}", value, kind, id)))
+}
+
 pub(crate) async fn set_pipeline_status(
     pipeline_id: u32,
     status: ExecutionStatus,
@@ -116,46 +314,83 @@ pub(crate) async fn set_job_status(job_id: u32, status: ExecutionStatus) -> Resu
 }
 
 pub(crate) async fn set_step_status(step_id: u32, status: ExecutionStatus) -> Result<()> {
-    sqlx::query(
-        r#"
-            UPDATE steps SET status = ? WHERE id = ?
-            "#,
-    )
-    .bind(status.to_string())
-    .bind(step_id)
-    .execute(&with_pool()?)
-    .await?;
+    // Record when the step starts running and when it reaches a terminal state
+    let query = match status {
+        ExecutionStatus::Pending => "UPDATE steps SET status = ? WHERE id = ?",
+        ExecutionStatus::Running => {
+            "UPDATE steps SET status = ?, started_at = strftime('%Y-%m-%d %H:%M:%f', 'now'), finished_at = NULL WHERE id = ?"
+        }
+        ExecutionStatus::Completed | ExecutionStatus::Failed | ExecutionStatus::Cancelled => {
+            "UPDATE steps SET status = ?, finished_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE id = ?"
+        }
+    };
+
+    sqlx::query(query)
+        .bind(status.to_string())
+        .bind(step_id)
+        .execute(&with_pool()?)
+        .await?;
     Ok(())
 }
 
-pub(crate) async fn set_step_log(step_id: u32, log_data: &[u8]) -> Result<()> {
+/// Stores a step's output along with its kind
+pub(crate) async fn set_step_output(step_id: u32, output: &StepOutput) -> Result<()> {
+    let (log_data, compressed) = compression::encode(&output.data)?;
     sqlx::query(
         r#"
-            UPDATE steps SET log_data = ? WHERE id = ?
+            UPDATE steps SET log_data = ?, log_compressed = ?, output_kind = ? WHERE id = ?
             "#,
     )
-    .bind(log_data)
+    .bind(log_data.as_ref())
+    .bind(compressed)
+    .bind(output.kind.to_string())
     .bind(step_id)
     .execute(&with_pool()?)
     .await?;
     Ok(())
 }
 
-pub(crate) async fn store_error(pipeline_id: u32, error: &str) -> Result<()> {
+pub(crate) async fn set_step_stats(step_id: u32, stats: &str) -> Result<()> {
+    sqlx::query("UPDATE steps SET stats = ? WHERE id = ?")
+        .bind(stats)
+        .bind(step_id)
+        .execute(&with_pool()?)
+        .await?;
+    Ok(())
+}
+
+/// Records why a pipeline stopped, along with the job and step that failed if
+/// a step caused it
+pub(crate) async fn store_error(
+    pipeline_id: u32,
+    error: &PapError,
+    failed_step: Option<(u32, u32)>,
+) -> Result<()> {
+    // A cancelled pipeline keeps its status; anything else is a failure
+    let status = match error {
+        PapError::Cancelled(_) => ExecutionStatus::Cancelled,
+        _ => ExecutionStatus::Failed,
+    };
+
     let db = with_pool()?;
     let mut tx = db.begin().await?;
 
     sqlx::query(r#"UPDATE pipelines SET execution_status = ? WHERE id = ?"#)
-        .bind(ExecutionStatus::Failed.to_string())
+        .bind(status.to_string())
         .bind(pipeline_id)
         .execute(&mut *tx)
         .await?;
 
-    sqlx::query(r#"INSERT INTO global_errors (pipeline_id, error_message) VALUES (?, ?)"#)
-        .bind(pipeline_id)
-        .bind(error)
-        .execute(&mut *tx)
-        .await?;
+    sqlx::query(
+        r#"INSERT INTO global_errors (pipeline_id, error_message, error, job_id, step_id) VALUES (?, ?, ?, ?, ?)"#,
+    )
+    .bind(pipeline_id)
+    .bind(error.to_string())
+    .bind(serde_json::to_string(error)?)
+    .bind(failed_step.map(|(job_id, _)| job_id))
+    .bind(failed_step.map(|(_, step_id)| step_id))
+    .execute(&mut *tx)
+    .await?;
 
     tx.commit().await?;
 
@@ -165,36 +400,167 @@ pub(crate) async fn store_error(pipeline_id: u32, error: &str) -> Result<()> {
 }
 
 pub(crate) async fn get_pipeline_status(id: u32) -> anyhow::Result<PipelineStatus> {
-    let pipeline = sqlx::query(
+    get_pipeline_statuses(&[id])
+        .await?
+        .pop()
+        .ok_or_else(|| PapError::NotFound(format!("Pipeline {}", id)).into())
+}
+
+/// Gets the status of several pipelines in a few queries, in the order of
+/// `ids`. Ids of pipelines that don't exist are skipped.
+pub(crate) async fn get_pipeline_statuses(ids: &[u32]) -> anyhow::Result<Vec<PipelineStatus>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; ids.len()].join(", ");
+
+    let query = format!(
+        "SELECT id, config, execution_status, created_at, dry_run FROM pipelines WHERE id IN ({})",
+        placeholders
+    );
+    let mut pipeline_query = sqlx::query(&query);
+    for id in ids {
+        pipeline_query = pipeline_query.bind(id);
+    }
+    let mut pipelines = HashMap::new();
+    for row in pipeline_query.fetch_all(&with_pool()?).await? {
+        pipelines.insert(row.get::<u32, _>(0), row);
+    }
+
+    let query = format!(
+        "SELECT pipeline_id, id FROM jobs WHERE pipeline_id IN ({}) ORDER BY id",
+        placeholders
+    );
+    let mut job_query = sqlx::query_as::<_, (u32, u32)>(&query);
+    for id in ids {
+        job_query = job_query.bind(id);
+    }
+    let mut jobs: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (pipeline_id, job_id) in job_query.fetch_all(&with_pool()?).await? {
+        jobs.entry(pipeline_id).or_default().push(job_id);
+    }
+
+    // Only the most recent error of each pipeline is reported
+    let query = format!(
         r#"
-        SELECT config, context, execution_status
-        FROM pipelines
-        WHERE id = ?
+        SELECT pipeline_id, error_message, error, job_id, step_id
+        FROM global_errors
+        WHERE id IN (
+            SELECT MAX(id) FROM global_errors WHERE pipeline_id IN ({}) GROUP BY pipeline_id
+        )
         "#,
-    )
-    .bind(id)
-    .fetch_optional(&with_pool()?)
-    .await?
-    .ok_or_else(|| PapError::NotFound(format!("Pipeline {}", id)))?;
+        placeholders
+    );
+    let mut error_query =
+        sqlx::query_as::<_, (u32, String, Option<String>, Option<u32>, Option<u32>)>(&query);
+    for id in ids {
+        error_query = error_query.bind(id);
+    }
+    let mut errors = HashMap::new();
+    for (pipeline_id, message, error, job_id, step_id) in
+        error_query.fetch_all(&with_pool()?).await?
+    {
+        let error = match error {
+            Some(error) => serde_json::from_str(&error)?,
+            // Errors stored before their kind was recorded
+            None => PapError::Execution(message),
+        };
+        errors.insert(pipeline_id, (error, job_id, step_id));
+    }
 
-    let jobs = sqlx::query_scalar(
+    let query = format!(
         r#"
-        SELECT id
-        FROM jobs
-        WHERE pipeline_id = ?
+        SELECT pipeline_id, COUNT(*), SUM(status = ?)
+        FROM steps
+        WHERE pipeline_id IN ({})
+        GROUP BY pipeline_id
         "#,
-    )
-    .bind(id)
-    .fetch_all(&with_pool()?)
-    .await?;
+        placeholders
+    );
+    let mut step_query =
+        sqlx::query_as::<_, (u32, u32, u32)>(&query).bind(ExecutionStatus::Completed.to_string());
+    for id in ids {
+        step_query = step_query.bind(id);
+    }
+    let mut step_counts = HashMap::new();
+    for (pipeline_id, total, completed) in step_query.fetch_all(&with_pool()?).await? {
+        step_counts.insert(pipeline_id, (completed, total));
+    }
 
-    Ok(PipelineStatus {
-        id,
-        config: serde_json::from_str(pipeline.get(0))?,
-        jobs,
-        status: ExecutionStatus::from_str(&pipeline.get::<String, _>(2))?,
-        error: None,
-    })
+    let mut statuses = Vec::new();
+    for &id in ids {
+        let Some(pipeline) = pipelines.get(&id) else {
+            continue;
+        };
+        let (completed_steps, total_steps) = step_counts.get(&id).copied().unwrap_or_default();
+        let (error, failed_job, failed_step) = match errors.get(&id) {
+            Some((error, job_id, step_id)) => (Some(error.clone()), *job_id, *step_id),
+            None => (None, None, None),
+        };
+
+        statuses.push(PipelineStatus {
+            id,
+            config: serde_json::from_str(pipeline.get(1))?,
+            jobs: jobs.get(&id).cloned().unwrap_or_default(),
+            status: parse_status(pipeline.get(2), "pipeline", id)?,
+            error,
+            failed_job,
+            failed_step,
+            created_at: pipeline.get(3),
+            dry_run: pipeline.get(4),
+            completed_steps,
+            total_steps,
+            progress: progress(completed_steps, total_steps),
+        });
+    }
+    Ok(statuses)
+}
+
+fn progress(completed_steps: u32, total_steps: u32) -> f32 {
+    if total_steps == 0 {
+        return 0.0;
+    }
+    completed_steps as f32 / total_steps as f32
+}
+
+pub(crate) async fn get_pipeline_context(id: u32) -> anyhow::Result<pap_api::Context> {
+    let context = sqlx::query_scalar::<_, Vec<u8>>("SELECT context FROM pipelines WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&with_pool()?)
+        .await?
+        .ok_or_else(|| PapError::NotFound(format!("Pipeline {}", id)))?;
+
+    Ok(serde_json::from_slice(&context)?)
+}
+
+/// Gets the config a pipeline ran with. Each step's IO is as it was stored,
+/// including the namespaces given to fields the config left unset.
+pub(crate) async fn get_pipeline_config(id: u32) -> anyhow::Result<Config> {
+    let config = sqlx::query_scalar::<_, String>("SELECT config FROM pipelines WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&with_pool()?)
+        .await?
+        .ok_or_else(|| PapError::NotFound(format!("Pipeline {}", id)))?;
+    let mut config: Config = serde_json::from_str(&config)?;
+
+    // Jobs and their steps are stored in config order
+    let job_ids =
+        sqlx::query_scalar::<_, u32>("SELECT id FROM jobs WHERE pipeline_id = ? ORDER BY id")
+            .bind(id)
+            .fetch_all(&with_pool()?)
+            .await?;
+    for (job, job_id) in config.jobs.iter_mut().zip(job_ids) {
+        let ios =
+            sqlx::query_scalar::<_, String>("SELECT io FROM steps WHERE job_id = ? ORDER BY id")
+                .bind(job_id)
+                .fetch_all(&with_pool()?)
+                .await?;
+        for (step, io) in job.steps.iter_mut().zip(ios) {
+            step.io = serde_json::from_str(&io)?;
+        }
+    }
+
+    Ok(config)
 }
 
 pub(crate) async fn get_job_status(id: u32) -> anyhow::Result<JobStatus> {
@@ -212,7 +578,9 @@ pub(crate) async fn get_job_status(id: u32) -> anyhow::Result<JobStatus> {
 
     let steps = sqlx::query(
         r#"
-                SELECT id, name, call, args, io, status, log_data
+                SELECT id, name, call, args, io, status, log_data, started_at, finished_at,
+                       (julianday(finished_at) - julianday(started_at)) * 86400.0,
+                       cleanup_on_failure, log_compressed, env, stats, output_kind
                 FROM steps
                 WHERE job_id = ?
                 ORDER BY id ASC
@@ -225,16 +593,23 @@ pub(crate) async fn get_job_status(id: u32) -> anyhow::Result<JobStatus> {
     let step_statuses = steps
         .into_iter()
         .map(|step| {
+            let step_id = step.get(0);
             Ok(StepStatus {
-                id: step.get(0),
+                id: step_id,
                 config: Step {
                     name: step.get(1),
                     call: step.get(2),
                     args: serde_json::from_str(step.get(3))?,
                     io: serde_json::from_str(step.get(4))?, // Parse io config
+                    env: serde_json::from_str(step.get(12))?,
+                    cleanup_on_failure: step.get(10),
                 },
-                status: ExecutionStatus::from_str(&step.get::<String, _>(5))?,
-                output: step.get(6),
+                status: parse_status(step.get(5), "step", step_id)?,
+                output: decode_output(step.get(6), step.get(11), step.get(14))?,
+                stats: step.get(13),
+                started_at: step.get(7),
+                finished_at: step.get(8),
+                duration: step.get::<Option<f64>, _>(9).map(Duration::from_secs_f64),
             })
         })
         .collect::<anyhow::Result<Vec<_>>>()?;
@@ -243,16 +618,152 @@ pub(crate) async fn get_job_status(id: u32) -> anyhow::Result<JobStatus> {
         id,
         config: serde_json::from_str(job.get(1))?,
         steps: step_statuses,
-        status: ExecutionStatus::from_str(&job.get::<String, _>(2))?,
+        status: parse_status(job.get(2), "job", id)?,
         current_step: job.get(3),
     })
 }
 
-#[allow(dead_code)]
+/// Gets the output of the most recent step with the given name that ran
+/// before `step_id` in the same pipeline
+pub(crate) async fn get_prior_step_output(
+    pipeline_id: u32,
+    step_id: u32,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let (output, compressed) = sqlx::query_as::<_, (Option<Vec<u8>>, bool)>(
+        r#"
+        SELECT log_data, log_compressed
+        FROM steps
+        WHERE pipeline_id = ? AND name = ? AND id < ?
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(pipeline_id)
+    .bind(name)
+    .bind(step_id)
+    .fetch_optional(&with_pool()?)
+    .await?
+    .ok_or_else(|| {
+        PapError::NotFound(format!(
+            "Step {} before step {} in pipeline {}",
+            name, step_id, pipeline_id
+        ))
+    })?;
+
+    Ok(decode_log(output, compressed)?.unwrap_or_default())
+}
+
+/// Gets a step's log, which is empty until the step logs something. Only a
+/// step that doesn't exist is an error.
+pub(crate) async fn get_step_log(id: u32) -> Result<Vec<u8>> {
+    // The row is fetched whole, so that a step without a log isn't mistaken
+    // for a missing one
+    let (log_data, compressed) = sqlx::query_as::<_, (Option<Vec<u8>>, bool)>(
+        "SELECT log_data, log_compressed FROM steps WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&with_pool()?)
+    .await?
+    .ok_or_else(|| PapError::NotFound(format!("Step {}", id)))?;
+
+    Ok(decode_log(log_data, compressed)?.unwrap_or_default())
+}
+
+/// Finds the ID of the step named `step_name` in a pipeline, optionally only
+/// looking in the job named `job_name`. A name shared by steps of several
+/// jobs needs the job to pick one.
+pub(crate) async fn find_step_id(
+    pipeline_id: u32,
+    job_name: Option<&str>,
+    step_name: &str,
+) -> Result<u32> {
+    let rows = sqlx::query_as::<_, (u32, String)>(
+        r#"
+        SELECT steps.id, jobs.name
+        FROM steps JOIN jobs ON steps.job_id = jobs.id
+        WHERE jobs.pipeline_id = ? AND steps.name = ? AND (? IS NULL OR jobs.name = ?)
+        ORDER BY jobs.id, steps.id
+        "#,
+    )
+    .bind(pipeline_id)
+    .bind(step_name)
+    .bind(job_name)
+    .bind(job_name)
+    .fetch_all(&with_pool()?)
+    .await?;
+
+    match rows.as_slice() {
+        [(id, _)] => Ok(*id),
+        [] => Err(PapError::NotFound(match job_name {
+            Some(job_name) => format!(
+                "Step {} in job {} of pipeline {}",
+                step_name, job_name, pipeline_id
+            ),
+            None => format!("Step {} in pipeline {}", step_name, pipeline_id),
+        })
+        .into()),
+        rows => {
+            let jobs: Vec<_> = rows.iter().map(|(_, job)| job.as_str()).collect();
+            Err(PapError::Configuration(format!(
+                "step {} is in several jobs of pipeline {} ({}); name the job to pick one",
+                step_name,
+                pipeline_id,
+                jobs.join(", ")
+            ))
+            .into())
+        }
+    }
+}
+
+/// Gets the log of every step of a pipeline that has one, ordered by job and
+/// then step
+pub(crate) async fn get_pipeline_logs(pipeline_id: u32) -> Result<Vec<(u32, Vec<u8>)>> {
+    let rows = sqlx::query_as::<_, (u32, Option<Vec<u8>>, bool)>(
+        r#"
+        SELECT steps.id, steps.log_data, steps.log_compressed
+        FROM steps JOIN jobs ON steps.job_id = jobs.id
+        WHERE jobs.pipeline_id = ? AND steps.log_data IS NOT NULL
+        ORDER BY jobs.id, steps.id
+        "#,
+    )
+    .bind(pipeline_id)
+    .fetch_all(&with_pool()?)
+    .await?;
+
+    rows.into_iter()
+        .map(|(id, log_data, compressed)| {
+            Ok((id, decode_log(log_data, compressed)?.unwrap_or_default()))
+        })
+        .collect()
+}
+
+/// Decompresses a step log if there is one
+fn decode_log(log_data: Option<Vec<u8>>, compressed: bool) -> Result<Option<Vec<u8>>> {
+    log_data
+        .map(|log_data| compression::decode(log_data, compressed))
+        .transpose()
+}
+
+fn decode_output(
+    log_data: Option<Vec<u8>>,
+    compressed: bool,
+    kind: Option<String>,
+) -> Result<Option<StepOutput>> {
+    let kind = match kind {
+        Some(kind) => OutputKind::from_str(&kind)
+            .map_err(|_| PapError::Database(format!("invalid output kind '{}'", kind)))?,
+        None => OutputKind::default(),
+    };
+    Ok(decode_log(log_data, compressed)?.map(|data| StepOutput { kind, data }))
+}
+
 pub(crate) async fn get_step_status(id: u32) -> anyhow::Result<StepStatus> {
     let step = sqlx::query(
         r#"
-        SELECT job_id, name, call, args, io, status, log_data
+        SELECT job_id, name, call, args, io, status, log_data, started_at, finished_at,
+               (julianday(finished_at) - julianday(started_at)) * 86400.0,
+               cleanup_on_failure, log_compressed, env, stats, output_kind
         FROM steps
         WHERE id = ?
         "#,
@@ -269,48 +780,352 @@ pub(crate) async fn get_step_status(id: u32) -> anyhow::Result<StepStatus> {
             call: step.get(2),
             args: serde_json::from_str(step.get(3))?,
             io: serde_json::from_str(step.get(4))?, // Parse io config
+            env: serde_json::from_str(step.get(12))?,
+            cleanup_on_failure: step.get(10),
         },
-        status: ExecutionStatus::from_str(&step.get::<String, _>(5))?,
-        output: step.get(6),
+        status: parse_status(step.get(5), "step", id)?,
+        output: decode_output(step.get(6), step.get(11), step.get(14))?,
+        stats: step.get(13),
+        started_at: step.get(7),
+        finished_at: step.get(8),
+        duration: step.get::<Option<f64>, _>(9).map(Duration::from_secs_f64),
     })
 }
 
 pub(crate) async fn get_object(namespace: &str, key: &[u8]) -> Result<Vec<u8>, PapError> {
-    sqlx::query_scalar::<_, Vec<u8>>("SELECT value FROM objects WHERE namespace = ? AND key = ?")
+    let (value, compressed, checksum) = sqlx::query_as::<_, (Vec<u8>, bool, Option<Vec<u8>>)>(
+        "SELECT value, compressed, checksum FROM objects WHERE namespace = ? AND key = ?",
+    )
+    .bind(namespace)
+    .bind(key)
+    .fetch_optional(&with_pool()?)
+    .await?
+    .ok_or_else(|| {
+        PapError::NotFound(format!(
+            "Object in namespace {} with key {:?}",
+            namespace, key
+        ))
+    })?;
+
+    let value = compression::decode(value, compressed)?;
+    if !integrity::is_intact(&value, checksum.as_deref()) {
+        return Err(PapError::Database(format!(
+            "checksum mismatch for object in namespace {} with key {:?}",
+            namespace, key
+        )));
+    }
+    Ok(value)
+}
+
+pub(crate) async fn object_exists(namespace: &str, key: &[u8]) -> Result<bool> {
+    let found = sqlx::query("SELECT 1 FROM objects WHERE namespace = ? AND key = ? LIMIT 1")
         .bind(namespace)
         .bind(key)
         .fetch_optional(&with_pool()?)
-        .await?
-        .ok_or_else(|| {
-            PapError::NotFound(format!(
-                "Object in namespace {} with key {:?}",
-                namespace, key
-            ))
-        })
+        .await?;
+    Ok(found.is_some())
 }
 
-pub(crate) async fn put_object(namespace: &str, key: &[u8], value: &[u8]) -> Result<()> {
-    sqlx::query("INSERT OR REPLACE INTO objects (namespace, key, value, created_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)")
-            .bind(namespace)
-            .bind(key)
-            .bind(value)
-            .execute(&with_pool()?)
+/// Lists the keys of every object in a namespace, in key order
+pub(crate) async fn get_object_keys(namespace: &str) -> Result<Vec<Vec<u8>>> {
+    let keys = sqlx::query_scalar("SELECT key FROM objects WHERE namespace = ? ORDER BY key")
+        .bind(namespace)
+        .fetch_all(&with_pool()?)
+        .await?;
+    Ok(keys)
+}
+
+pub(crate) async fn get_namespaces() -> Result<Vec<String>> {
+    let namespaces =
+        sqlx::query_scalar("SELECT DISTINCT namespace FROM objects ORDER BY namespace")
+            .fetch_all(&with_pool()?)
+            .await?;
+    Ok(namespaces)
+}
+
+/// Default for the largest object value that may be stored, in bytes
+pub const DEFAULT_MAX_OBJECT_SIZE: usize = 256 * 1024 * 1024;
+
+static MAX_OBJECT_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_OBJECT_SIZE);
+
+/// Sets the largest object value that may be stored, in bytes, so that a
+/// runaway step can't fill the disk
+pub fn set_max_object_size(bytes: usize) {
+    MAX_OBJECT_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+static EXCLUSIVE_NAMESPACES: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether a step may only write to a namespace that no other active
+/// pipeline has written to. Off by default, in which case namespaces are
+/// shared and the last write to a key wins.
+pub fn set_exclusive_namespaces(enabled: bool) {
+    EXCLUSIVE_NAMESPACES.store(enabled, Ordering::Relaxed);
+}
+
+/// Rejects object values larger than the configured maximum
+pub(crate) fn check_object_size(value: &[u8]) -> Result<(), PapError> {
+    let max = MAX_OBJECT_SIZE.load(Ordering::Relaxed);
+    if value.len() > max {
+        return Err(PapError::Configuration(format!(
+            "object exceeds max size: {} bytes > {} bytes",
+            value.len(),
+            max
+        )));
+    }
+    Ok(())
+}
+
+/// Stores an object, recording the step that wrote it if there is one
+pub(crate) async fn put_object(
+    namespace: &str,
+    key: &[u8],
+    value: &[u8],
+    step_id: Option<u32>,
+) -> Result<()> {
+    check_object_size(value)?;
+
+    let db = with_pool()?;
+    let mut tx = db.begin().await?;
+    insert_object(&mut tx, namespace, key, value, step_id).await?;
+    if let Some(step_id) = step_id {
+        check_namespace_owner(&mut tx, namespace, step_id).await?;
+        check_quota(&mut tx, step_id).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub(crate) async fn put_objects_batch(
+    namespace: &str,
+    items: &[(Vec<u8>, Vec<u8>)],
+    step_id: Option<u32>,
+) -> Result<()> {
+    for (_, value) in items {
+        check_object_size(value)?;
+    }
+
+    let db = with_pool()?;
+    let mut tx = db.begin().await?;
+
+    for (key, value) in items {
+        insert_object(&mut tx, namespace, key, value, step_id).await?;
+    }
+    if let Some(step_id) = step_id {
+        check_namespace_owner(&mut tx, namespace, step_id).await?;
+        check_quota(&mut tx, step_id).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Stores an object, attributing it to the step and its pipeline. Overwriting
+/// a key makes the writer its owner: its pipeline's quota is charged for it,
+/// and cleaning up after the writer, such as deleting its pipeline or a
+/// failed step's objects, removes it.
+///
+/// With exclusive namespaces on, a key whose owner is another pipeline that
+/// is still pending or running is not overwritten, since taking it over could
+/// leave that pipeline without a claim on the namespace.
+///
+/// Writing first means the transaction takes the write lock up front, so
+/// concurrent writers to the same key queue behind each other and the last one
+/// wins. Checks that read, such as the exclusive namespace check, must come
+/// after this, or a transaction would need to upgrade its read lock and could
+/// fail as busy instead of waiting.
+async fn insert_object(
+    tx: &mut Transaction<'_, Sqlite>,
+    namespace: &str,
+    key: &[u8],
+    value: &[u8],
+    step_id: Option<u32>,
+) -> Result<()> {
+    let checksum = integrity::checksum(value);
+    let (value, compressed) = compression::encode(value)?;
+    let exclusive = step_id.is_some() && EXCLUSIVE_NAMESPACES.load(Ordering::Relaxed);
+    let result = sqlx::query(
+        r#"
+        INSERT INTO objects
+            (namespace, key, value, created_at, step_id, compressed, pipeline_id, checksum)
+        VALUES (?, ?, ?, CURRENT_TIMESTAMP, ?, ?, (SELECT pipeline_id FROM steps WHERE id = ?), ?)
+        ON CONFLICT (namespace, key) DO UPDATE SET
+            value = excluded.value,
+            created_at = excluded.created_at,
+            compressed = excluded.compressed,
+            checksum = excluded.checksum,
+            step_id = excluded.step_id,
+            pipeline_id = excluded.pipeline_id
+        WHERE NOT ?
+            OR objects.pipeline_id IS excluded.pipeline_id
+            OR NOT EXISTS (
+                SELECT 1 FROM pipelines p
+                WHERE p.id = objects.pipeline_id AND p.execution_status IN (?, ?)
+            )
+        "#,
+    )
+    .bind(namespace)
+    .bind(key)
+    .bind(value.as_ref())
+    .bind(step_id)
+    .bind(compressed)
+    .bind(step_id)
+    .bind(checksum)
+    .bind(exclusive)
+    .bind(ExecutionStatus::Pending.to_string())
+    .bind(ExecutionStatus::Running.to_string())
+    .execute(&mut **tx)
+    .await?;
+
+    // The update was skipped, as the key belongs to another active pipeline
+    if result.rows_affected() == 0 {
+        let owner = sqlx::query_scalar::<_, Option<u32>>(
+            "SELECT pipeline_id FROM objects WHERE namespace = ? AND key = ?",
+        )
+        .bind(namespace)
+        .bind(key)
+        .fetch_one(&mut **tx)
+        .await?;
+        return Err(PapError::Configuration(format!(
+            "namespace '{}' is in use by active pipeline {}",
+            namespace,
+            owner.map_or_else(|| "unknown".to_string(), |id| id.to_string())
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Fails with `PapError::Configuration` if exclusive namespaces are on and
+/// `namespace` holds objects from a pipeline other than the step's that is
+/// still pending or running
+async fn check_namespace_owner(
+    tx: &mut Transaction<'_, Sqlite>,
+    namespace: &str,
+    step_id: u32,
+) -> Result<()> {
+    if !EXCLUSIVE_NAMESPACES.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let owner = sqlx::query_scalar::<_, u32>(
+        r#"
+        SELECT p.id
+        FROM objects o
+        JOIN pipelines p ON o.pipeline_id = p.id
+        WHERE o.namespace = ?
+          AND p.id != (SELECT pipeline_id FROM steps WHERE id = ?)
+          AND p.execution_status IN (?, ?)
+        LIMIT 1
+        "#,
+    )
+    .bind(namespace)
+    .bind(step_id)
+    .bind(ExecutionStatus::Pending.to_string())
+    .bind(ExecutionStatus::Running.to_string())
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(owner) = owner {
+        return Err(PapError::Configuration(format!(
+            "namespace '{}' is in use by active pipeline {}",
+            namespace, owner
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Fails with `PapError::QuotaExceeded` if the objects stored by the step's
+/// pipeline, including any just written in `tx`, exceed the pipeline's quota.
+/// Keys the step overwrote count as its pipeline's, whoever wrote them before.
+async fn check_quota(tx: &mut Transaction<'_, Sqlite>, step_id: u32) -> Result<()> {
+    // Pipelines without a quota, the usual case, are found by the index alone
+    let usage = sqlx::query_as::<_, (u32, i64, i64)>(
+        r#"
+        SELECT p.id, p.object_quota, p.object_bytes
+        FROM pipelines p
+        JOIN steps s ON s.pipeline_id = p.id
+        WHERE s.id = ? AND p.object_quota IS NOT NULL
+        "#,
+    )
+    .bind(step_id)
+    .fetch_optional(&mut **tx)
     .await?;
+
+    if let Some((pipeline_id, quota, used)) = usage {
+        if used > quota {
+            return Err(PapError::QuotaExceeded(format!(
+                "pipeline {} would store {} bytes of objects, over its quota of {} bytes",
+                pipeline_id, used, quota
+            ))
+            .into());
+        }
+    }
     Ok(())
 }
 
-pub(crate) async fn setup_pipeline(context: &pap_api::Context) -> anyhow::Result<PipelineStatus> {
+/// Deletes every object written by a step
+pub(crate) async fn delete_step_objects(step_id: u32) -> Result<()> {
+    sqlx::query("DELETE FROM objects WHERE step_id = ?")
+        .bind(step_id)
+        .execute(&with_pool()?)
+        .await?;
+    Ok(())
+}
+
+/// Namespace an IO field of a step defaults to when the config leaves it
+/// unset, so that steps don't share objects unless asked to
+pub(crate) fn default_io_namespace(
+    pipeline_id: u32,
+    job_id: u32,
+    step_id: u32,
+    io: &str,
+) -> String {
+    format!(
+        "pipeline-{}/job-{}/step-{}/{}",
+        pipeline_id, job_id, step_id, io
+    )
+}
+
+/// Stores a new pipeline without any default IO namespaces
+#[cfg(test)]
+pub(crate) async fn setup_pipeline(
+    context: &pap_api::Context,
+    dry_run: bool,
+) -> anyhow::Result<PipelineStatus> {
+    setup_pipeline_with_io(context, dry_run, |_| Vec::new()).await
+}
+
+/// Stores a new pipeline, giving each IO field named by `default_io` that a
+/// step leaves unset a namespace of the step's own
+pub(crate) async fn setup_pipeline_with_io(
+    context: &pap_api::Context,
+    dry_run: bool,
+    default_io: impl Fn(&Step) -> Vec<String>,
+) -> anyhow::Result<PipelineStatus> {
     let db = with_pool()?;
     let mut tx = db.begin().await?;
 
-    let pipeline_id = sqlx::query_scalar::<_, u32>(
-        "INSERT INTO pipelines (config, context) VALUES (?, ?) RETURNING id",
+    let (pipeline_id, created_at) = sqlx::query_as::<_, (u32, Option<String>)>(
+        "INSERT INTO pipelines (config, context, created_at, dry_run, object_quota) VALUES (?, ?, CURRENT_TIMESTAMP, ?, ?) RETURNING id, created_at",
     )
     .bind(serde_json::to_string(&context.config)?)
     .bind(serde_json::to_vec(&context)?)
+    .bind(dry_run)
+    .bind(context.config.object_quota.map(|quota| quota as i64))
     .fetch_one(&mut *tx)
     .await?;
 
+    for (key, value) in &context.config.labels {
+        sqlx::query("INSERT INTO pipeline_labels (pipeline_id, key, value) VALUES (?, ?, ?)")
+            .bind(pipeline_id)
+            .bind(key)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+    }
+
     let mut job_ids = Vec::new();
     for job in &context.config.jobs {
         let job_id = sqlx::query_scalar::<_, u32>(
@@ -323,8 +1138,8 @@ pub(crate) async fn setup_pipeline(context: &pap_api::Context) -> anyhow::Result
         job_ids.push(job_id);
 
         for step in &job.steps {
-            sqlx::query_scalar::<_, u32>(
-                    "INSERT INTO steps (job_id, pipeline_id, name, call, args, io) VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+            let step_id = sqlx::query_scalar::<_, u32>(
+                    "INSERT INTO steps (job_id, pipeline_id, name, call, args, io, cleanup_on_failure, env) VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
                 )
                 .bind(job_id)
                 .bind(pipeline_id)
@@ -332,41 +1147,274 @@ pub(crate) async fn setup_pipeline(context: &pap_api::Context) -> anyhow::Result
                 .bind(&step.call)
                 .bind(serde_json::to_string(&step.args)?)
                 .bind(serde_json::to_string(&step.io)?)
+                .bind(step.cleanup_on_failure)
+                .bind(serde_json::to_string(&step.env)?)
                 .fetch_one(&mut *tx)
                 .await?;
+
+            // The defaults need the step's ID, so they're filled in after
+            let mut io = step.io.clone();
+            for name in default_io(step) {
+                let namespace = default_io_namespace(pipeline_id, job_id, step_id, &name);
+                io.entry(name).or_insert(namespace);
+            }
+            if io != step.io {
+                sqlx::query("UPDATE steps SET io = ? WHERE id = ?")
+                    .bind(serde_json::to_string(&io)?)
+                    .bind(step_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
         }
     }
 
     tx.commit().await?;
 
+    let total_steps = context
+        .config
+        .jobs
+        .iter()
+        .map(|job| job.steps.len() as u32)
+        .sum();
     Ok(PipelineStatus {
         id: pipeline_id,
         config: context.config.clone(),
         jobs: job_ids,
         status: ExecutionStatus::Running,
         error: None,
+        failed_job: None,
+        failed_step: None,
+        created_at,
+        dry_run,
+        completed_steps: 0,
+        total_steps,
+        progress: 0.0,
     })
 }
 
-pub(crate) async fn cancel_pipeline(id: u32) -> Result<()> {
+/// Runs a trivial query to check that the database is reachable
+pub(crate) async fn ping() -> Result<()> {
+    sqlx::query("SELECT 1").execute(&with_pool()?).await?;
+    Ok(())
+}
+
+pub(crate) async fn count_active_pipelines() -> Result<u32> {
+    let count =
+        sqlx::query_scalar("SELECT COUNT(*) FROM pipelines WHERE execution_status IN (?, ?)")
+            .bind(ExecutionStatus::Pending.to_string())
+            .bind(ExecutionStatus::Running.to_string())
+            .fetch_one(&with_pool()?)
+            .await?;
+    Ok(count)
+}
+
+/// Counts pipelines, optionally only those with the given status
+pub(crate) async fn count_pipelines(status: Option<ExecutionStatus>) -> Result<u64> {
+    let count: i64 = match status {
+        Some(status) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM pipelines WHERE execution_status = ?")
+                .bind(status.to_string())
+                .fetch_one(&with_pool()?)
+                .await?
+        }
+        None => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM pipelines")
+                .fetch_one(&with_pool()?)
+                .await?
+        }
+    };
+    Ok(count as u64)
+}
+
+/// Counts jobs, optionally only those with the given status
+pub(crate) async fn count_jobs(status: Option<ExecutionStatus>) -> Result<u64> {
+    let count: i64 = match status {
+        Some(status) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM jobs WHERE status = ?")
+                .bind(status.to_string())
+                .fetch_one(&with_pool()?)
+                .await?
+        }
+        None => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+                .fetch_one(&with_pool()?)
+                .await?
+        }
+    };
+    Ok(count as u64)
+}
+
+pub(crate) async fn get_pipeline_ids() -> Result<Vec<u32>> {
+    // Newest first; ids break ties between pipelines submitted in the same second
+    let ids = sqlx::query_scalar("SELECT id FROM pipelines ORDER BY created_at DESC, id DESC")
+        .fetch_all(&with_pool()?)
+        .await?;
+    Ok(ids)
+}
+
+pub(crate) async fn get_pipeline_ids_by_label(key: &str, value: &str) -> Result<Vec<u32>> {
+    let ids = sqlx::query_scalar(
+        r#"
+        SELECT p.id
+        FROM pipelines p
+        JOIN pipeline_labels l ON p.id = l.pipeline_id
+        WHERE l.key = ? AND l.value = ?
+        ORDER BY p.created_at DESC, p.id DESC
+        "#,
+    )
+    .bind(key)
+    .bind(value)
+    .fetch_all(&with_pool()?)
+    .await?;
+    Ok(ids)
+}
+
+pub(crate) async fn get_pipeline_job_ids(pipeline_id: u32) -> Result<Vec<u32>> {
+    let ids = sqlx::query_scalar("SELECT id FROM jobs WHERE pipeline_id = ? ORDER BY id")
+        .bind(pipeline_id)
+        .fetch_all(&with_pool()?)
+        .await?;
+    Ok(ids)
+}
+
+pub(crate) async fn get_pipeline_history(pipeline_id: u32) -> Result<Vec<StatusChange>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, entity_type, entity_id, old_status, new_status, timestamp
+        FROM status_history
+        WHERE pipeline_id = ?
+        ORDER BY id
+        "#,
+    )
+    .bind(pipeline_id)
+    .fetch_all(&with_pool()?)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let id: u32 = row.get(0);
+            let entity_type: String = row.get(1);
+            let entity_type = StatusEntity::from_str(&entity_type).map_err(|_| {
+                PapError::Database(format!(
+                    "invalid entity type '{}' for status change {}",
+                    entity_type, id
+                ))
+            })?;
+            Ok(StatusChange {
+                entity_type,
+                entity_id: row.get(2),
+                old_status: parse_status(row.get(3), "status change", id)?,
+                new_status: parse_status(row.get(4), "status change", id)?,
+                timestamp: row.get(5),
+            })
+        })
+        .collect()
+}
+
+/// Cancels a pipeline with all of its unfinished jobs and steps, returning
+/// how many unfinished pipelines matched `id`. Finished jobs and steps keep
+/// their status, so that resuming the pipeline doesn't run completed steps
+/// again.
+pub(crate) async fn cancel_pipeline(id: u32) -> Result<u64> {
     let db = with_pool()?;
     let mut tx = db.begin().await?;
 
-    sqlx::query("UPDATE pipelines SET execution_status = ? WHERE id = ?")
+    let result = sqlx::query(
+        "UPDATE pipelines SET execution_status = ? WHERE id = ? AND execution_status IN (?, ?)",
+    )
+    .bind(ExecutionStatus::Cancelled.to_string())
+    .bind(id)
+    .bind(ExecutionStatus::Pending.to_string())
+    .bind(ExecutionStatus::Running.to_string())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(0);
+    }
+
+    for table in ["jobs", "steps"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET status = ? WHERE pipeline_id = ? AND status IN (?, ?)",
+            table
+        ))
         .bind(ExecutionStatus::Cancelled.to_string())
         .bind(id)
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(ExecutionStatus::Running.to_string())
         .execute(&mut *tx)
         .await?;
+    }
 
-    sqlx::query("UPDATE jobs SET status = ? WHERE pipeline_id = ?")
-        .bind(ExecutionStatus::Cancelled.to_string())
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Cancels every pipeline that hasn't finished, along with its unfinished
+/// jobs and steps, returning the IDs of the pipelines cancelled
+pub(crate) async fn cancel_all_pipelines() -> Result<Vec<u32>> {
+    let db = with_pool()?;
+    let mut tx = db.begin().await?;
+
+    // Jobs and steps go first, while their pipelines can still be told apart
+    for table in ["jobs", "steps"] {
+        let query = format!(
+            r#"
+            UPDATE {} SET status = ?
+            WHERE status IN (?, ?)
+              AND pipeline_id IN (SELECT id FROM pipelines WHERE execution_status IN (?, ?))
+            "#,
+            table
+        );
+        sqlx::query(&query)
+            .bind(ExecutionStatus::Cancelled.to_string())
+            .bind(ExecutionStatus::Pending.to_string())
+            .bind(ExecutionStatus::Running.to_string())
+            .bind(ExecutionStatus::Pending.to_string())
+            .bind(ExecutionStatus::Running.to_string())
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let ids = sqlx::query_scalar(
+        "UPDATE pipelines SET execution_status = ? WHERE execution_status IN (?, ?) RETURNING id",
+    )
+    .bind(ExecutionStatus::Cancelled.to_string())
+    .bind(ExecutionStatus::Pending.to_string())
+    .bind(ExecutionStatus::Running.to_string())
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(ids)
+}
+
+/// Resets every job and step of a pipeline that hasn't completed to pending,
+/// and forgets why the pipeline stopped, so that it can run again
+pub(crate) async fn reset_for_resume(id: u32) -> Result<()> {
+    let db = with_pool()?;
+    let mut tx = db.begin().await?;
+
+    sqlx::query("UPDATE pipelines SET execution_status = ? WHERE id = ?")
+        .bind(ExecutionStatus::Pending.to_string())
         .bind(id)
         .execute(&mut *tx)
         .await?;
 
-    // Also cancel steps directly by pipeline_id
-    sqlx::query("UPDATE steps SET status = ? WHERE pipeline_id = ?")
-        .bind(ExecutionStatus::Cancelled.to_string())
+    sqlx::query("UPDATE jobs SET status = ? WHERE pipeline_id = ? AND status != ?")
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(id)
+        .bind(ExecutionStatus::Completed.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE steps SET status = ? WHERE pipeline_id = ? AND status != ?")
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(id)
+        .bind(ExecutionStatus::Completed.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM global_errors WHERE pipeline_id = ?")
         .bind(id)
         .execute(&mut *tx)
         .await?;
@@ -375,10 +1423,29 @@ pub(crate) async fn cancel_pipeline(id: u32) -> Result<()> {
     Ok(())
 }
 
-pub(crate) async fn delete_pipeline(id: u32) -> Result<()> {
+/// Deletes a pipeline and everything it stored, returning how many pipelines
+/// matched `id`
+pub(crate) async fn delete_pipeline(id: u32) -> Result<u64> {
     let db = with_pool()?;
     let mut tx = db.begin().await?;
 
+    // Errors refer to the pipeline, its jobs, and its steps, so they go first
+    sqlx::query("DELETE FROM global_errors WHERE pipeline_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM status_history WHERE pipeline_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Delete objects written by this pipeline's steps
+    sqlx::query("DELETE FROM objects WHERE pipeline_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
     // Delete steps belonging to jobs in this pipeline
     sqlx::query(r#"DELETE FROM steps WHERE job_id IN (SELECT id FROM jobs WHERE pipeline_id = ?)"#)
         .bind(id)
@@ -391,38 +1458,101 @@ pub(crate) async fn delete_pipeline(id: u32) -> Result<()> {
         .execute(&mut *tx)
         .await?;
 
+    // Delete labels attached to this pipeline
+    sqlx::query("DELETE FROM pipeline_labels WHERE pipeline_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
     // Delete the pipeline itself
-    sqlx::query("DELETE FROM pipelines WHERE id = ?")
+    let result = sqlx::query("DELETE FROM pipelines WHERE id = ?")
         .bind(id)
         .execute(&mut *tx)
         .await?;
 
     tx.commit().await?;
-    Ok(())
+    Ok(result.rows_affected())
 }
 
-pub(crate) async fn cancel_job(id: u32) -> Result<()> {
+/// Cancels a job and its unfinished steps, returning how many unfinished jobs
+/// matched `id`
+pub(crate) async fn cancel_job(id: u32) -> Result<u64> {
     let db = with_pool()?;
     let mut tx = db.begin().await?;
 
-    // Cancel all steps belonging to this job
-    sqlx::query("UPDATE steps SET status = ? WHERE pipeline_id = ?")
+    // Cancel the job itself
+    let result = sqlx::query("UPDATE jobs SET status = ? WHERE id = ? AND status IN (?, ?)")
         .bind(ExecutionStatus::Cancelled.to_string())
         .bind(id)
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(ExecutionStatus::Running.to_string())
         .execute(&mut *tx)
         .await?;
+    if result.rows_affected() == 0 {
+        return Ok(0);
+    }
 
-    // Cancel the job itself
-    sqlx::query("UPDATE jobs SET status = ? WHERE pipeline_id = ?")
+    // Completed steps stay completed, so that a resume doesn't rerun them
+    sqlx::query("UPDATE steps SET status = ? WHERE job_id = ? AND status IN (?, ?)")
         .bind(ExecutionStatus::Cancelled.to_string())
         .bind(id)
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(ExecutionStatus::Running.to_string())
         .execute(&mut *tx)
         .await?;
 
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Cancels every job and step of a pipeline that hasn't started, so that none
+/// are left pending once the pipeline stops early
+pub(crate) async fn cancel_pending(pipeline_id: u32) -> Result<()> {
+    let db = with_pool()?;
+    let mut tx = db.begin().await?;
+
+    for table in ["jobs", "steps"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET status = ? WHERE pipeline_id = ? AND status = ?",
+            table
+        ))
+        .bind(ExecutionStatus::Cancelled.to_string())
+        .bind(pipeline_id)
+        .bind(ExecutionStatus::Pending.to_string())
+        .execute(&mut *tx)
+        .await?;
+    }
+
     tx.commit().await?;
     Ok(())
 }
 
+/// Marks a step as running if it is pending, returning whether it was
+pub(crate) async fn start_step(id: u32) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE steps SET status = ?, started_at = strftime('%Y-%m-%d %H:%M:%f', 'now'), finished_at = NULL WHERE id = ? AND status = ?",
+    )
+    .bind(ExecutionStatus::Running.to_string())
+    .bind(id)
+    .bind(ExecutionStatus::Pending.to_string())
+    .execute(&with_pool()?)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Cancels a single step if it hasn't finished, returning whether it was
+/// cancelled. The rest of its job is unaffected.
+pub(crate) async fn cancel_step(id: u32) -> Result<bool> {
+    let result = sqlx::query("UPDATE steps SET status = ? WHERE id = ? AND status IN (?, ?)")
+        .bind(ExecutionStatus::Cancelled.to_string())
+        .bind(id)
+        .bind(ExecutionStatus::Pending.to_string())
+        .bind(ExecutionStatus::Running.to_string())
+        .execute(&with_pool()?)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
 pub(crate) async fn is_step_cancelled(step_id: u32) -> Result<bool> {
     // Check step status
     let step_status: String = sqlx::query_scalar("SELECT status FROM steps WHERE id = ?")
@@ -430,29 +1560,29 @@ pub(crate) async fn is_step_cancelled(step_id: u32) -> Result<bool> {
         .fetch_one(&with_pool()?)
         .await?;
 
-    if ExecutionStatus::from_str(&step_status)? == ExecutionStatus::Cancelled {
+    if parse_status(&step_status, "step", step_id)? == ExecutionStatus::Cancelled {
         return Ok(true);
     }
 
     // Check job status
-    let job_status: String = sqlx::query_scalar(
-        "SELECT j.status FROM jobs j JOIN steps s ON j.id = s.job_id WHERE s.id = ?"
+    let (job_id, job_status): (u32, String) = sqlx::query_as(
+        "SELECT j.id, j.status FROM jobs j JOIN steps s ON j.id = s.job_id WHERE s.id = ?"
     )
     .bind(step_id)
     .fetch_one(&with_pool()?)
     .await?;
 
-    if ExecutionStatus::from_str(&job_status)? == ExecutionStatus::Cancelled {
+    if parse_status(&job_status, "job", job_id)? == ExecutionStatus::Cancelled {
         return Ok(true);
     }
 
     // Check pipeline status
-    let pipeline_status: String = sqlx::query_scalar(
-        "SELECT p.execution_status FROM pipelines p JOIN steps s ON p.id = s.pipeline_id WHERE s.id = ?"
+    let (pipeline_id, pipeline_status): (u32, String) = sqlx::query_as(
+        "SELECT p.id, p.execution_status FROM pipelines p JOIN steps s ON p.id = s.pipeline_id WHERE s.id = ?"
     )
     .bind(step_id)
     .fetch_one(&with_pool()?)
     .await?;
 
-    Ok(ExecutionStatus::from_str(&pipeline_status)? == ExecutionStatus::Cancelled)
+    Ok(parse_status(&pipeline_status, "pipeline", pipeline_id)? == ExecutionStatus::Cancelled)
 }
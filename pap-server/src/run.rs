@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use pap_api::{Config, Context, EventRecord, PipelineEvent, PipelineStatus, StepStatus};
+use sqlx::SqlitePool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+use crate::queries;
+use crate::server::PipelineServer;
+use crate::step::builtin_executors;
+
+/// A pipeline running in-process, started by [`spawn_pipeline`]
+pub struct PipelineRun {
+    /// The pipeline's ID in the database
+    pub id: u32,
+    events: broadcast::Receiver<EventRecord>,
+    finished: bool,
+    handle: JoinHandle<()>,
+}
+
+impl PipelineRun {
+    /// Waits for the pipeline's next lifecycle event, returning `None` once
+    /// the pipeline has finished. Events are dropped if they aren't taken
+    /// quickly enough to keep up.
+    pub async fn next_event(&mut self) -> Option<PipelineEvent> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            match self.events.recv().await {
+                Ok(record) => {
+                    self.finished = matches!(record.event, PipelineEvent::PipelineFinished { .. });
+                    return Some(record.event);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Gets the status of one of the pipeline's steps, including its output
+    /// once it has finished
+    pub async fn step(&self, step_id: u32) -> Result<StepStatus> {
+        queries::get_step_status(step_id).await
+    }
+
+    /// Waits for the pipeline to finish, returning its final status
+    pub async fn wait(self) -> Result<PipelineStatus> {
+        self.handle.await?;
+        queries::get_pipeline_status(self.id).await
+    }
+}
+
+/// Starts running a pipeline in-process with the builtin step executors,
+/// storing its state in `pool`. Files the config refers to are read relative
+/// to `base_path`.
+///
+/// The server state is global, so only one database can be used at a time.
+pub async fn spawn_pipeline(
+    config: Config,
+    base_path: PathBuf,
+    pool: SqlitePool,
+) -> Result<PipelineRun> {
+    let context = Context::build_with_config(config, base_path)?;
+    let server = PipelineServer::new(pool, builtin_executors()).await?;
+    server.validate(&context)?;
+
+    let status = server.setup_pipeline(&context, false).await?;
+    // Subscribe before starting so that no event is missed
+    let events = server.subscribe();
+    let pipeline = status.clone();
+    let handle = tokio::spawn(async move {
+        server.execute_blocking(&pipeline).await;
+    });
+
+    Ok(PipelineRun {
+        id: status.id,
+        events,
+        finished: false,
+        handle,
+    })
+}
+
+/// Runs a pipeline in-process to completion, returning its final status. See
+/// [`spawn_pipeline`] to follow its progress instead.
+pub async fn run_pipeline(
+    config: Config,
+    base_path: PathBuf,
+    pool: SqlitePool,
+) -> Result<PipelineStatus> {
+    spawn_pipeline(config, base_path, pool).await?.wait().await
+}
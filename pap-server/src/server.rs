@@ -1,22 +1,121 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::task;
-use tokio::{sync::Mutex, task::JoinHandle};
+use tokio::{
+    sync::{broadcast, Mutex},
+    task::JoinHandle,
+    time::Instant,
+};
 
 use anyhow::{bail, Result};
-use pap_api::{ExecutionStatus, JobStatus, PapApi, PapError, PipelineStatus, StepStatus};
+use futures::{future, Stream, StreamExt};
+use pap_api::{
+    EventRecord, ExecutionStatus, ExecutorInfo, HealthStatus, JobStatus, PapApi, PapApiRequest,
+    PapApiResponse, PapError, PipelineEvent, PipelineStatus, ServerAddr, StatusChange, StepOutput,
+    StepStatus, SubmitResult,
+};
 use sqlx::{Pool, Sqlite};
 use tarpc::context::Context;
+use tarpc::server::{BaseChannel, Channel};
+use tarpc::tokio_serde::formats::Json;
 
 use crate::db::{init_pool, with_pool};
-use crate::{queries, step::StepContext, step::StepExecutorRegistry};
+use crate::events::EventBus;
+use crate::queries;
+use crate::step::{
+    parse_list, parse_step_reference, run_pinned, LogLevel, StepContext, StepExecutor,
+    StepExecutorRegistry,
+};
+
+/// How many client connections are served at once unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+
+/// Serves each of `channels` with `serve`, at most `limit` at once. Further
+/// channels wait until one of those closes.
+///
+/// Only the channels count against the limit. Requests on a channel are
+/// handled as they arrive, and pipelines they submit run in the background
+/// after the request returns, so the limit doesn't bound how many pipelines
+/// run at once.
+pub async fn serve_channels<S, F>(channels: S, limit: usize, serve: impl FnMut(S::Item) -> F)
+where
+    S: Stream,
+    F: Future<Output = ()>,
+{
+    channels
+        .map(serve)
+        .buffer_unordered(limit)
+        .for_each(|_| async {})
+        .await
+}
+
+/// Serves the tarpc service on `addr` until the process exits, with at most
+/// `limit` client connections at once. A unix socket left behind by an earlier
+/// server is replaced, but one a server is still listening on is not.
+pub async fn serve(addr: &ServerAddr, server: PipelineServer, limit: usize) -> Result<()> {
+    match addr {
+        ServerAddr::Tcp(tcp_addr) => {
+            let tcp_addr: std::net::SocketAddr = tcp_addr.parse()?;
+            let listener = tarpc::serde_transport::tcp::listen(tcp_addr, Json::default).await?;
+            log::info!("Server listening on {}", addr);
+            serve_transports(listener, server, limit).await;
+        }
+        ServerAddr::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                if tokio::net::UnixStream::connect(path).await.is_ok() {
+                    bail!("another server is listening on {}", path.display());
+                }
+                std::fs::remove_file(path)?;
+            }
+            let listener = tarpc::serde_transport::unix::listen(path, Json::default).await?;
+            log::info!("Server listening on {}", addr);
+            serve_transports(listener, server, limit).await;
+        }
+    }
+    Ok(())
+}
+
+/// Serves a channel over each transport `listener` accepts, skipping those
+/// that fail to connect
+async fn serve_transports<L, T>(listener: L, server: PipelineServer, limit: usize)
+where
+    L: Stream<Item = std::io::Result<T>>,
+    T: tarpc::Transport<tarpc::Response<PapApiResponse>, tarpc::ClientMessage<PapApiRequest>>
+        + Send
+        + 'static,
+{
+    let channels = listener
+        .filter_map(|r| future::ready(r.ok()))
+        .map(BaseChannel::with_defaults);
+    serve_channels(channels, limit, |channel| {
+        channel.execute(server.clone().serve()).for_each(|x| async {
+            tokio::spawn(x);
+        })
+    })
+    .await;
+}
 
 #[derive(Clone)]
 pub struct PipelineServer {
     registry: Arc<StepExecutorRegistry>,
     handles: Arc<Mutex<HashMap<u32, JoinHandle<()>>>>,
+    events: Arc<EventBus>,
 }
 
+/// How long an `events` call waits for a new event, well within the default
+/// tarpc request deadline
+const EVENT_POLL_TIMEOUT: Duration = Duration::from_secs(5);
+
 impl PipelineServer {
+    /// Creates a server that runs steps with the executors in `registry`,
+    /// usually [`StepExecutorRegistry::with_builtins`] with any custom
+    /// executors added. The database in `pool` becomes the one every query
+    /// uses.
     pub async fn new(pool: Pool<Sqlite>, registry: StepExecutorRegistry) -> Result<Self> {
         // Initialize the thread-local pool
         init_pool(pool)?;
@@ -27,62 +126,105 @@ impl PipelineServer {
         Ok(Self {
             registry: Arc::new(registry),
             handles: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            events: Arc::new(EventBus::new()),
         })
     }
 
     pub fn validate(&self, context: &pap_api::Context) -> Result<()> {
+        // An empty pipeline completes at once, which is more likely a mistake
+        // in the config than the intent
+        if !context.config.allow_empty {
+            if context.config.jobs.is_empty() {
+                bail!("pipeline has no jobs; set allow_empty to run it anyway");
+            }
+            if let Some(job) = context.config.jobs.iter().find(|job| job.steps.is_empty()) {
+                bail!(
+                    "job {} has no steps; set allow_empty to run it anyway",
+                    job.name
+                );
+            }
+        }
+
+        // Steps may only reference the output of steps that run before them
+        let mut earlier_steps = HashSet::new();
         for job in &context.config.jobs {
             for step in &job.steps {
-                if self.registry.get(&step.call).is_none() {
-                    bail!("step executor not found: {}", step.call);
+                let executor = match self.registry.get(&step.call) {
+                    Some(executor) => executor,
+                    None => bail!("step executor not found: {}", step.call),
+                };
+                executor.requirements().validate(step, &context.config)?;
+                executor.validate(step, context)?;
+                if let Some(level) = step.args.get("log_level") {
+                    // Only executors that declare the argument log by level
+                    if !executor
+                        .arg_schema()
+                        .iter()
+                        .any(|arg| arg.name == "log_level")
+                    {
+                        bail!(
+                            "step {}: {} does not take a log_level argument",
+                            step.name,
+                            step.call
+                        );
+                    }
+                    if let Err(e) = level.parse::<LogLevel>() {
+                        bail!("step {}: {}", step.name, e);
+                    }
                 }
+
+                for value in step.io.values() {
+                    if let Some(name) = parse_step_reference(value)? {
+                        if !earlier_steps.contains(name) {
+                            bail!(
+                                "step {} references a step that does not run before it: {}",
+                                step.name,
+                                name
+                            );
+                        }
+                    }
+                }
+                earlier_steps.insert(step.name.as_str());
             }
         }
         // TODO: ensure context has all expected fields
         Ok(())
     }
 
-    pub async fn setup_pipeline(&self, context: &pap_api::Context) -> Result<PipelineStatus> {
-        let pipeline_id = sqlx::query_scalar::<_, u32>(
-            "INSERT INTO pipelines (config, context) VALUES (?, ?) RETURNING id",
-        )
-        .bind(serde_json::to_string(&context.config)?)
-        .bind(serde_json::to_vec(&context)?)
-        .fetch_one(&with_pool()?)
-        .await?;
-
-        let mut job_ids = Vec::new();
-        for job in &context.config.jobs {
-            let job_id = sqlx::query_scalar::<_, u32>(
-                "INSERT INTO jobs (pipeline_id, name) VALUES (?, ?) RETURNING id",
-            )
-            .bind(pipeline_id)
-            .bind(serde_json::to_string(&job)?)
-            .fetch_one(&with_pool()?)
-            .await?;
-            job_ids.push(job_id);
-
-            for step in &job.steps {
-                sqlx::query_scalar::<_, u32>(
-                    "INSERT INTO steps (job_id, name, call, args, io) VALUES (?, ?, ?, ?, ?) RETURNING id",
-                )
-                .bind(job_id)
-                .bind(&step.name)
-                .bind(&step.call)
-                .bind(serde_json::to_string(&step.args)?)
-                .bind(serde_json::to_string(&step.io)?)  // Add IO configuration
-                .fetch_one(&with_pool()?)
-                .await?;
-            }
-        }
+    /// Finds things in a config that are likely mistakes but don't stop it
+    /// from running, for the submitter to look over
+    pub fn warnings(&self, context: &pap_api::Context) -> Vec<String> {
+        let config = &context.config;
+        // Any argument may name a project, including as part of a list such as
+        // `libraries`, so only projects no argument mentions are reported
+        let mentioned: HashSet<&str> = config
+            .jobs
+            .iter()
+            .flat_map(|job| &job.steps)
+            .flat_map(|step| step.args.values())
+            .flat_map(|value| parse_list(value))
+            .collect();
+
+        config
+            .projects
+            .iter()
+            .filter(|project| !mentioned.contains(project.name.as_str()))
+            .map(|project| format!("project {} is not used by any step", project.name))
+            .collect()
+    }
 
-        Ok(PipelineStatus {
-            id: pipeline_id,
-            config: context.config.clone(),
-            jobs: job_ids,
-            status: ExecutionStatus::Running,
-            error: None,
+    pub async fn setup_pipeline(
+        &self,
+        context: &pap_api::Context,
+        dry_run: bool,
+    ) -> Result<PipelineStatus> {
+        queries::setup_pipeline_with_io(context, dry_run, |step| {
+            self.registry
+                .get(&step.call)
+                .map(|executor| executor.requirements().default_io)
+                .unwrap_or_default()
         })
+        .await
     }
 
     async fn execute_step(&self, step: &StepStatus, pipeline: &PipelineStatus) -> Result<()> {
@@ -92,35 +234,71 @@ impl PipelineServer {
             .ok_or_else(|| anyhow::anyhow!("step executor not found: {}", step.config.call))?;
 
         // Get context data from database
-        let context: pap_api::Context =
-            sqlx::query_scalar::<_, Vec<u8>>("SELECT context FROM pipelines WHERE id = ?")
-                .bind(pipeline.id)
-                .fetch_one(&with_pool()?)
-                .await
-                .map(|data| serde_json::from_slice(&data))??;
-
-        let mut context = StepContext::new(step, pipeline, &context);
-
-        let result = task::block_in_place(|| executor.execute(&mut context));
+        let context = queries::get_pipeline_context(pipeline.id).await?;
+
+        let (result, output, stats) = if executor.pinned_to_thread() {
+            let registry = self.registry.clone();
+            let (step, pipeline) = (step.clone(), pipeline.clone());
+            run_pinned(move || {
+                let executor = registry
+                    .get(&step.config.call)
+                    .expect("executor was found before the step was sent");
+                run_step(executor, &step, &pipeline, &context)
+            })
+            .await?
+        } else {
+            task::block_in_place(|| run_step(executor, step, pipeline, &context))
+        };
 
         // Store the log regardless of execution result
-        queries::set_step_log(step.id, &context.get_log()).await?;
+        queries::set_step_output(step.id, &output).await?;
+        if let Some(stats) = stats {
+            queries::set_step_stats(step.id, &stats).await?;
+        }
 
         result
     }
 
     async fn execute(&self, pipeline: &PipelineStatus) -> Result<()> {
         queries::set_pipeline_status(pipeline.id, ExecutionStatus::Running).await?;
+        let pipeline_deadline = pipeline.config.timeout_secs.map(|secs| {
+            Deadline::after(
+                secs,
+                format!("pipeline {} ran for over {}s", pipeline.id, secs),
+            )
+        });
 
         for job_id in &pipeline.jobs {
             // Check if pipeline was cancelled
             let pipeline_status = queries::get_pipeline_status(pipeline.id).await?;
             if pipeline_status.status == ExecutionStatus::Cancelled {
-                return Ok(());
+                return Err(cancelled(pipeline.id));
             }
 
+            // Jobs that already finished, having completed before a resume or
+            // been cancelled on their own, are not run again
             let job_status = queries::get_job_status(*job_id).await?;
+            if job_status.status.is_finished() {
+                continue;
+            }
             queries::set_job_status(*job_id, ExecutionStatus::Running).await?;
+            self.events.publish(PipelineEvent::JobStarted {
+                pipeline_id: pipeline.id,
+                job_id: *job_id,
+            });
+
+            // The job must finish by whichever of its own and the pipeline's
+            // timeouts comes first
+            let job_deadline = job_status.config.timeout_secs.map(|secs| {
+                Deadline::after(
+                    secs,
+                    format!("job {} ran for over {}s", job_status.config.name, secs),
+                )
+            });
+            let deadline = [pipeline_deadline.clone(), job_deadline]
+                .into_iter()
+                .flatten()
+                .min_by_key(|deadline| deadline.at);
 
             for step in &job_status.steps {
                 // Check if job was cancelled
@@ -129,50 +307,309 @@ impl PipelineServer {
                     break;
                 }
 
-                queries::set_step_status(step.id, ExecutionStatus::Running).await?;
+                if let Some(deadline) = deadline.as_ref().filter(|d| d.passed()) {
+                    return Err(self.time_out(pipeline, *job_id, None, deadline).await?);
+                }
+
+                // Only pending steps start, so steps that completed before a
+                // resume or were cancelled on their own stay as they are
+                if !queries::start_step(step.id).await? {
+                    continue;
+                }
+                self.events.publish(PipelineEvent::StepStarted {
+                    pipeline_id: pipeline.id,
+                    job_id: *job_id,
+                    step_id: step.id,
+                });
+
+                // A step that runs past the deadline is stopped the same way
+                // cancelling it would
+                let watchdog = deadline.as_ref().map(|deadline| {
+                    let (at, step_id) = (deadline.at, step.id);
+                    tokio::spawn(async move {
+                        tokio::time::sleep_until(at).await;
+                        if let Err(e) = queries::cancel_step(step_id).await {
+                            log::error!("Failed to stop step {} at its deadline: {}", step_id, e);
+                        }
+                    })
+                });
+                let result = self.execute_step(step, pipeline).await;
+                if let Some(watchdog) = watchdog {
+                    watchdog.abort();
+                }
+                if let Some(deadline) = deadline.as_ref().filter(|d| d.passed()) {
+                    return Err(self
+                        .time_out(pipeline, *job_id, Some(step), deadline)
+                        .await?);
+                }
+
+                let step_finished = |status| PipelineEvent::StepFinished {
+                    pipeline_id: pipeline.id,
+                    job_id: *job_id,
+                    step_id: step.id,
+                    status,
+                };
+
+                // A step stopped by cancellation neither completed nor failed
+                if queries::is_step_cancelled(step.id).await? {
+                    queries::set_step_status(step.id, ExecutionStatus::Cancelled).await?;
+                    self.events
+                        .publish(step_finished(ExecutionStatus::Cancelled));
+
+                    // Cancelling just this step leaves the rest of the job to run
+                    let job = queries::get_job_status(*job_id).await?;
+                    if job.status == ExecutionStatus::Cancelled {
+                        break;
+                    }
+                    continue;
+                }
 
-                match self.execute_step(step, pipeline).await {
+                match result {
                     Ok(_) => {
                         queries::set_step_status(step.id, ExecutionStatus::Completed).await?;
+                        self.events
+                            .publish(step_finished(ExecutionStatus::Completed));
                     }
                     Err(e) => {
+                        if step.config.cleanup_on_failure {
+                            queries::delete_step_objects(step.id).await?;
+                        }
                         queries::set_step_status(step.id, ExecutionStatus::Failed).await?;
+                        self.events.publish(step_finished(ExecutionStatus::Failed));
                         queries::set_job_status(*job_id, ExecutionStatus::Failed).await?;
+                        self.events.publish(PipelineEvent::JobFinished {
+                            pipeline_id: pipeline.id,
+                            job_id: *job_id,
+                            status: ExecutionStatus::Failed,
+                        });
                         queries::set_pipeline_status(pipeline.id, ExecutionStatus::Failed).await?;
-                        return Err(e);
+                        return Err(StepFailure {
+                            job_id: *job_id,
+                            step_id: step.id,
+                            step_name: step.config.name.clone(),
+                            error: e,
+                        }
+                        .into());
                     }
                 }
             }
 
             // If we got here and weren't cancelled, the job succeeded
-            if queries::get_job_status(*job_id).await?.status != ExecutionStatus::Cancelled {
+            let job_cancelled =
+                queries::get_job_status(*job_id).await?.status == ExecutionStatus::Cancelled;
+            let status = if job_cancelled {
+                ExecutionStatus::Cancelled
+            } else {
                 queries::set_job_status(*job_id, ExecutionStatus::Completed).await?;
-            }
+                ExecutionStatus::Completed
+            };
+            self.events.publish(PipelineEvent::JobFinished {
+                pipeline_id: pipeline.id,
+                job_id: *job_id,
+                status,
+            });
         }
 
-        // If we got here and weren't cancelled, the pipeline succeeded
-        if queries::get_pipeline_status(pipeline.id).await?.status != ExecutionStatus::Cancelled {
-            queries::set_pipeline_status(pipeline.id, ExecutionStatus::Completed).await?;
+        // The last job may have been cut short by cancelling the pipeline
+        if queries::get_pipeline_status(pipeline.id).await?.status == ExecutionStatus::Cancelled {
+            return Err(cancelled(pipeline.id));
         }
+        queries::set_pipeline_status(pipeline.id, ExecutionStatus::Completed).await?;
 
         Ok(())
     }
 
-    pub async fn execute_blocking(&self, pipeline: &PipelineStatus) {
-        if let Err(e) = self.execute(pipeline).await {
-            if let Err(store_err) = queries::store_error(pipeline.id, &e.to_string()).await {
-                eprintln!("Failed to store error: {}", store_err);
-            }
+    /// Fails the job and pipeline for running past `deadline`, along with the
+    /// step that was running, if any. Nothing left in the pipeline runs.
+    async fn time_out(
+        &self,
+        pipeline: &PipelineStatus,
+        job_id: u32,
+        step: Option<&StepStatus>,
+        deadline: &Deadline,
+    ) -> Result<anyhow::Error> {
+        if let Some(step) = step {
+            queries::set_step_status(step.id, ExecutionStatus::Failed).await?;
+            self.events.publish(PipelineEvent::StepFinished {
+                pipeline_id: pipeline.id,
+                job_id,
+                step_id: step.id,
+                status: ExecutionStatus::Failed,
+            });
         }
+        queries::set_job_status(job_id, ExecutionStatus::Failed).await?;
+        self.events.publish(PipelineEvent::JobFinished {
+            pipeline_id: pipeline.id,
+            job_id,
+            status: ExecutionStatus::Failed,
+        });
+        queries::cancel_pending(pipeline.id).await?;
+        queries::set_pipeline_status(pipeline.id, ExecutionStatus::Failed).await?;
+
+        Ok(TimedOut {
+            reason: deadline.reason.clone(),
+            failed_step: step.map(|step| (job_id, step.id)),
+        }
+        .into())
+    }
+
+    pub async fn execute_blocking(&self, pipeline: &PipelineStatus) {
+        let status = match self.execute(pipeline).await {
+            Ok(()) => ExecutionStatus::Completed,
+            Err(e) => record_error(pipeline.id, e).await,
+        };
+        self.events.publish(PipelineEvent::PipelineFinished {
+            pipeline_id: pipeline.id,
+            status,
+        });
+    }
+
+    /// Receives every pipeline event published from now on
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.events.subscribe()
     }
 
     pub async fn execute_background(&self, pipeline: &PipelineStatus) {
         let server = self.clone();
         let move_pipeline = pipeline.clone();
+        self.events.publish(PipelineEvent::PipelineSubmitted {
+            pipeline_id: pipeline.id,
+        });
+        // Holding the lock until the handle is stored keeps a pipeline that
+        // finishes at once from removing its entry before it is added
+        let mut handles = self.handles.lock().await;
         let handle = tokio::spawn(async move {
             server.execute_blocking(&move_pipeline).await;
+            server.handles.lock().await.remove(&move_pipeline.id);
         });
-        self.handles.lock().await.insert(pipeline.id, handle);
+        handles.insert(pipeline.id, handle);
+    }
+
+    /// How many pipelines are running in the background
+    #[cfg(test)]
+    pub(crate) async fn running_pipelines(&self) -> usize {
+        self.handles.lock().await.len()
+    }
+}
+
+/// A step failure, remembering where in the pipeline it happened
+#[derive(Debug, thiserror::Error)]
+#[error("step {step_name} ({step_id}) failed: {error}")]
+struct StepFailure {
+    job_id: u32,
+    step_id: u32,
+    step_name: String,
+    error: anyhow::Error,
+}
+
+/// A pipeline running past its or one of its jobs' timeout
+#[derive(Debug, thiserror::Error)]
+#[error("{reason}")]
+struct TimedOut {
+    reason: String,
+    /// The job and step that were stopped, if a step was running
+    failed_step: Option<(u32, u32)>,
+}
+
+/// A point in time a pipeline or job must finish by
+#[derive(Clone)]
+struct Deadline {
+    at: Instant,
+    /// Why running past it stops the pipeline
+    reason: String,
+}
+
+impl Deadline {
+    fn after(secs: u64, reason: String) -> Self {
+        Self {
+            at: Instant::now() + Duration::from_secs(secs),
+            reason,
+        }
+    }
+
+    fn passed(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
+/// Stores why a pipeline stopped, returning the status it stopped with
+async fn record_error(pipeline_id: u32, e: anyhow::Error) -> ExecutionStatus {
+    let (error, failed_step) = if let Some(timeout) = e.downcast_ref::<TimedOut>() {
+        (
+            PapError::Timeout(timeout.reason.clone()),
+            timeout.failed_step,
+        )
+    } else {
+        match e.downcast::<StepFailure>() {
+            Ok(failure) => (
+                PapError::Execution(failure.to_string()),
+                Some((failure.job_id, failure.step_id)),
+            ),
+            // Keep the kind of errors raised as PapError, e.g. cancellation
+            Err(e) => (
+                e.downcast::<PapError>()
+                    .unwrap_or_else(|e| PapError::Execution(e.to_string())),
+                None,
+            ),
+        }
+    };
+    if let Err(store_err) = queries::store_error(pipeline_id, &error, failed_step).await {
+        eprintln!("Failed to store error: {}", store_err);
+    }
+
+    match error {
+        PapError::Cancelled(_) => ExecutionStatus::Cancelled,
+        _ => ExecutionStatus::Failed,
+    }
+}
+
+/// Hides a job's `env` values before it is sent to a client. Steps read the
+/// real values from the database instead.
+fn redact_job(job: &mut JobStatus) {
+    job.config.redact_env();
+    for step in &mut job.steps {
+        step.config.redact_env();
+    }
+}
+
+/// Runs a step with its executor, returning the result along with what the
+/// step logged and the statistics it last reported
+fn run_step(
+    executor: &dyn StepExecutor,
+    step: &StepStatus,
+    pipeline: &PipelineStatus,
+    context: &pap_api::Context,
+) -> (Result<()>, StepOutput, Option<String>) {
+    let mut context = StepContext::new(step, pipeline, context);
+
+    let result = if pipeline.dry_run {
+        executor.dry_run(&mut context).inspect(|_| {
+            context.log("Dry run: step setup succeeded, execution skipped");
+        })
+    } else {
+        executor.execute(&mut context)
+    };
+    (result, context.get_output(), context.get_stats())
+}
+
+fn cancelled(pipeline_id: u32) -> anyhow::Error {
+    PapError::Cancelled(format!("Pipeline {} was cancelled", pipeline_id)).into()
+}
+
+/// Runs a request's work within what is left of its deadline, so that work the
+/// client has stopped waiting for doesn't keep holding the database
+async fn within_deadline<T, E: Into<PapError>>(
+    ctx: &Context,
+    work: impl Future<Output = Result<T, E>>,
+) -> Result<T, PapError> {
+    let remaining = ctx
+        .deadline
+        .saturating_duration_since(std::time::Instant::now());
+    match tokio::time::timeout(remaining, work).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(PapError::Timeout(
+            "request deadline passed before it finished".to_string(),
+        )),
     }
 }
 
@@ -181,63 +618,312 @@ impl PapApi for PipelineServer {
         self,
         _: Context,
         pipeline_context: pap_api::Context,
-    ) -> Result<u32, PapError> {
-        self.validate(&pipeline_context)?;
-        let status = queries::setup_pipeline(&pipeline_context).await?;
+    ) -> Result<SubmitResult, PapError> {
+        self.validate(&pipeline_context)
+            .map_err(|e| PapError::Configuration(e.to_string()))?;
+        let warnings = self.warnings(&pipeline_context);
+        let status = self.setup_pipeline(&pipeline_context, false).await?;
+        self.execute_background(&status).await;
+        Ok(SubmitResult {
+            id: status.id,
+            warnings,
+        })
+    }
+
+    async fn dry_run_pipeline(
+        self,
+        _: Context,
+        pipeline_context: pap_api::Context,
+    ) -> Result<SubmitResult, PapError> {
+        self.validate(&pipeline_context)
+            .map_err(|e| PapError::Configuration(e.to_string()))?;
+        let warnings = self.warnings(&pipeline_context);
+        let status = self.setup_pipeline(&pipeline_context, true).await?;
+        self.execute_background(&status).await;
+        Ok(SubmitResult {
+            id: status.id,
+            warnings,
+        })
+    }
+
+    async fn resubmit_pipeline(self, _: Context, id: u32) -> Result<u32, PapError> {
+        let pipeline_context = queries::get_pipeline_context(id).await?;
+        self.validate(&pipeline_context)
+            .map_err(|e| PapError::Configuration(e.to_string()))?;
+        let status = self.setup_pipeline(&pipeline_context, false).await?;
         self.execute_background(&status).await;
         Ok(status.id)
     }
 
-    async fn get_pipeline(self, _: Context, id: u32) -> Result<PipelineStatus, PapError> {
-        Ok(queries::get_pipeline_status(id).await?)
+    async fn resume_pipeline(self, _: Context, id: u32) -> Result<(), PapError> {
+        // The previous run must have finished, including recording its error
+        if let Some(handle) = self.handles.lock().await.get(&id) {
+            if !handle.is_finished() {
+                return Err(PapError::Configuration(format!(
+                    "Pipeline {} is still running",
+                    id
+                )));
+            }
+        }
+
+        let status = queries::get_pipeline_status(id).await?;
+        if !matches!(
+            status.status,
+            ExecutionStatus::Failed | ExecutionStatus::Cancelled
+        ) {
+            return Err(PapError::Configuration(format!(
+                "Pipeline {} is {}; only failed or cancelled pipelines can be resumed",
+                id, status.status
+            )));
+        }
+
+        queries::reset_for_resume(id).await?;
+        let status = queries::get_pipeline_status(id).await?;
+        self.execute_background(&status).await;
+        Ok(())
     }
 
-    async fn get_pipelines(self, _: Context) -> Result<Vec<u32>, PapError> {
-        Ok(sqlx::query_scalar("SELECT id FROM pipelines")
-            .fetch_all(&with_pool()?)
-            .await?)
+    async fn get_pipeline(self, ctx: Context, id: u32) -> Result<PipelineStatus, PapError> {
+        let mut status = within_deadline(&ctx, queries::get_pipeline_status(id)).await?;
+        status.config.redact_env();
+        Ok(status)
+    }
+
+    async fn get_pipeline_statuses(
+        self,
+        ctx: Context,
+        ids: Vec<u32>,
+    ) -> Result<Vec<PipelineStatus>, PapError> {
+        let mut statuses = within_deadline(&ctx, queries::get_pipeline_statuses(&ids)).await?;
+        for status in &mut statuses {
+            status.config.redact_env();
+        }
+        Ok(statuses)
+    }
+
+    async fn export_config(self, ctx: Context, id: u32) -> Result<String, PapError> {
+        within_deadline(&ctx, async {
+            let mut config = queries::get_pipeline_config(id).await?;
+            config.redact_env();
+            serde_yaml::to_string(&config).map_err(|e| PapError::Internal(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_pipelines(self, ctx: Context) -> Result<Vec<u32>, PapError> {
+        within_deadline(&ctx, queries::get_pipeline_ids()).await
+    }
+
+    async fn get_pipelines_by_label(
+        self,
+        ctx: Context,
+        key: String,
+        value: String,
+    ) -> Result<Vec<u32>, PapError> {
+        within_deadline(&ctx, queries::get_pipeline_ids_by_label(&key, &value)).await
+    }
+
+    async fn count_pipelines(
+        self,
+        ctx: Context,
+        status: Option<ExecutionStatus>,
+    ) -> Result<u64, PapError> {
+        within_deadline(&ctx, queries::count_pipelines(status)).await
     }
 
     async fn cancel_pipeline(self, _: Context, id: u32) -> Result<(), PapError> {
-        queries::cancel_pipeline(id).await?;
+        if queries::cancel_pipeline(id).await? == 0 {
+            let status = queries::get_pipeline_status(id).await?.status;
+            return Err(PapError::Configuration(format!(
+                "Pipeline {} is already {}; only pending or running pipelines can be cancelled",
+                id, status
+            )));
+        }
         Ok(())
     }
 
+    async fn cancel_all_pipelines(self, _: Context) -> Result<u64, PapError> {
+        let ids = queries::cancel_all_pipelines().await?;
+
+        // Stop the pipelines' tasks rather than waiting for each to notice.
+        // A running step is left to see the cancellation itself, but its
+        // result and log are no longer recorded.
+        let mut handles = self.handles.lock().await;
+        let aborted: Vec<_> = ids
+            .iter()
+            .filter_map(|id| handles.remove(id).map(|handle| (*id, handle)))
+            .collect();
+        drop(handles);
+        for (id, handle) in aborted {
+            handle.abort();
+            // An aborted task can't record why it stopped, so record it here
+            let status = record_error(id, cancelled(id)).await;
+            self.events.publish(PipelineEvent::PipelineFinished {
+                pipeline_id: id,
+                status,
+            });
+        }
+        Ok(ids.len() as u64)
+    }
+
     async fn delete_pipeline(self, _: Context, id: u32) -> Result<(), PapError> {
-        queries::delete_pipeline(id).await?;
+        if queries::delete_pipeline(id).await? == 0 {
+            return Err(PapError::NotFound(format!("Pipeline {}", id)));
+        }
         Ok(())
     }
 
-    async fn get_job(self, _: Context, id: u32) -> Result<JobStatus, PapError> {
-        Ok(queries::get_job_status(id).await?)
+    async fn get_job(self, ctx: Context, id: u32) -> Result<JobStatus, PapError> {
+        let mut job = within_deadline(&ctx, queries::get_job_status(id)).await?;
+        redact_job(&mut job);
+        Ok(job)
     }
 
-    async fn get_jobs(self, _: Context) -> Result<Vec<u32>, PapError> {
-        Ok(sqlx::query_scalar("SELECT id FROM jobs")
-            .fetch_all(&with_pool()?)
-            .await?)
+    async fn get_step(self, ctx: Context, id: u32) -> Result<StepStatus, PapError> {
+        let mut step = within_deadline(&ctx, queries::get_step_status(id)).await?;
+        step.config.redact_env();
+        Ok(step)
+    }
+
+    async fn get_jobs(self, ctx: Context) -> Result<Vec<u32>, PapError> {
+        within_deadline(&ctx, async {
+            sqlx::query_scalar("SELECT id FROM jobs")
+                .fetch_all(&with_pool()?)
+                .await
+                .map_err(PapError::from)
+        })
+        .await
+    }
+
+    async fn count_jobs(
+        self,
+        ctx: Context,
+        status: Option<ExecutionStatus>,
+    ) -> Result<u64, PapError> {
+        within_deadline(&ctx, queries::count_jobs(status)).await
+    }
+
+    async fn get_pipeline_jobs(self, ctx: Context, id: u32) -> Result<Vec<u32>, PapError> {
+        within_deadline(&ctx, async {
+            // An unknown pipeline would otherwise look like one without jobs
+            queries::get_pipeline_status(id).await?;
+            queries::get_pipeline_job_ids(id).await
+        })
+        .await
+    }
+
+    async fn get_pipeline_history(
+        self,
+        ctx: Context,
+        id: u32,
+    ) -> Result<Vec<StatusChange>, PapError> {
+        within_deadline(&ctx, async {
+            // An empty history could be mistaken for a pipeline that never ran
+            queries::get_pipeline_status(id).await?;
+            queries::get_pipeline_history(id).await
+        })
+        .await
     }
 
     async fn cancel_job(self, _: Context, id: u32) -> Result<(), PapError> {
-        queries::cancel_job(id).await?;
+        if queries::cancel_job(id).await? == 0 {
+            let status = queries::get_job_status(id).await?.status;
+            return Err(PapError::Configuration(format!(
+                "Job {} is already {}; only pending or running jobs can be cancelled",
+                id, status
+            )));
+        }
         Ok(())
     }
 
-    async fn get_step_log(self, _: Context, id: u32) -> Result<Vec<u8>, PapError> {
-        sqlx::query_scalar::<_, Vec<u8>>("SELECT log_data FROM steps WHERE id = ?")
-            .bind(id)
-            .fetch_optional(&with_pool()?)
-            .await?
-            .ok_or_else(|| PapError::NotFound(format!("Step log for {}", id)))
+    async fn cancel_step(self, _: Context, id: u32) -> Result<(), PapError> {
+        if !queries::cancel_step(id).await? {
+            let status = queries::get_step_status(id).await?.status;
+            return Err(PapError::Configuration(format!(
+                "Step {} is {}; only pending or running steps can be cancelled",
+                id, status
+            )));
+        }
+        Ok(())
+    }
+
+    async fn get_step_log(self, ctx: Context, id: u32) -> Result<Vec<u8>, PapError> {
+        within_deadline(&ctx, queries::get_step_log(id)).await
+    }
+
+    async fn get_pipeline_logs(
+        self,
+        ctx: Context,
+        id: u32,
+    ) -> Result<Vec<(u32, Vec<u8>)>, PapError> {
+        within_deadline(&ctx, async {
+            queries::get_pipeline_status(id).await?;
+            queries::get_pipeline_logs(id).await
+        })
+        .await
+    }
+
+    async fn find_step(
+        self,
+        ctx: Context,
+        pipeline_id: u32,
+        job: Option<String>,
+        step: String,
+    ) -> Result<u32, PapError> {
+        within_deadline(
+            &ctx,
+            queries::find_step_id(pipeline_id, job.as_deref(), &step),
+        )
+        .await
+    }
+
+    async fn list_executors(self, _: Context) -> Vec<ExecutorInfo> {
+        self.registry.info()
+    }
+
+    async fn api_version(self, _: Context) -> Result<u32, PapError> {
+        Ok(pap_api::API_VERSION)
+    }
+
+    async fn health(self, _: Context) -> Result<HealthStatus, PapError> {
+        let db_ok = queries::ping().await.is_ok();
+        let active_pipelines = if db_ok {
+            queries::count_active_pipelines().await?
+        } else {
+            0
+        };
+
+        Ok(HealthStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            db_ok,
+            active_pipelines,
+        })
+    }
+
+    async fn events(self, _: Context, after: Option<u64>) -> Result<Vec<EventRecord>, PapError> {
+        Ok(self.events.poll(after, EVENT_POLL_TIMEOUT).await)
     }
 
     async fn get_object(
         self,
-        _: Context,
+        ctx: Context,
         namespace: String,
         key: Vec<u8>,
     ) -> Result<Vec<u8>, PapError> {
-        queries::get_object(&namespace, &key).await
+        within_deadline(&ctx, queries::get_object(&namespace, &key)).await
+    }
+
+    async fn object_exists(
+        self,
+        ctx: Context,
+        namespace: String,
+        key: Vec<u8>,
+    ) -> Result<bool, PapError> {
+        within_deadline(&ctx, queries::object_exists(&namespace, &key)).await
+    }
+
+    async fn list_namespaces(self, ctx: Context) -> Result<Vec<String>, PapError> {
+        within_deadline(&ctx, queries::get_namespaces()).await
     }
 
     async fn put_object(
@@ -247,7 +933,7 @@ impl PapApi for PipelineServer {
         key: Vec<u8>,
         value: Vec<u8>,
     ) -> Result<(), PapError> {
-        queries::put_object(&namespace, &key, &value)
+        queries::put_object(&namespace, &key, &value, None)
             .await
             .map_err(Into::into)
     }
@@ -0,0 +1,172 @@
+use std::io::Read;
+use std::time::Duration;
+
+use anyhow::bail;
+use pap_api::{ArgSchema, ArgType, Context, Step};
+use reqwest::Url;
+
+use super::{StepContext, StepExecutor, StepRequirements};
+
+/// Largest body downloaded unless `max_size` is given
+const DEFAULT_FETCH_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Longest a download may take unless `timeout_secs` is given
+const DEFAULT_FETCH_TIMEOUT_SECS: u64 = 60;
+
+/// Downloads a URL and stores the body as an object, for importing seeds or
+/// other inputs from outside the pipeline
+pub struct FetchStepExecutor;
+
+impl StepExecutor for FetchStepExecutor {
+    fn name(&self) -> String {
+        "fetch".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let url = parse_url(
+            ctx.get_arg("url")
+                .ok_or(anyhow::anyhow!("missing `url` argument"))?,
+        )?;
+        let max_size = positive_arg(ctx, "max_size", DEFAULT_FETCH_MAX_SIZE)?;
+        let timeout = positive_arg(ctx, "timeout_secs", DEFAULT_FETCH_TIMEOUT_SECS)?;
+        let output = ctx
+            .get_io("output")
+            .ok_or(anyhow::anyhow!("missing `output` IO"))?;
+        let key = ctx
+            .get_arg("key")
+            .ok_or(anyhow::anyhow!("missing `key` argument"))?;
+
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(timeout))
+            .build()?;
+        let response = client.get(url.clone()).send()?.error_for_status()?;
+
+        let allowed = ctx.get_list_arg("content_type");
+        if !allowed.is_empty() {
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            // Parameters such as the charset don't matter here
+            let media_type = content_type.split(';').next().unwrap_or_default().trim();
+            if !allowed.iter().any(|a| a.eq_ignore_ascii_case(media_type)) {
+                bail!(
+                    "{} has content type `{}`, expected one of: {}",
+                    url,
+                    content_type,
+                    allowed.join(", ")
+                );
+            }
+        }
+
+        // The length header is only a hint, so the body is capped as it's read
+        if let Some(len) = response.content_length() {
+            if len > max_size {
+                bail!("{} is {} bytes, over the {} byte limit", url, len, max_size);
+            }
+        }
+        let mut body = Vec::new();
+        response.take(max_size + 1).read_to_end(&mut body)?;
+        if body.len() as u64 > max_size {
+            bail!("{} is over the {} byte limit", url, max_size);
+        }
+
+        ctx.write_object(output, key.as_bytes(), &body)?;
+        ctx.log(&format!(
+            "Fetched {} bytes from {} into {}/{}",
+            body.len(),
+            url,
+            output,
+            key
+        ));
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        positive_arg(ctx, "max_size", DEFAULT_FETCH_MAX_SIZE)?;
+        positive_arg(ctx, "timeout_secs", DEFAULT_FETCH_TIMEOUT_SECS)?;
+        ctx.log(&format!(
+            "Would fetch {}",
+            ctx.get_arg("url").unwrap_or_default()
+        ));
+        Ok(())
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec!["url".to_string(), "key".to_string()],
+            io: vec!["output".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn validate(&self, step: &Step, _context: &Context) -> anyhow::Result<()> {
+        if let Some(url) = step.args.get("url") {
+            parse_url(url).map_err(|e| anyhow::anyhow!("step {}: {}", step.name, e))?;
+        }
+        Ok(())
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        vec![
+            ArgSchema {
+                name: "url".to_string(),
+                arg_type: ArgType::String,
+                required: true,
+                default: None,
+                description: "http or https URL to download".to_string(),
+            },
+            ArgSchema {
+                name: "key".to_string(),
+                arg_type: ArgType::String,
+                required: true,
+                default: None,
+                description: "Key to store the body under in the `output` namespace".to_string(),
+            },
+            ArgSchema {
+                name: "max_size".to_string(),
+                arg_type: ArgType::Integer,
+                required: false,
+                default: Some(DEFAULT_FETCH_MAX_SIZE.to_string()),
+                description: "Largest body in bytes that may be downloaded".to_string(),
+            },
+            ArgSchema {
+                name: "content_type".to_string(),
+                arg_type: ArgType::List,
+                required: false,
+                default: None,
+                description: "Media types the response may have. Any are accepted if unset"
+                    .to_string(),
+            },
+            ArgSchema {
+                name: "timeout_secs".to_string(),
+                arg_type: ArgType::Integer,
+                required: false,
+                default: Some(DEFAULT_FETCH_TIMEOUT_SECS.to_string()),
+                description: "Longest the download may take".to_string(),
+            },
+        ]
+    }
+}
+
+/// Only web URLs may be fetched, so a config can't read the server's files
+fn parse_url(url: &str) -> anyhow::Result<Url> {
+    let parsed = Url::parse(url).map_err(|e| anyhow::anyhow!("invalid url `{}`: {}", url, e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => bail!(
+            "unsupported url scheme `{}` in {}, expected http or https",
+            scheme,
+            url
+        ),
+    }
+}
+
+fn positive_arg(ctx: &StepContext, name: &str, default: u64) -> anyhow::Result<u64> {
+    match ctx.get_int_arg(name)? {
+        Some(value) if value > 0 => Ok(value as u64),
+        Some(value) => bail!("invalid {} value: {}", name, value),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,74 @@
+use pap_api::{ArgSchema, ArgType};
+use regex::bytes::Regex;
+
+use super::{StepContext, StepExecutor, StepRequirements};
+
+/// Searches a stored object for a byte pattern, logging the offset of every
+/// match and optionally storing them in an output namespace
+pub struct GrepStepExecutor;
+
+impl StepExecutor for GrepStepExecutor {
+    fn name(&self) -> String {
+        "grep".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let pattern = ctx
+            .get_arg("pattern")
+            .ok_or(anyhow::anyhow!("missing `pattern` argument"))?;
+        let regex = Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("invalid `pattern` argument: {}", e))?;
+        let namespace = ctx
+            .get_io("namespace")
+            .ok_or(anyhow::anyhow!("missing `namespace` IO"))?;
+        let key = ctx
+            .get_io("key")
+            .ok_or(anyhow::anyhow!("missing `key` IO"))?;
+
+        let data = ctx.read_object(namespace, key.as_bytes())?;
+        let offsets: Vec<_> = regex
+            .find_iter(&data)
+            .map(|m| format!("{:#x}", m.start()))
+            .collect();
+
+        ctx.log(&format!(
+            "Found {} matches for `{}` in {}/{}",
+            offsets.len(),
+            pattern,
+            namespace,
+            key
+        ));
+        for offset in &offsets {
+            ctx.log(offset);
+        }
+
+        // Matches are stored one offset per line, under the searched key
+        if let Some(output) = ctx.get_io("output") {
+            let mut matches = offsets.join("\n");
+            if !matches.is_empty() {
+                matches.push('\n');
+            }
+            ctx.write_object(output, key.as_bytes(), matches.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec!["pattern".to_string()],
+            io: vec!["namespace".to_string(), "key".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        vec![ArgSchema {
+            name: "pattern".to_string(),
+            arg_type: ArgType::String,
+            required: true,
+            default: None,
+            description: "Regular expression matched against the object's bytes".to_string(),
+        }]
+    }
+}
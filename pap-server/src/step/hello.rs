@@ -1,4 +1,6 @@
-use super::{StepContext, StepExecutor};
+use pap_api::{ArgSchema, ArgType};
+
+use super::{StepContext, StepExecutor, StepRequirements};
 
 pub struct HelloStepExecutor;
 
@@ -11,8 +13,49 @@ impl StepExecutor for HelloStepExecutor {
         let name = ctx
             .get_arg("name")
             .ok_or(anyhow::anyhow!("missing `name` argument"))?;
-        let message = format!("Hello, {}!", name);
-        ctx.log(&message);
+        let count = ctx.get_int_arg("count")?.unwrap_or(1);
+        let shout = ctx.get_bool_arg("shout")?.unwrap_or(false);
+
+        let mut message = format!("Hello, {}!", name);
+        if shout {
+            message = message.to_uppercase();
+        }
+        for _ in 0..count {
+            ctx.log(&message);
+        }
         Ok(())
     }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec!["name".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        vec![
+            ArgSchema {
+                name: "name".to_string(),
+                arg_type: ArgType::String,
+                required: true,
+                default: None,
+                description: "Who to greet".to_string(),
+            },
+            ArgSchema {
+                name: "count".to_string(),
+                arg_type: ArgType::Integer,
+                required: false,
+                default: Some("1".to_string()),
+                description: "How many times to greet".to_string(),
+            },
+            ArgSchema {
+                name: "shout".to_string(),
+                arg_type: ArgType::Boolean,
+                required: false,
+                default: Some("false".to_string()),
+                description: "Greet in uppercase".to_string(),
+            },
+        ]
+    }
 }
@@ -24,6 +24,12 @@ where
     harness_fn: H,
     observers: OT,
     snapshot: Snapshot,
+    /// Most inputs run between snapshot restores
+    persistent_iters: u64,
+    /// Inputs run since the snapshot was last restored
+    runs_since_restore: u64,
+    /// Snapshot restores so far
+    restores: u64,
     phantom: PhantomData<(*const S,)>,
 }
 
@@ -55,7 +61,14 @@ where
 
         let ret = self.harness_fn.borrow_mut()(&mut self.vm, input);
 
-        self.vm.restore(&self.snapshot);
+        // A run that didn't return may leave the VM in any state, so it is
+        // always followed by a restore
+        self.runs_since_restore += 1;
+        if ret != ExitKind::Ok || self.runs_since_restore >= self.persistent_iters {
+            self.vm.restore(&self.snapshot);
+            self.runs_since_restore = 0;
+            self.restores += 1;
+        }
 
         Ok(ret)
     }
@@ -111,7 +124,29 @@ where
             harness_fn,
             observers,
             snapshot,
+            persistent_iters: 1,
+            runs_since_restore: 0,
+            restores: 0,
             phantom: PhantomData,
         })
     }
+
+    /// Runs up to `iters` inputs between snapshot restores instead of
+    /// restoring after every one. Only sound for harnesses that leave no state
+    /// behind that affects later runs.
+    pub fn with_persistent_iters(mut self, iters: u64) -> Self {
+        self.persistent_iters = iters.max(1);
+        self
+    }
+
+    /// How many times the VM has been restored from its snapshot
+    pub fn restores(&self) -> u64 {
+        self.restores
+    }
+
+    /// Gives back the VM, restored to the state it was handed over in
+    pub fn into_vm(mut self) -> Vm {
+        self.vm.restore(&self.snapshot);
+        self.vm
+    }
 }
@@ -1,47 +1,80 @@
+use std::cell::{RefCell, UnsafeCell};
 use std::cmp::max;
+use std::collections::{BTreeMap, HashSet};
 use std::num::NonZero;
 use std::rc::Rc;
+use std::str::FromStr;
 use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use icicle_fuzzing::coverage::register_afl_hit_counts_all;
 use icicle_vm::cpu::mem::perm::{EXEC, READ, WRITE};
 use icicle_vm::cpu::mem::Mapping;
 use icicle_vm::cpu::{Config, ExceptionCode};
 use icicle_vm::Vm;
 use icicle_vm::VmExit;
+use libafl::common::HasMetadata;
 use libafl::feedbacks::MaxMapFeedback;
 use libafl::generators::RandBytesGenerator;
 use libafl::inputs::HasMutatorBytes;
-use libafl::monitors::SimpleMonitor;
+use libafl::mutators::token_mutations::Tokens;
 use libafl::observers::{CanTrack, ConstMapObserver, HitcountsMapObserver};
-use libafl::stages::StdMutationalStage;
+use libafl::schedulers::powersched::PowerSchedule;
+use libafl::schedulers::{PowerQueueScheduler, StdWeightedScheduler};
+use libafl::stages::{CalibrationStage, StdMutationalStage, StdPowerMutationalStage};
 use libafl::{
+    corpus::Corpus,
     events::SimpleEventManager,
     executors::ExitKind,
     feedbacks::CrashFeedback,
     fuzzer::{Fuzzer, StdFuzzer},
     inputs::BytesInput,
-    mutators::{havoc_mutations::havoc_mutations, scheduled::StdScheduledMutator},
+    mutators::{
+        havoc_mutations::havoc_mutations,
+        scheduled::{tokens_mutations, StdScheduledMutator},
+    },
     schedulers::QueueScheduler,
-    state::StdState,
+    state::{HasCorpus, HasExecutions, HasSolutions, StdState},
+};
+use libafl_bolts::{
+    current_nanos,
+    rands::StdRand,
+    tuples::{tuple_list, Merge},
 };
-use libafl_bolts::HasLen;
-use libafl_bolts::{current_nanos, rands::StdRand, tuples::tuple_list};
 use libafl_targets::EDGES_MAP_DEFAULT_SIZE;
 use mlua::Error;
 use mlua::UserData;
+use pap_api::OutputKind;
 
+use crate::step::icicle::minimize::minimize_input;
+use crate::step::icicle::monitor::{FuzzStats, StatsMonitor};
+use crate::step::icicle::reproducer::{binary_checksum, encode_input, Reproducer, HARNESS_ARGS};
 use crate::step::icicle::sqlcorpus::SqlCorpus;
-use crate::step::StepContext;
+use crate::step::icicle::triage::TriageReport;
+use crate::step::icicle::vm_cache::{self, VmKey};
+use crate::step::icicle::{get_libraries, Library};
+use crate::step::{LogLevel, StepContext};
 
 #[inline]
 fn vm_reg(vm: &Vm, reg: &str) -> pcode::VarNode {
     vm.cpu.arch.sleigh.get_reg(reg).unwrap().var
 }
 
-static mut EDGES_MAP: [u8; EDGES_MAP_DEFAULT_SIZE] = [0; EDGES_MAP_DEFAULT_SIZE];
+thread_local! {
+    /// Coverage the VMs on this thread record into. Fuzzing steps are pinned
+    /// to a thread and run one at a time there, so fuzzers running at once
+    /// never share a map, and a cached VM stays on the thread whose map it
+    /// was registered with.
+    static EDGES_MAP: UnsafeCell<[u8; EDGES_MAP_DEFAULT_SIZE]> =
+        const { UnsafeCell::new([0; EDGES_MAP_DEFAULT_SIZE]) };
+}
+
+/// This thread's coverage map, which lives as long as the thread
+fn edges_map() -> *mut [u8; EDGES_MAP_DEFAULT_SIZE] {
+    EDGES_MAP.with(UnsafeCell::get)
+}
 
 struct LuaVmBridge<'a> {
     vm: RwLock<&'a mut Vm>,
@@ -83,48 +116,209 @@ impl UserData for LuaVmBridge<'_> {
 #[derive(Clone)]
 struct RhaiVmBridge<'a>(Rc<RwLock<&'a mut Vm>>);
 
+type RhaiResult<T> = Result<T, Box<rhai::EvalAltResult>>;
+
+/// Converts an address from a harness script, which only has signed integers
+fn guest_addr(offset: i64) -> RhaiResult<u64> {
+    Ok(u64::try_from(offset).map_err(|_| format!("address out of range: {}", offset))?)
+}
+
+fn rhai_reg(vm: &Vm, reg_name: &str) -> RhaiResult<pcode::VarNode> {
+    Ok(vm
+        .cpu
+        .arch
+        .sleigh
+        .get_reg(reg_name)
+        .ok_or_else(|| format!("unknown register: {}", reg_name))?
+        .var)
+}
+
 impl<'a> RhaiVmBridge<'a> {
-    fn read_mem_u32(&mut self, offset: i64) -> i64 {
+    /// Reads a `width` byte unsigned integer in the guest's byte order
+    fn read_uint(&mut self, offset: i64, width: usize) -> RhaiResult<i64> {
+        let addr = guest_addr(offset)?;
         let mut vm = self.0.write().expect("lock poisoned");
-        let mut buf = [0u8; 4];
+        let mut buf = [0u8; 8];
+        let buf = &mut buf[..width];
         vm.cpu
             .mem
-            .read_bytes(offset as u64, &mut buf, READ)
-            .unwrap_or_else(|_| panic!("failed to read memory at 0x{:x}", offset));
+            .read_bytes(addr, buf, READ)
+            .map_err(|_| format!("failed to read memory at 0x{:x}", addr))?;
 
-        if vm.cpu.arch.sleigh.big_endian {
-            u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as i64
-        } else {
-            u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as i64
+        if !vm.cpu.arch.sleigh.big_endian {
+            buf.reverse();
         }
+        let value = buf.iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+        Ok(value as i64)
     }
 
-    fn write_reg(&mut self, reg_name: String, value: i64) {
+    fn read_mem_u8(&mut self, offset: i64) -> RhaiResult<i64> {
+        self.read_uint(offset, 1)
+    }
+
+    fn read_mem_u16(&mut self, offset: i64) -> RhaiResult<i64> {
+        self.read_uint(offset, 2)
+    }
+
+    fn read_mem_u32(&mut self, offset: i64) -> RhaiResult<i64> {
+        self.read_uint(offset, 4)
+    }
+
+    /// Values of 2^63 and up come back negative, as rhai integers are signed
+    fn read_mem_u64(&mut self, offset: i64) -> RhaiResult<i64> {
+        self.read_uint(offset, 8)
+    }
+
+    fn write_mem(&mut self, offset: i64, data: rhai::Blob) -> RhaiResult<()> {
+        let addr = guest_addr(offset)?;
         let mut vm = self.0.write().expect("lock poisoned");
-        let reg = vm_reg(&vm, &reg_name);
+        vm.cpu
+            .mem
+            .write_bytes(addr, &data, WRITE)
+            .map_err(|_| format!("failed to write memory at 0x{:x}", addr))?;
+        Ok(())
+    }
+
+    fn read_reg(&mut self, reg_name: String) -> RhaiResult<i64> {
+        let vm = self.0.write().expect("lock poisoned");
+        let reg = rhai_reg(&vm, &reg_name)?;
+        Ok(vm.cpu.read_reg(reg) as i64)
+    }
+
+    fn write_reg(&mut self, reg_name: String, value: i64) -> RhaiResult<()> {
+        let mut vm = self.0.write().expect("lock poisoned");
+        let reg = rhai_reg(&vm, &reg_name)?;
         vm.cpu.write_reg(reg, value as u64);
+        Ok(())
+    }
+
+    /// Starts the run somewhere other than the harnessed function
+    fn set_pc(&mut self, addr: i64) -> RhaiResult<()> {
+        let addr = guest_addr(addr)?;
+        self.0.write().expect("lock poisoned").cpu.write_pc(addr);
+        Ok(())
     }
 }
 
-fn run_rhai_harness(vm: &mut Vm, harness_code: &str) -> Result<()> {
+fn run_rhai_harness(vm: &mut Vm, harness: &FuzzHarness, input_len: usize) -> Result<()> {
     let static_vm = unsafe { std::mem::transmute::<&mut Vm, &'static mut Vm>(vm) };
     let mut engine = rhai::Engine::new();
     let vm = RhaiVmBridge(Rc::new(RwLock::new(static_vm)));
 
+    // Abort runaway scripts instead of hanging the step
     engine
+        .set_max_operations(harness.op_limit)
+        .set_max_call_levels(HARNESS_MAX_CALL_LEVELS)
         .register_type::<RhaiVmBridge>()
+        .register_fn("read_mem_u8", RhaiVmBridge::read_mem_u8)
+        .register_fn("read_mem_u16", RhaiVmBridge::read_mem_u16)
         .register_fn("read_mem_u32", RhaiVmBridge::read_mem_u32)
-        .register_fn("write_reg", RhaiVmBridge::write_reg);
+        .register_fn("read_mem_u64", RhaiVmBridge::read_mem_u64)
+        .register_fn("write_mem", RhaiVmBridge::write_mem)
+        .register_fn("read_reg", RhaiVmBridge::read_reg)
+        .register_fn("write_reg", RhaiVmBridge::write_reg)
+        .register_fn("set_pc", RhaiVmBridge::set_pc);
 
     let mut scope = rhai::Scope::new();
-    scope.push_constant("input_addr", 0x4100_0000_i64);
+    scope.push_constant("input_addr", harness.input_addr as i64);
+    scope.push_constant("input_len", input_len as i64);
     scope.push("vm", vm);
 
     engine
-        .eval_with_scope::<()>(&mut scope, harness_code)
-        .expect("failed to run harness");
+        .eval_with_scope::<()>(&mut scope, &harness.lua_code)
+        .map_err(|e| anyhow!("harness script failed: {}", e))
+}
 
-    Ok(())
+/// Address the harnessed function returns to unless `return_addr` is given.
+/// Nothing may be mapped there, so returning stops the VM.
+pub(super) const DEFAULT_RETURN_ADDR: u64 = 0x1336;
+
+/// Most operations a harness script may run per input unless
+/// `harness_op_limit` is given
+pub(super) const DEFAULT_HARNESS_OP_LIMIT: u64 = 100_000;
+
+/// Deepest a harness script's function calls may nest
+const HARNESS_MAX_CALL_LEVELS: usize = 32;
+
+/// Most instructions a single run may execute unless `instruction_limit` is
+/// given
+pub(super) const DEFAULT_INSTRUCTION_LIMIT: u64 = 1_000_000;
+
+/// Most bytes of memory a run may allocate unless `memory_limit` is given
+pub(super) const DEFAULT_MEMORY_LIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Size of the pages icicle allocates guest memory in
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Seconds between checkpoints of the fuzzer's state unless
+/// `checkpoint_interval` is given
+pub(super) const DEFAULT_CHECKPOINT_INTERVAL: u64 = 60;
+
+/// Key of the checkpoint object written to the `checkpoint` namespace
+pub(crate) const CHECKPOINT_KEY: &[u8] = b"checkpoint";
+
+/// How the fuzzer picks the next corpus entry to mutate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CorpusScheduler {
+    /// Every entry in turn, in the order they were added
+    Queue,
+    /// Entries at random, favoring fast ones that reach rarely hit edges
+    Weighted,
+    /// Every entry in turn, mutated more often the more promising it is
+    Power,
+}
+
+impl FromStr for CorpusScheduler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "queue" => Ok(Self::Queue),
+            "weighted" => Ok(Self::Weighted),
+            "power" => Ok(Self::Power),
+            _ => bail!(
+                "invalid scheduler value: {}; expected queue, weighted, or power",
+                s
+            ),
+        }
+    }
+}
+
+/// Everything the fuzzer tracks between runs, which checkpoints store
+pub(crate) type FuzzState = StdState<BytesInput, SqlCorpus, StdRand, SqlCorpus>;
+
+/// Restores the fuzzer's state from a checkpoint
+pub(crate) fn load_checkpoint(data: &[u8]) -> Result<FuzzState> {
+    Ok(postcard::from_bytes(data)?)
+}
+
+/// Restores the fuzzer's state from the checkpoint in `namespace`, if any
+fn read_checkpoint(ctx: &StepContext, namespace: &str) -> Result<Option<FuzzState>> {
+    let keys = ctx.list_objects(namespace)?;
+    if !keys.iter().any(|key| key == CHECKPOINT_KEY) {
+        return Ok(None);
+    }
+    load_checkpoint(&ctx.read_object(namespace, CHECKPOINT_KEY)?).map(Some)
+}
+
+/// Stores the fuzzer's state, after the testcases it refers to so that a run
+/// resumed from it finds them
+fn write_checkpoint(ctx: &StepContext, namespace: &str, state: &FuzzState) -> Result<()> {
+    state.corpus().flush()?;
+    state.solutions().flush()?;
+    ctx.write_object(namespace, CHECKPOINT_KEY, &postcard::to_allocvec(state)?)
+}
+
+/// Reads every object in `namespace` as a token for the token mutations
+pub(crate) fn load_dictionary(ctx: &StepContext, namespace: &str) -> Result<Tokens> {
+    let mut tokens = Tokens::new();
+    for key in ctx.list_objects(namespace)? {
+        let token = ctx.read_object(namespace, &key)?;
+        if !token.is_empty() {
+            tokens.add_token(&token);
+        }
+    }
+    Ok(tokens)
 }
 
 struct FuzzHarness {
@@ -133,17 +327,62 @@ struct FuzzHarness {
     return_addr: u64,
     stack_addr: u64,
     lua_code: String,
+    /// Most operations the harness script may run
+    op_limit: u64,
+    /// Address whose execution counts as a solution, for reachability fuzzing
+    target_addr: Option<u64>,
+    /// Most instructions a run may execute before it counts as a hang
+    instruction_limit: u64,
+    /// Most bytes of guest memory the VM may allocate before a run counts as
+    /// running out of memory
+    memory_limit: u64,
 }
 
 impl FuzzHarness {
-    fn new(input_addr: u64, func_addr: u64, stack_addr: u64, lua_code: String) -> Self {
+    fn new(
+        input_addr: u64,
+        func_addr: u64,
+        return_addr: u64,
+        stack_addr: u64,
+        lua_code: String,
+        op_limit: u64,
+        target_addr: Option<u64>,
+    ) -> Self {
         Self {
             input_addr,
             func_addr,
-            return_addr: 0x1336,
+            return_addr,
             stack_addr,
             lua_code,
+            op_limit,
+            target_addr,
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+
+    fn with_instruction_limit(mut self, limit: u64) -> Self {
+        self.instruction_limit = limit;
+        self
+    }
+
+    fn with_memory_limit(mut self, limit: u64) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Caps the memory the VM may allocate, so that a target allocating
+    /// without bound stops with `VmExit::OutOfMemory` instead of exhausting
+    /// the host
+    fn limit_memory(&self, vm: &mut Vm) -> Result<()> {
+        let pages = (self.memory_limit / PAGE_SIZE) as usize;
+        if !vm.cpu.mem.set_capacity(pages) {
+            bail!(
+                "memory_limit of {} bytes is less than the VM already uses",
+                self.memory_limit
+            );
         }
+        Ok(())
     }
 
     fn setup_input(&self, vm: &mut Vm, input: &[u8]) -> Result<()> {
@@ -164,7 +403,7 @@ impl FuzzHarness {
         Ok(())
     }
 
-    fn setup_registers(&self, vm: &mut Vm) -> Result<()> {
+    fn setup_registers(&self, vm: &mut Vm, input_len: usize) -> Result<()> {
         // Set up base CPU state
         // println!("writing pc: 0x{:x}", self.func_addr);
         vm.cpu.write_pc(self.func_addr);
@@ -172,129 +411,362 @@ impl FuzzHarness {
         vm.cpu.write_reg(vm_reg(vm, "lr"), self.return_addr);
 
         // Run harness
-        run_rhai_harness(vm, &self.lua_code)?;
+        run_rhai_harness(vm, self, input_len)?;
 
         Ok(())
     }
+
+    /// Runs a single input through the VM and classifies how the run ended.
+    /// The caller is responsible for restoring the VM afterwards.
+    fn run(&self, vm: &mut Vm, input: &[u8]) -> ExitKind {
+        match self.execute(vm, input) {
+            Ok(exit) => self.exit_kind(vm, exit),
+            Err(kind) => kind,
+        }
+    }
+
+    /// Sets up the VM for an input and runs it, returning how the VM stopped,
+    /// or the outcome directly if the input couldn't be run
+    fn execute(&self, vm: &mut Vm, input: &[u8]) -> Result<VmExit, ExitKind> {
+        if input.len() < 8 {
+            return Err(ExitKind::Ok);
+        }
+
+        // Ignore potential errors in harness - just treat them as crashes
+        if self.setup_input(vm, input).is_err() {
+            log::error!("Failed to setup input");
+            return Err(ExitKind::Crash);
+        }
+        if let Err(e) = self.setup_registers(vm, input.len()) {
+            log::error!("Harness is broken: {}", e);
+            return Err(ExitKind::Crash);
+        }
+
+        Ok(self.run_until_return(vm))
+    }
+
+    /// Runs the VM until the function returns, stopping with
+    /// `VmExit::InstructionLimit` if it runs for too long
+    fn run_until_return(&self, vm: &mut Vm) -> VmExit {
+        vm.icount_limit = vm.cpu.icount.saturating_add(self.instruction_limit);
+        vm.run_until(self.return_addr)
+    }
+
+    /// Maps how the VM stopped to the outcome reported to the fuzzer
+    fn exit_kind(&self, vm: &Vm, exit: VmExit) -> ExitKind {
+        match exit {
+            // Reaching the target is what we're looking for, so it's reported
+            // like a crash to have the input stored as a solution
+            VmExit::Breakpoint if self.target_addr == Some(vm.cpu.read_pc()) => ExitKind::Crash,
+            // Jumping to the unmapped return address means the function returned
+            VmExit::UnhandledException((ExceptionCode::ExecViolation, addr))
+                if addr == self.return_addr =>
+            {
+                ExitKind::Ok
+            }
+            exit => exit_kind(exit),
+        }
+    }
 }
 
-pub fn fuzz(ctx: &StepContext) -> Result<()> {
-    // Get project configuration
-    let project = get_project(ctx)?;
-    let loader = project
-        .loader
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("no loader configuration"))?;
+/// Describes how the VM stopped, including the exception code and address of
+/// unhandled exceptions
+fn describe_exit(exit: &VmExit) -> String {
+    match exit {
+        VmExit::UnhandledException((code, addr)) => {
+            format!("UnhandledException {:?} at {:#x}", code, addr)
+        }
+        exit => format!("{:?}", exit),
+    }
+}
+
+/// Identifies an input in the log by its length and leading bytes
+fn describe_input(input: &[u8]) -> String {
+    let prefix: String = input
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{} bytes starting {}", input.len(), prefix)
+}
+
+/// Maps how the VM stopped to the outcome reported to the fuzzer
+fn exit_kind(exit: VmExit) -> ExitKind {
+    match exit {
+        VmExit::Running => ExitKind::Ok,
+        VmExit::InstructionLimit => ExitKind::Timeout,
+        VmExit::Breakpoint => ExitKind::Ok,
+        VmExit::Interrupted => ExitKind::Timeout,
+        VmExit::Halt => ExitKind::Crash,
+        VmExit::Killed => ExitKind::Crash,
+        VmExit::Deadlock => ExitKind::Crash,
+        VmExit::OutOfMemory => ExitKind::Oom,
+        VmExit::Unimplemented => ExitKind::Timeout,
+        VmExit::UnhandledException(_) => ExitKind::Crash,
+    }
+}
 
+fn build_harness(ctx: &StepContext, loader: &pap_api::LoaderConfig) -> Result<FuzzHarness> {
+    harness_from_args(|name| ctx.get_arg(name), loader)
+}
+
+/// Builds the harness from the arguments named in [`HARNESS_ARGS`], looked up
+/// with `get_arg`
+fn harness_from_args<'a>(
+    get_arg: impl Fn(&str) -> Option<&'a str>,
+    loader: &pap_api::LoaderConfig,
+) -> Result<FuzzHarness> {
     // Parse function address
-    let function = ctx
-        .get_arg("function")
-        .ok_or(anyhow!("Missing function arg"))?;
+    let function = get_arg("function").ok_or(anyhow!("Missing function arg"))?;
     let fuzz_func_addr = u64::from_str_radix(function.trim_start_matches("0x"), 16)?;
 
     // Setup harness
-    let harness_config = ctx
-        .get_arg("harness")
-        .ok_or(anyhow!("Missing harness arg"))?;
-    let input_addr = ctx
-        .get_arg("input_addr")
+    let harness_config = get_arg("harness").ok_or(anyhow!("Missing harness arg"))?;
+    let input_addr = get_arg("input_addr")
         .map(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16))
         .unwrap_or(Ok(0x4100_0000))?;
-    let harness = FuzzHarness::new(
+    let return_addr = get_arg("return_addr")
+        .map(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16))
+        .unwrap_or(Ok(DEFAULT_RETURN_ADDR))?;
+    let target_addr = get_arg("target_address")
+        .map(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16))
+        .transpose()?;
+    let op_limit = get_arg("harness_op_limit")
+        .map(|s| s.parse::<u64>())
+        .transpose()?
+        .unwrap_or(DEFAULT_HARNESS_OP_LIMIT);
+    let instruction_limit = get_arg("instruction_limit")
+        .map(|s| s.parse::<u64>())
+        .transpose()?
+        .unwrap_or(DEFAULT_INSTRUCTION_LIMIT);
+    let memory_limit = get_arg("memory_limit")
+        .map(|s| s.parse::<u64>())
+        .transpose()?
+        .unwrap_or(DEFAULT_MEMORY_LIMIT);
+    Ok(FuzzHarness::new(
         input_addr,
         fuzz_func_addr,
+        return_addr,
         loader.stack_address,
         harness_config.to_string(),
+        op_limit,
+        target_addr,
+    )
+    .with_instruction_limit(instruction_limit)
+    .with_memory_limit(memory_limit))
+}
+
+/// The emulator settings for a project, with its `vm` overrides applied over
+/// the defaults
+pub(crate) fn vm_config(project: &pap_api::Project) -> Config {
+    let vm = &project.vm;
+    Config {
+        enable_jit: vm.enable_jit.unwrap_or(false),
+        enable_jit_mem: vm.enable_jit_mem.unwrap_or(false),
+        enable_recompilation: vm.enable_recompilation.unwrap_or(false),
+        enable_shadow_stack: vm.enable_shadow_stack.unwrap_or(false),
+        ..Config::from_target_triple(project.arch.as_str())
+    }
+}
+
+fn map_binary(vm: &mut Vm, base_address: u64, binary: &[u8]) -> Result<()> {
+    let rwx = READ | WRITE | EXEC;
+    vm.cpu.mem.map_memory_len(
+        base_address,
+        binary.len() as u64,
+        Mapping {
+            perm: rwx,
+            value: 0,
+        },
     );
+    vm.cpu.mem.write_bytes(base_address, binary, rwx)?;
+    Ok(())
+}
 
-    // Configure and setup VM
-    let mut vm = {
-        let config = Config {
-            enable_jit: false,
-            enable_jit_mem: false,
-            enable_recompilation: false,
-            enable_shadow_stack: false,
-            ..icicle_vm::cpu::Config::from_target_triple(project.arch.as_str())
-        };
-        let mut vm = icicle_vm::build(&config)?;
+/// Finds the binary of a project among the files submitted with the pipeline
+fn project_binary<'a>(ctx: &'a StepContext, project: &pap_api::Project) -> Result<&'a [u8]> {
+    ctx.get_file(&project.binary).ok_or_else(|| {
+        anyhow!(
+            "missing binary file {} for project {}",
+            project.binary,
+            project.name
+        )
+    })
+}
 
-        // Load binary
-        let binary = ctx
-            .get_file(&project.binary)
-            .ok_or_else(|| anyhow!("missing binary file"))?;
-        let rwx = READ | WRITE | EXEC;
-        vm.cpu.mem.map_memory_len(
-            loader.base_address,
-            binary.len() as u64,
-            Mapping {
-                perm: rwx,
-                value: 0,
-            },
-        );
-        vm.cpu.mem.write_bytes(loader.base_address, binary, rwx)?;
+fn build_vm(
+    ctx: &StepContext,
+    project: &pap_api::Project,
+    loader: &pap_api::LoaderConfig,
+    harness: &FuzzHarness,
+) -> Result<Vm> {
+    let binary = project_binary(ctx, project)?;
+    build_vm_with(project, loader, binary, &get_libraries(ctx)?, harness)
+}
 
-        // Setup memory regions
+fn build_vm_with(
+    project: &pap_api::Project,
+    loader: &pap_api::LoaderConfig,
+    binary: &[u8],
+    libraries: &[Library],
+    harness: &FuzzHarness,
+) -> Result<Vm> {
+    let mut vm = icicle_vm::build(&vm_config(project))?;
+
+    // Load binary
+    map_binary(&mut vm, loader.base_address, binary)?;
+
+    // Load the libraries it calls into at their own base addresses
+    for Library { loader, binary, .. } in libraries {
+        map_binary(&mut vm, loader.base_address, binary)?;
+    }
+
+    // Setup memory regions
+    vm.cpu.mem.map_memory_len(
+        loader.stack_address - 0x500_0000,
+        0x500_0000,
+        Mapping {
+            perm: READ | WRITE,
+            value: 0,
+        },
+    );
+
+    // Initialize MMIO regions from project config
+    for region in &project.mmio {
         vm.cpu.mem.map_memory_len(
-            loader.stack_address - 0x500_0000,
-            0x500_0000,
+            region.address,
+            0x1000, // TODO: Make size configurable
             Mapping {
                 perm: READ | WRITE,
-                value: 0,
+                value: 1,
             },
         );
+        // TODO: Handle different MMIO handlers
+        vm.cpu.mem.write_u32(region.address, 0, READ | WRITE)?;
+    }
 
-        // Initialize MMIO regions from project config
-        for region in &project.mmio {
-            vm.cpu.mem.map_memory_len(
-                region.address,
-                0x1000, // TODO: Make size configurable
-                Mapping {
-                    perm: READ | WRITE,
-                    value: 1,
-                },
+    // Stop when the target is reached so the run can be classified
+    if let Some(target_addr) = harness.target_addr {
+        vm.add_breakpoint(target_addr);
+    }
+
+    harness.limit_memory(&mut vm)?;
+    Ok(vm)
+}
+
+/// Builds the VM and runs the harness setup once, without fuzzing.
+pub fn dry_run(ctx: &StepContext) -> Result<()> {
+    let project = get_project(ctx)?;
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no loader configuration"))?;
+
+    let harness = build_harness(ctx, loader)?;
+    let mut vm = build_vm(ctx, project, loader, &harness)?;
+
+    harness.setup_input(&mut vm, &[0; 8])?;
+    harness.setup_registers(&mut vm, 8)?;
+    ctx.log_at(LogLevel::Normal, "Harness ran successfully");
+
+    for io_field in ["input", "output", "solutions"] {
+        if let Some(namespace) = ctx.get_io(io_field) {
+            ctx.log_at(
+                LogLevel::Normal,
+                &format!("{} namespace: {}", io_field, namespace),
             );
-            // TODO: Handle different MMIO handlers
-            vm.cpu.mem.write_u32(region.address, 0, READ | WRITE)?;
         }
+    }
 
-        vm
-    };
+    Ok(())
+}
+
+pub fn fuzz(ctx: &StepContext) -> Result<()> {
+    // Get project configuration
+    let project = get_project(ctx)?;
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no loader configuration"))?;
+
+    let harness = build_harness(ctx, loader)?;
+    let stop_on_solution = ctx
+        .get_arg("stop_on_solution")
+        .map(|s| s.parse::<bool>())
+        .transpose()?
+        .unwrap_or(false);
+    // Submission rejects setting both, so this is whichever one was set
+    let verbose = ctx
+        .get_arg("verbose")
+        .map(|s| s.parse::<bool>())
+        .transpose()?
+        .unwrap_or(false)
+        || ctx.logs_at(LogLevel::Verbose);
+    let persistent_iters = ctx
+        .get_arg("persistent_iters")
+        .map(|s| s.parse::<u64>())
+        .transpose()?
+        .unwrap_or(1);
+    let checkpoint_interval = ctx
+        .get_arg("checkpoint_interval")
+        .map(|s| s.parse::<u64>())
+        .transpose()?
+        .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL);
+    let checkpoint_io = ctx.get_io("checkpoint");
+    let scheduler = ctx
+        .get_arg("scheduler")
+        .map(|s| s.parse::<CorpusScheduler>())
+        .transpose()?
+        .unwrap_or(CorpusScheduler::Queue);
+
+    // Configure and setup VM, reusing one an earlier step prepared the same way
+    let binary = project_binary(ctx, project)?;
+    let libraries = get_libraries(ctx)?;
+    let vm_key = VmKey::new(binary, project, loader, &libraries, harness.target_addr);
+    let (mut vm, cached) = vm_cache::checkout(&vm_key, || {
+        let mut vm = build_vm(ctx, project, loader, &harness)?;
+        register_afl_hit_counts_all(
+            &mut vm,
+            edges_map().cast::<u8>(),
+            EDGES_MAP_DEFAULT_SIZE as u32,
+        );
+        Ok(vm)
+    })?;
+    if cached {
+        ctx.log_at(LogLevel::Normal, "Reusing a VM prepared by an earlier step");
+        harness.limit_memory(&mut vm)?;
+    }
+    // The state before any harness code runs, which is what gets cached
+    let prepared = vm.snapshot();
+
+    // Fail the step on a broken harness script up front, rather than
+    // reporting every input as a crash
+    harness.setup_input(&mut vm, &[0; 8])?;
+    harness.setup_registers(&mut vm, 8)?;
 
     // Create harness closure with minimal error handling
     let mut harness_fn = |vm: &mut Vm, input: &BytesInput| -> ExitKind {
-        if input.len() < 8 {
-            return ExitKind::Ok;
+        if !verbose {
+            return harness.run(vm, input.bytes());
         }
 
-        // Ignore potential errors in harness - just treat them as crashes
-        if harness.setup_input(vm, input.bytes()).is_err() {
-            log::error!("Failed to setup input");
-            return ExitKind::Crash;
-        }
-        if let Err(e) = harness.setup_registers(vm) {
-            log::error!("Harness is broken: {}", e);
-            return ExitKind::Crash;
-        }
-
-        let vm_result = vm.run_until(harness.return_addr);
-
-        match vm_result {
-            VmExit::Running => ExitKind::Ok,
-            VmExit::InstructionLimit => ExitKind::Timeout,
-            VmExit::Breakpoint => ExitKind::Ok,
-            VmExit::Interrupted => ExitKind::Timeout,
-            VmExit::Halt => ExitKind::Crash,
-            VmExit::Killed => ExitKind::Crash,
-            VmExit::Deadlock => ExitKind::Crash,
-            VmExit::OutOfMemory => ExitKind::Oom,
-            VmExit::Unimplemented => ExitKind::Timeout,
-            VmExit::UnhandledException(e) => {
-                if matches!(e, (ExceptionCode::ExecViolation, 0x1336)) {
-                    ExitKind::Ok
-                } else {
-                    ExitKind::Crash
+        // Describe every run that doesn't end normally, which is too noisy
+        // to do by default
+        match harness.execute(vm, input.bytes()) {
+            Ok(exit) => {
+                let description = describe_exit(&exit);
+                let kind = harness.exit_kind(vm, exit);
+                if kind != ExitKind::Ok {
+                    ctx.log(&format!(
+                        "{:?} for input of {}: {}",
+                        kind,
+                        describe_input(input.bytes()),
+                        description
+                    ));
                 }
+                kind
             }
+            Err(kind) => kind,
         }
     };
 
@@ -309,65 +781,521 @@ pub fn fuzz(ctx: &StepContext) -> Result<()> {
         .to_string();
 
     // Setup LibAFL components
-    #[allow(static_mut_refs)]
     let edges_observer = unsafe {
         HitcountsMapObserver::new(ConstMapObserver::<_, EDGES_MAP_DEFAULT_SIZE>::new(
             "edges",
-            &mut EDGES_MAP,
+            &mut *edges_map(),
         ))
         .track_indices()
     };
-    register_afl_hit_counts_all(
-        &mut vm,
-        unsafe { EDGES_MAP.as_mut_ptr() },
-        EDGES_MAP_DEFAULT_SIZE as u32,
-    );
 
     let mut feedback = MaxMapFeedback::new(&edges_observer);
     let mut objective = CrashFeedback::new();
 
-    // Create corpus instances with appropriate namespaces
-    let main_corpus = SqlCorpus::new(output_io);
-    let solutions_corpus = SqlCorpus::new(solutions_io);
+    // Pick up where an earlier run left off if it left a checkpoint
+    let checkpoint = match checkpoint_io {
+        Some(namespace) => read_checkpoint(ctx, namespace)?,
+        None => None,
+    };
+    let mut state: FuzzState = match checkpoint {
+        Some(mut state) => {
+            ctx.log(&format!(
+                "Resuming from checkpoint after {} executions",
+                state.executions()
+            ));
+            // The corpora still name the namespaces and step of the run that
+            // wrote the checkpoint, but this run's testcases belong to it
+            state.corpus_mut().rebind(output_io, ctx.status.id)?;
+            state
+                .solutions_mut()
+                .rebind(solutions_io.clone(), ctx.status.id)?;
+            state
+        }
+        None => {
+            // Create corpus instances with appropriate namespaces
+            let main_corpus = SqlCorpus::new(output_io, ctx.status.id);
+            let solutions_corpus = SqlCorpus::new(solutions_io.clone(), ctx.status.id);
 
-    let mut state = StdState::new(
-        StdRand::with_seed(current_nanos()),
-        main_corpus,
-        solutions_corpus,
-        &mut feedback,
-        &mut objective,
-    )?;
+            StdState::new(
+                StdRand::with_seed(current_nanos()),
+                main_corpus,
+                solutions_corpus,
+                &mut feedback,
+                &mut objective,
+            )?
+        }
+    };
 
-    let mon = SimpleMonitor::new(|s| ctx.log(s));
+    // Solutions a checkpoint brought along were reported by the run that
+    // found them
+    let carried: HashSet<Vec<u8>> = state.solutions().keys().into_iter().collect();
+    let solutions_at_start = state.solutions().count();
+
+    // The token mutations only do anything with a dictionary to draw from
+    let dictionary_io = ctx.get_io("dictionary");
+    if let Some(namespace) = dictionary_io {
+        let tokens = load_dictionary(ctx, namespace)?;
+        ctx.log_at(
+            LogLevel::Normal,
+            &format!("Loaded {} dictionary tokens", tokens.len()),
+        );
+        state.add_metadata(tokens);
+    }
+
+    // Keep the latest stats as the step's machine-readable output. Quiet
+    // steps only log the periodic summaries.
+    let last_stats = RefCell::new(FuzzStats::default());
+    let mon = StatsMonitor::new(
+        |s| ctx.log(s),
+        |stats| {
+            if let Err(e) = ctx.set_stats(stats) {
+                log::error!("Failed to record fuzzer stats: {}", e);
+            }
+            *last_stats.borrow_mut() = stats.clone();
+        },
+    )
+    .heartbeats_only(!ctx.logs_at(LogLevel::Normal));
     let mut mgr = SimpleEventManager::new(mon);
-    let scheduler = QueueScheduler::new();
-    let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
-
-    let mut executor = super::executor::IcicleInProcessExecutor::new(
-        vm,
-        &mut harness_fn,
-        tuple_list!(edges_observer),
-        &mut fuzzer,
-        &mut state,
-        &mut mgr,
-    )?;
-
-    // Generate initial corpus
-    let mut generator = RandBytesGenerator::new(unsafe { NonZero::new_unchecked(128) });
-    state
-        .generate_initial_inputs(&mut fuzzer, &mut executor, &mut generator, &mut mgr, 64)
-        .expect("rut roh");
-
-    let mutator = StdScheduledMutator::new(havoc_mutations());
-    let mut stages = tuple_list!(StdMutationalStage::new(mutator));
-
-    loop {
+
+    // Each scheduler is its own type, and so needs its own fuzzer and
+    // executor. The loop is the same for all of them.
+    macro_rules! fuzz_with {
+        ($scheduler:expr, $stages:expr) => {{
+            let mut stages = $stages;
+            let mut fuzzer = StdFuzzer::new($scheduler, feedback, objective);
+
+            let mut executor = super::executor::IcicleInProcessExecutor::new(
+                vm,
+                &mut harness_fn,
+                tuple_list!(edges_observer),
+                &mut fuzzer,
+                &mut state,
+                &mut mgr,
+            )?
+            .with_persistent_iters(persistent_iters);
+
+            // Generate initial corpus, unless a checkpoint brought one along
+            if state.corpus().count() == 0 {
+                let mut generator = RandBytesGenerator::new(unsafe { NonZero::new_unchecked(128) });
+                state
+                    .generate_initial_inputs(
+                        &mut fuzzer,
+                        &mut executor,
+                        &mut generator,
+                        &mut mgr,
+                        64,
+                    )
+                    .expect("rut roh");
+            }
+
+            let mut last_checkpoint = Instant::now();
+            loop {
+                if ctx.is_cancelled() {
+                    break;
+                }
+                fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 10)?;
+
+                if stop_on_solution && state.solutions().count() > solutions_at_start {
+                    ctx.log_at(LogLevel::Normal, "Found a solution, stopping");
+                    break;
+                }
+
+                if let Some(namespace) = checkpoint_io {
+                    if last_checkpoint.elapsed() >= Duration::from_secs(checkpoint_interval) {
+                        write_checkpoint(ctx, namespace, &state)?;
+                        last_checkpoint = Instant::now();
+                    }
+                }
+            }
+
+            (executor.restores(), executor.into_vm())
+        }};
+    }
+
+    // Each mutator is its own type too, so picking one picks a scheduler
+    // separately for each
+    macro_rules! fuzz_with_mutator {
+        ($mutator:expr) => {{
+            let mutator = $mutator;
+            match scheduler {
+                CorpusScheduler::Queue => fuzz_with!(
+                    QueueScheduler::new(),
+                    tuple_list!(StdMutationalStage::new(mutator))
+                ),
+                // Power schedules weigh entries by how long they take to run,
+                // which the calibration stage measures before they're mutated
+                CorpusScheduler::Weighted => fuzz_with!(
+                    StdWeightedScheduler::with_schedule(
+                        &mut state,
+                        &edges_observer,
+                        Some(PowerSchedule::EXPLORE)
+                    ),
+                    tuple_list!(
+                        CalibrationStage::new(&feedback),
+                        StdPowerMutationalStage::<_, _, BytesInput, _, _>::new(mutator)
+                    )
+                ),
+                CorpusScheduler::Power => fuzz_with!(
+                    PowerQueueScheduler::new(&mut state, &edges_observer, PowerSchedule::FAST),
+                    tuple_list!(
+                        CalibrationStage::new(&feedback),
+                        StdPowerMutationalStage::<_, _, BytesInput, _, _>::new(mutator)
+                    )
+                ),
+            }
+        }};
+    }
+
+    // Without a dictionary the token mutations would only waste picks
+    let (restores, mut vm) = if dictionary_io.is_some() {
+        fuzz_with_mutator!(StdScheduledMutator::new(
+            havoc_mutations().merge(tokens_mutations())
+        ))
+    } else {
+        fuzz_with_mutator!(StdScheduledMutator::new(havoc_mutations()))
+    };
+
+    ctx.log(&format!(
+        "Ran {} inputs with {} snapshot restores",
+        state.executions(),
+        restores
+    ));
+
+    // Store testcases still buffered by the corpora
+    match checkpoint_io {
+        Some(namespace) => write_checkpoint(ctx, namespace, &state)?,
+        None => {
+            state.corpus().flush()?;
+            state.solutions().flush()?;
+        }
+    }
+
+    // Name each crasher so it can be fetched with `pap object get --key-hex`
+    let found: Vec<_> = state
+        .solutions()
+        .keys()
+        .into_iter()
+        .filter(|key| !carried.contains(key))
+        .collect();
+    let mut stats = last_stats.take();
+    stats.solution_keys = found.iter().map(|key| encode_input(key)).collect();
+    ctx.set_stats(&stats)?;
+
+    // Record how to replay each crash without rerunning the pipeline
+    if let Some(namespace) = ctx.get_io("reproducers") {
+        let written = write_reproducers(
+            ctx,
+            project,
+            &libraries,
+            &harness,
+            &mut vm,
+            &solutions_io,
+            &found,
+            namespace,
+        )?;
+        ctx.log_at(LogLevel::Normal, &format!("Wrote {} reproducers", written));
+    }
+
+    // Keep the VM for later steps with the same setup, without anything this
+    // step's harness left behind
+    vm.restore(&prepared);
+    vm_cache::checkin(vm_key, vm);
+
+    Ok(())
+}
+
+/// Runs an input, returning how the VM stopped and where if it crashed. The
+/// caller is responsible for restoring the VM afterwards.
+fn replay_crash(harness: &FuzzHarness, vm: &mut Vm, input: &[u8]) -> Result<Option<(String, u64)>> {
+    // Inputs this short are skipped by the fuzzer harness, so never crash
+    if input.len() < 8 {
+        return Ok(None);
+    }
+
+    harness.setup_input(vm, input)?;
+    harness.setup_registers(vm, input.len())?;
+    let exit = harness.run_until_return(vm);
+    let name = exit_name(&exit);
+    Ok((harness.exit_kind(vm, exit) == ExitKind::Crash).then(|| (name, vm.cpu.read_pc())))
+}
+
+/// Replays the inputs under `keys` in the solutions namespace, the ones this
+/// run found, writing a reproducer for each one that crashes under the same
+/// key in `namespace`
+#[allow(clippy::too_many_arguments)]
+fn write_reproducers(
+    ctx: &StepContext,
+    project: &pap_api::Project,
+    libraries: &[Library],
+    harness: &FuzzHarness,
+    vm: &mut Vm,
+    solutions_io: &str,
+    keys: &[Vec<u8>],
+    namespace: &str,
+) -> Result<usize> {
+    let args: BTreeMap<_, _> = HARNESS_ARGS
+        .iter()
+        .filter_map(|name| Some((name.to_string(), ctx.get_arg(name)?.to_string())))
+        .collect();
+    let mut checksums = BTreeMap::new();
+    checksums.insert(
+        project.binary.clone(),
+        binary_checksum(project_binary(ctx, project)?),
+    );
+    for library in libraries {
+        checksums.insert(
+            library.project.binary.clone(),
+            binary_checksum(library.binary),
+        );
+    }
+    let libraries: Vec<_> = libraries.iter().map(|l| l.project.clone()).collect();
+
+    let snapshot = vm.snapshot();
+    let mut written = 0;
+    for key in keys {
+        let input = ctx.read_object(solutions_io, key)?;
+        let crash = replay_crash(harness, vm, &input);
+        vm.restore(&snapshot);
+        let Some((exit, pc)) = crash? else {
+            continue;
+        };
+
+        let reproducer = Reproducer {
+            project: project.clone(),
+            libraries: libraries.clone(),
+            checksums: checksums.clone(),
+            args: args.clone(),
+            input: encode_input(&input),
+            exit,
+            pc,
+        };
+        ctx.write_object(namespace, key, &serde_json::to_vec_pretty(&reproducer)?)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Replays the crash recorded by every reproducer in the reproducers
+/// namespace, failing if any no longer crashes the same way. The projects the
+/// reproducers name must have the binaries they were recorded against
+/// submitted with the pipeline.
+pub fn reproduce(ctx: &StepContext) -> Result<()> {
+    let namespace = ctx
+        .get_io("reproducers")
+        .ok_or_else(|| anyhow::anyhow!("missing reproducers directory"))?;
+
+    let mut reproduced = 0;
+    let mut failed = 0;
+    for key in ctx.list_objects(namespace)? {
         if ctx.is_cancelled() {
             break;
         }
-        fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 10)?;
+
+        let reproducer: Reproducer = serde_json::from_slice(&ctx.read_object(namespace, &key)?)
+            .map_err(|e| anyhow!("invalid reproducer {:02x?}: {}", key, e))?;
+        let project = &reproducer.project;
+        let loader = project
+            .loader
+            .as_ref()
+            .ok_or_else(|| anyhow!("project {} has no loader configuration", project.name))?;
+        let libraries = reproducer
+            .libraries
+            .iter()
+            .map(|project| {
+                Ok(Library {
+                    project,
+                    loader: project.loader.as_ref().ok_or_else(|| {
+                        anyhow!("library {} has no loader configuration", project.name)
+                    })?,
+                    binary: project_binary(ctx, project)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // A different binary of the same name would replay to a misleading
+        // result rather than the recorded crash
+        let binaries = std::iter::once(project).chain(&reproducer.libraries);
+        for project in binaries {
+            let expected = reproducer.checksums.get(&project.binary).ok_or_else(|| {
+                anyhow!(
+                    "reproducer {:02x?} has no checksum for binary {}",
+                    key,
+                    project.binary
+                )
+            })?;
+            if binary_checksum(project_binary(ctx, project)?) != *expected {
+                bail!(
+                    "binary {} of project {} is not the one reproducer {:02x?} was recorded against",
+                    project.binary,
+                    project.name,
+                    key
+                );
+            }
+        }
+
+        let harness =
+            harness_from_args(|name| reproducer.args.get(name).map(String::as_str), loader)?;
+        let binary = project_binary(ctx, project)?;
+        let mut vm = build_vm_with(project, loader, binary, &libraries, &harness)?;
+
+        match replay_crash(&harness, &mut vm, &reproducer.input()?)? {
+            Some((exit, pc)) if exit == reproducer.exit && pc == reproducer.pc => {
+                ctx.log(&format!("Reproduced {:02x?}: {} at {:#x}", key, exit, pc));
+                reproduced += 1;
+            }
+            Some((exit, pc)) => {
+                ctx.log(&format!(
+                    "{:02x?} crashed with {} at {:#x}, expected {} at {:#x}",
+                    key, exit, pc, reproducer.exit, reproducer.pc
+                ));
+                failed += 1;
+            }
+            None => {
+                ctx.log(&format!("{:02x?} no longer crashes", key));
+                failed += 1;
+            }
+        }
     }
 
+    if failed > 0 {
+        bail!(
+            "{} of {} reproducers did not reproduce",
+            failed,
+            failed + reproduced
+        );
+    }
+    ctx.log(&format!("Reproduced {} crashes", reproduced));
+    Ok(())
+}
+
+/// Shrinks every input in the solutions namespace while it still crashes,
+/// storing the results under the same keys in the output namespace.
+pub fn minimize(ctx: &StepContext) -> Result<()> {
+    let project = get_project(ctx)?;
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no loader configuration"))?;
+
+    let harness = build_harness(ctx, loader)?;
+    let mut vm = build_vm(ctx, project, loader, &harness)?;
+    let snapshot = vm.snapshot();
+
+    let solutions_io = ctx
+        .get_io("solutions")
+        .ok_or_else(|| anyhow::anyhow!("missing solutions directory"))?;
+    let output_io = ctx
+        .get_io("output")
+        .ok_or_else(|| anyhow::anyhow!("missing output directory"))?;
+
+    let mut crashes = |input: &[u8]| {
+        let exit = harness.run(&mut vm, input);
+        vm.restore(&snapshot);
+        exit == ExitKind::Crash
+    };
+
+    for key in ctx.list_objects(solutions_io)? {
+        if ctx.is_cancelled() {
+            break;
+        }
+
+        // Removed corpus entries are left behind as empty objects
+        let input = ctx.read_object(solutions_io, &key)?;
+        if input.is_empty() {
+            continue;
+        }
+
+        if !crashes(&input) {
+            ctx.log(&format!("Skipping {:02x?}: input does not crash", key));
+            continue;
+        }
+
+        let minimized = minimize_input(&input, &mut crashes);
+        ctx.log(&format!(
+            "Minimized {:02x?}: {} -> {} bytes",
+            key,
+            input.len(),
+            minimized.len()
+        ));
+        ctx.write_object(output_io, &key, &minimized)?;
+    }
+
+    Ok(())
+}
+
+/// Registers captured for each crash site in a triage report
+const TRIAGE_REGISTERS: [&str; 7] = ["r0", "r1", "r2", "r3", "sp", "lr", "pc"];
+
+/// Key of the report object written by `triage`
+const TRIAGE_REPORT_KEY: &[u8] = b"report";
+
+/// Names how the VM stopped, leaving out details that vary between inputs
+/// crashing in the same place, such as the faulting address
+fn exit_name(exit: &VmExit) -> String {
+    match exit {
+        VmExit::UnhandledException((code, _)) => format!("UnhandledException({:?})", code),
+        exit => format!("{:?}", exit),
+    }
+}
+
+/// Replays every input in the solutions namespace and writes a JSON report of
+/// the distinct sites they crash at to the output namespace.
+pub fn triage(ctx: &StepContext) -> Result<()> {
+    let project = get_project(ctx)?;
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no loader configuration"))?;
+
+    let harness = build_harness(ctx, loader)?;
+    let mut vm = build_vm(ctx, project, loader, &harness)?;
+    let snapshot = vm.snapshot();
+
+    let solutions_io = ctx
+        .get_io("solutions")
+        .ok_or_else(|| anyhow::anyhow!("missing solutions directory"))?;
+    let output_io = ctx
+        .get_io("output")
+        .ok_or_else(|| anyhow::anyhow!("missing output directory"))?;
+
+    let mut report = TriageReport::default();
+    for key in ctx.list_objects(solutions_io)? {
+        if ctx.is_cancelled() {
+            break;
+        }
+
+        // Inputs the fuzzer harness ignores can't have crashed, and removed
+        // corpus entries are left behind as empty objects
+        let input = ctx.read_object(solutions_io, &key)?;
+        if input.len() < 8 {
+            continue;
+        }
+
+        harness.setup_input(&mut vm, &input)?;
+        harness.setup_registers(&mut vm, input.len())?;
+        let exit = harness.run_until_return(&mut vm);
+        let name = exit_name(&exit);
+
+        if harness.exit_kind(&vm, exit) == ExitKind::Crash {
+            let registers = TRIAGE_REGISTERS
+                .iter()
+                .map(|reg| (reg.to_string(), vm.cpu.read_reg(vm_reg(&vm, reg))))
+                .collect::<BTreeMap<_, _>>();
+            report.add(&key, name, vm.cpu.read_pc(), registers);
+        } else {
+            report.skip(&key);
+        }
+
+        vm.restore(&snapshot);
+    }
+
+    // The report is also the step's output, so it shows without fetching
+    // the object
+    let report = serde_json::to_vec_pretty(&report)?;
+    ctx.set_output_kind(OutputKind::Json);
+    ctx.log_raw(&report);
+    ctx.write_object(output_io, TRIAGE_REPORT_KEY, &report)?;
+
     Ok(())
 }
 
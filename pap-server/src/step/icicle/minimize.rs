@@ -0,0 +1,42 @@
+use std::cmp::min;
+
+/// Shrinks an input for as long as `crashes` keeps returning true for it.
+///
+/// Chunks of decreasing size are removed first, then each remaining byte is
+/// zeroed where that doesn't matter. `crashes` must hold for `input` itself.
+pub(crate) fn minimize_input(input: &[u8], mut crashes: impl FnMut(&[u8]) -> bool) -> Vec<u8> {
+    let mut current = input.to_vec();
+
+    let mut chunk = current.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < current.len() {
+            let end = min(start + chunk, current.len());
+            let mut candidate = current[..start].to_vec();
+            candidate.extend_from_slice(&current[end..]);
+
+            // Try the same position again after a successful removal, since
+            // it now holds the bytes that followed the removed chunk
+            if crashes(&candidate) {
+                current = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+
+    for i in 0..current.len() {
+        if current[i] == 0 {
+            continue;
+        }
+
+        let mut candidate = current.clone();
+        candidate[i] = 0;
+        if crashes(&candidate) {
+            current = candidate;
+        }
+    }
+
+    current
+}
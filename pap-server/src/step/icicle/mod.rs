@@ -1,10 +1,16 @@
 mod executor;
-mod fuzzer;
+pub(crate) mod fuzzer;
+pub(crate) mod minimize;
+mod monitor;
+pub(crate) mod reproducer;
 mod sqlcorpus;
+pub(crate) mod triage;
+pub(crate) mod vm_cache;
 
-use super::{StepContext, StepExecutor};
+use super::{parse_list, StepContext, StepExecutor, StepRequirements};
 use anyhow::{anyhow, bail};
 use fuzzer::fuzz;
+use pap_api::{ArgSchema, ArgType, Config, Context, Step};
 
 pub struct IcicleFuzzerExecutor;
 
@@ -14,58 +20,595 @@ impl StepExecutor for IcicleFuzzerExecutor {
     }
 
     fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
-        // Validate required arguments
-        let project_name = ctx
-            .get_arg("project")
-            .ok_or(anyhow::anyhow!("missing `project` argument"))?;
+        check_config(ctx, &FUZZER_IO)?;
 
-        // Find and validate the target project
-        let project = ctx.pipeline_status.config.projects
-            .iter()
-            .find(|p| p.name == project_name)
-            .ok_or_else(|| anyhow!("project not found: {}", project_name))?;
+        fuzz(ctx)?;
+
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        check_config(ctx, &FUZZER_IO)?;
+
+        fuzzer::dry_run(ctx)
+    }
 
-        // Validate project configuration
-        if project.binary.is_empty() {
-            bail!("project {} has no binary specified", project_name);
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec![
+                "project".to_string(),
+                "function".to_string(),
+                "harness".to_string(),
+            ],
+            io: FUZZER_IO.iter().map(|io| io.to_string()).collect(),
+            default_io: vec!["output".to_string(), "solutions".to_string()],
+            project_args: vec!["project".to_string()],
         }
+    }
+
+    fn validate(&self, step: &Step, context: &Context) -> anyhow::Result<()> {
+        // `verbose` is what `log_level: verbose` replaced, so the two could
+        // only disagree
+        if step.args.contains_key("verbose") && step.args.contains_key("log_level") {
+            bail!(
+                "step {} sets both verbose and log_level; use log_level: verbose instead",
+                step.name
+            );
+        }
+        check_layout(step, context)
+    }
+
+    /// Prepared VMs are cached for later steps, but can't leave the thread
+    /// that made them, so each pinned thread keeps its own
+    fn pinned_to_thread(&self) -> bool {
+        true
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        let mut args = harness_arg_schema();
+        args.push(ArgSchema {
+            name: "stop_on_solution".to_string(),
+            arg_type: ArgType::Boolean,
+            required: false,
+            default: Some("false".to_string()),
+            description: "Stop fuzzing once the run stores a solution: an input that \
+                          crashes, or that reaches `target_address` if one is given. \
+                          The hex keys of the solutions the run finds are listed in \
+                          the step's stats as `solution_keys`. If `reproducers` is \
+                          set, each is also replayed and recorded there for the \
+                          `reproduce` step"
+                .to_string(),
+        });
+        args.push(ArgSchema {
+            name: "verbose".to_string(),
+            arg_type: ArgType::Boolean,
+            required: false,
+            default: Some("false".to_string()),
+            description: "Deprecated, use `log_level: verbose` instead. Log how the VM \
+                          stopped for every input that doesn't return"
+                .to_string(),
+        });
+        args.push(ArgSchema {
+            name: "log_level".to_string(),
+            arg_type: ArgType::String,
+            required: false,
+            default: Some("normal".to_string()),
+            description: "How much to log: `quiet` for periodic summaries only, `normal`, \
+                          or `verbose` to also log how every input ran"
+                .to_string(),
+        });
+        args.push(ArgSchema {
+            name: "persistent_iters".to_string(),
+            arg_type: ArgType::Integer,
+            required: false,
+            default: Some("1".to_string()),
+            description: "Inputs run between snapshot restores, for state-clean harnesses"
+                .to_string(),
+        });
+        args.push(ArgSchema {
+            name: "checkpoint_interval".to_string(),
+            arg_type: ArgType::Integer,
+            required: false,
+            default: Some(fuzzer::DEFAULT_CHECKPOINT_INTERVAL.to_string()),
+            description: "Seconds between checkpoints of the fuzzer's state to `checkpoint`"
+                .to_string(),
+        });
+        args.push(ArgSchema {
+            name: "scheduler".to_string(),
+            arg_type: ArgType::String,
+            required: false,
+            default: Some("queue".to_string()),
+            description: "How corpus entries are picked for mutation: `queue`, `weighted`, \
+                          or `power`"
+                .to_string(),
+        });
+        args
+    }
+}
+
+/// IO fields the fuzzer step requires. It also reads seed tokens from
+/// `dictionary`, keeps its state in `checkpoint` and writes a reproducer for
+/// each new solution to `reproducers` when those are set.
+const FUZZER_IO: [&str; 3] = ["input", "output", "solutions"];
+
+/// Shrinks crashing inputs found by the fuzzer while they still crash
+pub struct IcicleMinimizeExecutor;
+
+/// IO fields of the minimize step
+const MINIMIZE_IO: [&str; 2] = ["solutions", "output"];
+
+impl StepExecutor for IcicleMinimizeExecutor {
+    fn name(&self) -> String {
+        "minimize".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        check_config(ctx, &MINIMIZE_IO)?;
+
+        fuzzer::minimize(ctx)
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec![
+                "project".to_string(),
+                "function".to_string(),
+                "harness".to_string(),
+            ],
+            io: MINIMIZE_IO.iter().map(|io| io.to_string()).collect(),
+            default_io: vec!["output".to_string()],
+            project_args: vec!["project".to_string()],
+        }
+    }
+
+    fn validate(&self, step: &Step, context: &Context) -> anyhow::Result<()> {
+        check_layout(step, context)
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        harness_arg_schema()
+    }
+}
+
+/// Replays crashing inputs and reports the distinct sites they crash at, as
+/// JSON in both the step's output and the `report` object in `output`
+pub struct IcicleTriageExecutor;
+
+/// IO fields of the triage step
+const TRIAGE_IO: [&str; 2] = ["solutions", "output"];
+
+impl StepExecutor for IcicleTriageExecutor {
+    fn name(&self) -> String {
+        "triage".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        check_config(ctx, &TRIAGE_IO)?;
+
+        fuzzer::triage(ctx)
+    }
 
-        // Validate architecture (must be ARM/Thumb based)
-        if !project.arch.starts_with("thumb") && !project.arch.starts_with("arm") {
-            bail!("project {} has unsupported architecture: {}", project_name, project.arch);
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec![
+                "project".to_string(),
+                "function".to_string(),
+                "harness".to_string(),
+            ],
+            io: TRIAGE_IO.iter().map(|io| io.to_string()).collect(),
+            default_io: vec!["output".to_string()],
+            project_args: vec!["project".to_string()],
         }
+    }
 
-        // Validate loader configuration
-        let loader = project.loader
-            .as_ref()
-            .ok_or_else(|| anyhow!("project {} has no loader configuration", project_name))?;
+    fn validate(&self, step: &Step, context: &Context) -> anyhow::Result<()> {
+        check_layout(step, context)
+    }
 
-        if loader.stack_address == 0 {
-            bail!("project {} has invalid stack address: 0", project_name);
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        harness_arg_schema()
+    }
+}
+
+/// Replays crashes from the reproducers the fuzzer writes to its
+/// `reproducers` namespace
+pub struct IcicleReproduceExecutor;
+
+/// IO fields of the reproduce step
+const REPRODUCE_IO: [&str; 1] = ["reproducers"];
+
+impl StepExecutor for IcicleReproduceExecutor {
+    fn name(&self) -> String {
+        "reproduce".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        fuzzer::reproduce(ctx)
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            io: REPRODUCE_IO.iter().map(|io| io.to_string()).collect(),
+            ..Default::default()
         }
+    }
+}
 
-        // Continue with existing validations
-        let function = ctx
-            .get_arg("function")
-            .ok_or(anyhow::anyhow!("missing `function` argument"))?;
+/// Arguments shared by the steps that run inputs through a harnessed function
+fn harness_arg_schema() -> Vec<ArgSchema> {
+    vec![
+        ArgSchema {
+            name: "project".to_string(),
+            arg_type: ArgType::String,
+            required: true,
+            default: None,
+            description: "Name of the project to run".to_string(),
+        },
+        ArgSchema {
+            name: "function".to_string(),
+            arg_type: ArgType::Address,
+            required: true,
+            default: None,
+            description: "Address of the function inputs are run through".to_string(),
+        },
+        ArgSchema {
+            name: "harness".to_string(),
+            arg_type: ArgType::String,
+            required: true,
+            default: None,
+            description: "Rhai script that sets up the VM before each run".to_string(),
+        },
+        ArgSchema {
+            name: "harness_op_limit".to_string(),
+            arg_type: ArgType::Integer,
+            required: false,
+            default: Some(fuzzer::DEFAULT_HARNESS_OP_LIMIT.to_string()),
+            description: "Most operations the harness script may run before it is aborted"
+                .to_string(),
+        },
+        ArgSchema {
+            name: "libraries".to_string(),
+            arg_type: ArgType::List,
+            required: false,
+            default: None,
+            description: "Projects whose binaries are mapped alongside the project's".to_string(),
+        },
+        ArgSchema {
+            name: "instruction_limit".to_string(),
+            arg_type: ArgType::Integer,
+            required: false,
+            default: Some(fuzzer::DEFAULT_INSTRUCTION_LIMIT.to_string()),
+            description: "Most instructions a run may execute before it counts as a timeout"
+                .to_string(),
+        },
+        ArgSchema {
+            name: "memory_limit".to_string(),
+            arg_type: ArgType::Integer,
+            required: false,
+            default: Some(fuzzer::DEFAULT_MEMORY_LIMIT.to_string()),
+            description: "Most bytes a run may allocate before it counts as out of memory"
+                .to_string(),
+        },
+        ArgSchema {
+            name: "input_addr".to_string(),
+            arg_type: ArgType::Address,
+            required: false,
+            default: Some("0x41000000".to_string()),
+            description: "Address the input is mapped at".to_string(),
+        },
+        ArgSchema {
+            name: "target_address".to_string(),
+            arg_type: ArgType::Address,
+            required: false,
+            default: None,
+            description: "Address whose execution counts as a solution".to_string(),
+        },
+        ArgSchema {
+            name: "return_addr".to_string(),
+            arg_type: ArgType::Address,
+            required: false,
+            default: Some(format!("{:#x}", fuzzer::DEFAULT_RETURN_ADDR)),
+            description: "Unmapped address the function returns to".to_string(),
+        },
+    ]
+}
+
+fn check_config(ctx: &StepContext, required_io: &[&str]) -> anyhow::Result<()> {
+    // Validate required arguments
+    let project_name = ctx
+        .get_arg("project")
+        .ok_or(anyhow::anyhow!("missing `project` argument"))?;
 
-        let _function_addr = u64::from_str_radix(function.trim_start_matches("0x"), 16)
-            .map_err(|_| anyhow::anyhow!("invalid function address: {}", function))?;
+    // Find and validate the target project
+    let project = ctx
+        .pipeline_status
+        .config
+        .projects
+        .iter()
+        .find(|p| p.name == project_name)
+        .ok_or_else(|| anyhow!("project not found: {}", project_name))?;
 
-        ctx
-            .get_arg("harness")
-            .ok_or(anyhow::anyhow!("missing `harness` argument"))?;
+    // Validate project configuration
+    if project.binary.is_empty() {
+        bail!("project {} has no binary specified", project_name);
+    }
 
-        // Validate required IO configuration
-        let required_io = ["input", "output", "solutions"];
-        for io_field in required_io {
-            if !ctx.has_io(io_field) {
-                bail!("missing required IO field: {}", io_field);
+    // Validate architecture (must be ARM/Thumb based)
+    if !project.arch.starts_with("thumb") && !project.arch.starts_with("arm") {
+        bail!(
+            "project {} has unsupported architecture: {}",
+            project_name,
+            project.arch
+        );
+    }
+
+    // Validate loader configuration
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow!("project {} has no loader configuration", project_name))?;
+
+    if loader.stack_address == 0 {
+        bail!("project {} has invalid stack address: 0", project_name);
+    }
+
+    // Continue with existing validations
+    let function = ctx
+        .get_arg("function")
+        .ok_or(anyhow::anyhow!("missing `function` argument"))?;
+
+    let _function_addr = u64::from_str_radix(function.trim_start_matches("0x"), 16)
+        .map_err(|_| anyhow::anyhow!("invalid function address: {}", function))?;
+
+    if let Some(target) = ctx.get_arg("target_address") {
+        u64::from_str_radix(target.trim_start_matches("0x"), 16)
+            .map_err(|_| anyhow::anyhow!("invalid target address: {}", target))?;
+    }
+
+    let return_addr = match ctx.get_arg("return_addr") {
+        Some(addr) => u64::from_str_radix(addr.trim_start_matches("0x"), 16)
+            .map_err(|_| anyhow::anyhow!("invalid return address: {}", addr))?,
+        None => fuzzer::DEFAULT_RETURN_ADDR,
+    };
+    let libraries = get_libraries(ctx)?;
+    for library in &libraries {
+        if library.project.arch != project.arch {
+            bail!(
+                "library {} has architecture {}, but project {} has {}",
+                library.project.name,
+                library.project.arch,
+                project_name,
+                project.arch
+            );
+        }
+    }
+
+    let binary_len = ctx.get_file(&project.binary).map_or(0, |b| b.len() as u64);
+    let input_addr = parse_input_addr(ctx.get_arg("input_addr"))?;
+    let regions = memory_regions(project, loader, binary_len, input_addr, &libraries);
+    check_overlaps(project, &regions)?;
+    check_return_addr(&regions, return_addr)?;
+
+    for flag in ["stop_on_solution", "verbose"] {
+        if let Some(value) = ctx.get_arg(flag) {
+            value
+                .parse::<bool>()
+                .map_err(|_| anyhow::anyhow!("invalid {} value: {}", flag, value))?;
+        }
+    }
+
+    for count in [
+        "persistent_iters",
+        "harness_op_limit",
+        "instruction_limit",
+        "memory_limit",
+        "checkpoint_interval",
+    ] {
+        if let Some(value) = ctx.get_arg(count) {
+            match value.parse::<u64>() {
+                Ok(n) if n > 0 => {}
+                _ => bail!("invalid {} value: {}", count, value),
             }
         }
+    }
 
-        fuzz(ctx)?;
+    if let Some(scheduler) = ctx.get_arg("scheduler") {
+        scheduler.parse::<fuzzer::CorpusScheduler>()?;
+    }
 
-        Ok(())
+    ctx.get_arg("harness")
+        .ok_or(anyhow::anyhow!("missing `harness` argument"))?;
+
+    // Validate required IO configuration
+    for io_field in required_io {
+        if !ctx.has_io(io_field) {
+            bail!("missing required IO field: {}", io_field);
+        }
     }
+
+    Ok(())
+}
+
+/// Checks a step's memory layout when its pipeline is submitted, so that
+/// overlapping regions are reported as a configuration error instead of
+/// failing the step. Problems that don't involve the layout, such as a
+/// missing loader, are left for the step to report when it runs.
+fn check_layout(step: &Step, context: &Context) -> anyhow::Result<()> {
+    let config = &context.config;
+    let Some(project) = step
+        .args
+        .get("project")
+        .and_then(|name| config.projects.iter().find(|p| &p.name == name))
+    else {
+        return Ok(());
+    };
+    let Some(loader) = &project.loader else {
+        return Ok(());
+    };
+
+    let get_file = |name: &str| context.files.get(name).map(Vec::as_slice);
+    let libraries: Vec<_> = step
+        .args
+        .get("libraries")
+        .map_or(Vec::new(), |value| parse_list(value))
+        .into_iter()
+        .filter_map(|name| find_library(config, get_file, name).ok())
+        .collect();
+
+    let binary_len = get_file(&project.binary).map_or(0, |b| b.len() as u64);
+    let input_addr = parse_input_addr(step.args.get("input_addr").map(String::as_str))?;
+    let regions = memory_regions(project, loader, binary_len, input_addr, &libraries);
+    check_overlaps(project, &regions)
+}
+
+fn parse_input_addr(addr: Option<&str>) -> anyhow::Result<u64> {
+    match addr {
+        Some(addr) => u64::from_str_radix(addr.trim_start_matches("0x"), 16)
+            .map_err(|_| anyhow::anyhow!("invalid input address: {}", addr)),
+        None => Ok(0x4100_0000),
+    }
+}
+
+/// A range of guest memory mapped when the VM is built
+struct MemoryRegion {
+    name: String,
+    start: u64,
+    len: u64,
+}
+
+impl MemoryRegion {
+    fn end(&self) -> u64 {
+        self.start.saturating_add(self.len)
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end()).contains(&addr)
+    }
+
+    fn overlaps(&self, other: &MemoryRegion) -> bool {
+        self.start < other.end() && other.start < self.end()
+    }
+}
+
+impl std::fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} region at {:#x}..{:#x}",
+            self.name,
+            self.start,
+            self.end()
+        )
+    }
+}
+
+/// Lists the regions mapped for a project, mirroring the mappings made when
+/// the VM is built
+fn memory_regions(
+    project: &pap_api::Project,
+    loader: &pap_api::LoaderConfig,
+    binary_len: u64,
+    input_addr: u64,
+    libraries: &[Library],
+) -> Vec<MemoryRegion> {
+    let region = |name: &str, start, len| MemoryRegion {
+        name: name.to_string(),
+        start,
+        len,
+    };
+
+    let mut regions = vec![
+        region("binary", loader.base_address, binary_len),
+        region(
+            "stack",
+            loader.stack_address.saturating_sub(0x500_0000),
+            0x500_0000,
+        ),
+        region("input", input_addr, 0x1000),
+    ];
+    regions.extend(
+        project
+            .mmio
+            .iter()
+            .map(|entry| region("MMIO", entry.address, 0x1000)),
+    );
+    regions.extend(libraries.iter().map(|library| {
+        region(
+            &format!("library {}", library.project.name),
+            library.loader.base_address,
+            library.binary.len() as u64,
+        )
+    }));
+    regions
+}
+
+/// Checks that no two mapped regions overlap, since mapping one silently
+/// replaces whatever part of the other was already there
+fn check_overlaps(project: &pap_api::Project, regions: &[MemoryRegion]) -> anyhow::Result<()> {
+    for (i, a) in regions.iter().enumerate() {
+        for b in &regions[i + 1..] {
+            // A peripheral's registers usually share a page, which is mapped
+            // the same way for each of them
+            if a.name == "MMIO" && b.name == "MMIO" {
+                continue;
+            }
+            if a.overlaps(b) {
+                bail!("project {}: the {} overlaps the {}", project.name, a, b);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the return address isn't mapped, since returning there must stop
+/// the VM instead of running whatever is at that address
+fn check_return_addr(regions: &[MemoryRegion], return_addr: u64) -> anyhow::Result<()> {
+    if let Some(region) = regions.iter().find(|region| region.contains(return_addr)) {
+        bail!(
+            "return address {:#x} overlaps the {} region at {:#x}",
+            return_addr,
+            region.name,
+            region.start
+        );
+    }
+    Ok(())
+}
+
+/// A project whose binary is mapped into the VM alongside the harnessed one,
+/// such as a shared library it calls into
+pub(crate) struct Library<'a> {
+    pub project: &'a pap_api::Project,
+    pub loader: &'a pap_api::LoaderConfig,
+    pub binary: &'a [u8],
+}
+
+/// Resolves the projects named by the `libraries` argument
+pub(crate) fn get_libraries<'a>(ctx: &'a StepContext) -> anyhow::Result<Vec<Library<'a>>> {
+    ctx.get_list_arg("libraries")
+        .into_iter()
+        .map(|name| find_library(&ctx.pipeline_status.config, |file| ctx.get_file(file), name))
+        .collect()
+}
+
+fn find_library<'a>(
+    config: &'a Config,
+    get_file: impl Fn(&str) -> Option<&'a [u8]>,
+    name: &str,
+) -> anyhow::Result<Library<'a>> {
+    let project = config
+        .projects
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| anyhow!("library project not found: {}", name))?;
+    let loader = project
+        .loader
+        .as_ref()
+        .ok_or_else(|| anyhow!("library {} has no loader configuration", name))?;
+    let binary = get_file(&project.binary)
+        .ok_or_else(|| anyhow!("missing binary file for library {}", name))?;
+    Ok(Library {
+        project,
+        loader,
+        binary,
+    })
 }
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use libafl::monitors::{ClientStats, Monitor, SimpleMonitor};
+use libafl_bolts::ClientId;
+use serde::Serialize;
+
+/// A snapshot of the fuzzer's progress
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct FuzzStats {
+    pub corpus_size: u64,
+    pub objectives: u64,
+    pub executions: u64,
+    pub execs_per_sec: f64,
+    /// Hex keys of the solutions this run found, filled in once it finishes
+    pub solution_keys: Vec<String>,
+}
+
+/// Event `SimpleMonitor` reports periodically, rather than as things happen
+const HEARTBEAT_EVENT: &str = "Client Heartbeat";
+
+/// Logs progress like `SimpleMonitor`, and also hands the stats behind each
+/// update to `on_stats`
+pub(crate) struct StatsMonitor<F, G>
+where
+    F: FnMut(&str),
+    G: FnMut(&FuzzStats),
+{
+    inner: SimpleMonitor<F>,
+    on_stats: G,
+    /// Log only the periodic heartbeats, not every new testcase or objective
+    heartbeats_only: bool,
+}
+
+impl<F, G> StatsMonitor<F, G>
+where
+    F: FnMut(&str),
+    G: FnMut(&FuzzStats),
+{
+    pub(crate) fn new(print_fn: F, on_stats: G) -> Self {
+        Self {
+            inner: SimpleMonitor::new(print_fn),
+            on_stats,
+            heartbeats_only: false,
+        }
+    }
+
+    /// Sets whether only the periodic heartbeats are logged
+    pub(crate) fn heartbeats_only(mut self, heartbeats_only: bool) -> Self {
+        self.heartbeats_only = heartbeats_only;
+        self
+    }
+}
+
+impl<F, G> Monitor for StatsMonitor<F, G>
+where
+    F: FnMut(&str),
+    G: FnMut(&FuzzStats),
+{
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        self.inner.client_stats_mut()
+    }
+
+    fn client_stats(&self) -> &[ClientStats] {
+        self.inner.client_stats()
+    }
+
+    fn start_time(&self) -> Duration {
+        self.inner.start_time()
+    }
+
+    fn set_start_time(&mut self, time: Duration) {
+        self.inner.set_start_time(time)
+    }
+
+    fn display(&mut self, event_msg: &str, sender_id: ClientId) {
+        if !self.heartbeats_only || event_msg == HEARTBEAT_EVENT {
+            self.inner.display(event_msg, sender_id);
+        }
+
+        let stats = FuzzStats {
+            corpus_size: self.corpus_size(),
+            objectives: self.objective_size(),
+            executions: self.total_execs(),
+            execs_per_sec: self.execs_per_sec(),
+            solution_keys: Vec::new(),
+        };
+        (self.on_stats)(&stats);
+    }
+}
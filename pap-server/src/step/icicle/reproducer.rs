@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Arguments of the fuzzer step that decide how an input runs, which a
+/// reproducer keeps so the crash replays the same way
+pub(crate) const HARNESS_ARGS: [&str; 8] = [
+    "function",
+    "harness",
+    "input_addr",
+    "return_addr",
+    "target_address",
+    "harness_op_limit",
+    "instruction_limit",
+    "memory_limit",
+];
+
+/// Everything needed to replay a crashing input outside the pipeline that
+/// found it, apart from the project binaries themselves
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Reproducer {
+    /// The project the input ran against
+    pub project: pap_api::Project,
+    /// Projects mapped alongside it, such as shared libraries
+    pub libraries: Vec<pap_api::Project>,
+    /// Checksum of the binary of the project and of each library, by file
+    /// name, so that replaying against a rebuilt binary fails clearly
+    pub checksums: BTreeMap<String, String>,
+    /// The fuzzer step's values for the arguments in [`HARNESS_ARGS`]
+    pub args: BTreeMap<String, String>,
+    /// The crashing input, hex-encoded
+    pub input: String,
+    /// How the VM stopped, e.g. `UnhandledException(ReadUnmapped)`
+    pub exit: String,
+    /// Program counter when the VM stopped
+    pub pc: u64,
+}
+
+impl Reproducer {
+    /// Decodes the crashing input
+    pub(crate) fn input(&self) -> Result<Vec<u8>> {
+        pap_api::parse_hex_key(&self.input).map_err(|e| anyhow!("invalid reproducer input: {}", e))
+    }
+}
+
+/// Hex-encoded SHA-256 of a project binary, for [`Reproducer::checksums`]
+pub(crate) fn binary_checksum(binary: &[u8]) -> String {
+    encode_input(&Sha256::digest(binary))
+}
+
+/// Hex-encodes an input for a reproducer
+pub(crate) fn encode_input(input: &[u8]) -> String {
+    input.iter().map(|b| format!("{:02x}", b)).collect()
+}
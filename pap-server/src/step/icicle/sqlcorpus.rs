@@ -4,12 +4,18 @@ use libafl::{
     Error,
 };
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, collections::HashSet};
-use tokio::runtime::Handle;
+use std::{cell::RefCell, collections::HashSet, time::Duration};
+
+use crate::step::object_batch::ObjectBatch;
+
+/// Number of testcase writes buffered before they are stored together
+const BATCH_ENTRIES: usize = 64;
+/// Longest a testcase write stays buffered before it is stored
+const BATCH_AGE: Duration = Duration::from_secs(1);
 
 #[derive(Serialize, Deserialize)]
 pub struct SqlCorpus {
-    namespace: String,
+    objects: ObjectBatch,
     current: Option<CorpusId>,
     cached_ids: HashSet<CorpusId>,
     disabled_ids: HashSet<CorpusId>,
@@ -17,9 +23,9 @@ pub struct SqlCorpus {
 }
 
 impl SqlCorpus {
-    pub fn new(namespace: String) -> Self {
+    pub fn new(namespace: String, step_id: u32) -> Self {
         Self {
-            namespace,
+            objects: ObjectBatch::new(namespace, Some(step_id), BATCH_ENTRIES, BATCH_AGE),
             current: None,
             cached_ids: HashSet::new(),
             disabled_ids: HashSet::new(),
@@ -32,16 +38,62 @@ impl SqlCorpus {
     }
 
     fn write_object(&self, key: &[u8], data: &[u8]) -> Result<(), Error> {
-        Handle::current()
-            .block_on(async { crate::queries::put_object(&self.namespace, key, data).await })
+        self.objects
+            .put(key, data)
             .map_err(|e| Error::illegal_state(format!("Failed to store testcase: {}", e)))
     }
 
     fn read_object(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
-        Handle::current()
-            .block_on(async { crate::queries::get_object(&self.namespace, key).await })
+        self.objects
+            .get(key)
             .map_err(|e| Error::illegal_state(format!("Failed to load testcase: {}", e)))
     }
+
+    /// Keys of the enabled testcases, in the order they were added
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        let mut ids: Vec<_> = self
+            .cached_ids
+            .difference(&self.disabled_ids)
+            .map(|id| id.0)
+            .collect();
+        ids.sort_unstable();
+        ids.into_iter().map(|id| self.make_key(id)).collect()
+    }
+
+    /// Moves the corpus to `namespace`, attributing its writes to `step_id`
+    /// from now on, e.g. for a corpus restored from a checkpoint written by
+    /// another step. Testcases are copied over when the namespace changes, so
+    /// that they're found there.
+    pub fn rebind(&mut self, namespace: String, step_id: u32) -> Result<(), Error> {
+        let previous = std::mem::replace(
+            &mut self.objects,
+            ObjectBatch::new(namespace, Some(step_id), BATCH_ENTRIES, BATCH_AGE),
+        );
+        if previous.namespace() == self.objects.namespace() {
+            return Ok(());
+        }
+
+        let mut ids: Vec<_> = self.cached_ids.iter().map(|id| id.0).collect();
+        ids.sort_unstable();
+        for id in ids {
+            let key = self.make_key(id);
+            let input = match self.testcases[id].borrow().input() {
+                Some(input) => input.bytes().to_vec(),
+                None => previous
+                    .get(&key)
+                    .map_err(|e| Error::illegal_state(format!("Failed to load testcase: {}", e)))?,
+            };
+            self.write_object(&key, &input)?;
+        }
+        self.flush()
+    }
+
+    /// Store any buffered testcases
+    pub fn flush(&self) -> Result<(), Error> {
+        self.objects
+            .flush()
+            .map_err(|e| Error::illegal_state(format!("Failed to store testcases: {}", e)))
+    }
 }
 
 impl Corpus for SqlCorpus {
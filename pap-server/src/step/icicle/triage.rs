@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::step::icicle::reproducer::encode_input;
+
+/// Summary of the distinct places a set of crashing inputs fail at
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct TriageReport {
+    /// Number of crashing inputs replayed
+    pub crashes: usize,
+    /// Distinct crash sites, in the order they were first seen
+    pub sites: Vec<CrashSite>,
+    /// Hex-encoded keys of the inputs that replayed without crashing
+    pub skipped: Vec<String>,
+}
+
+/// A place inputs crash at, identified by how the VM stopped and where
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CrashSite {
+    /// How the VM stopped, e.g. `UnhandledException(ReadUnmapped)`
+    pub exit: String,
+    /// Program counter when the VM stopped
+    pub pc: u64,
+    /// Register values when the VM stopped, for the first input seen here
+    pub registers: BTreeMap<String, u64>,
+    /// Hex-encoded keys of the inputs that crash here
+    pub inputs: Vec<String>,
+}
+
+impl TriageReport {
+    /// Records a crashing input, grouping it with earlier crashes at the same site
+    pub(crate) fn add(
+        &mut self,
+        key: &[u8],
+        exit: String,
+        pc: u64,
+        registers: BTreeMap<String, u64>,
+    ) {
+        self.crashes += 1;
+
+        let key = encode_input(key);
+        match self
+            .sites
+            .iter_mut()
+            .find(|site| site.exit == exit && site.pc == pc)
+        {
+            Some(site) => site.inputs.push(key),
+            None => self.sites.push(CrashSite {
+                exit,
+                pc,
+                registers,
+                inputs: vec![key],
+            }),
+        }
+    }
+
+    /// Records an input that replayed without crashing
+    pub(crate) fn skip(&mut self, key: &[u8]) {
+        self.skipped.push(encode_input(key));
+    }
+}
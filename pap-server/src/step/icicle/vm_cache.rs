@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use icicle_vm::Vm;
+
+use super::Library;
+
+/// Most prepared VMs kept at once by each thread
+const MAX_CACHED_VMS: usize = 8;
+
+thread_local! {
+    /// `Vm` isn't `Send`, since its hooks and environment are boxed trait
+    /// objects without a `Send` bound, so each thread keeps its own VMs.
+    /// Fuzzing steps are pinned to a few threads, and ones that run one after
+    /// another share a thread, so usually its cache too.
+    /// The oldest VMs come first.
+    static CACHE: RefCell<VecDeque<(VmKey, Vm)>> = RefCell::new(VecDeque::new());
+}
+static HITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Everything that goes into preparing a fuzzing VM, so that VMs built from
+/// equal keys are interchangeable
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct VmKey {
+    binary_hash: u64,
+    binary_len: usize,
+    arch: String,
+    vm: pap_api::VmConfig,
+    base_address: u64,
+    stack_address: u64,
+    /// Address, size, and handler of each MMIO region
+    mmio: Vec<(u64, u64, String)>,
+    /// Base address, hash, and length of each library binary
+    libraries: Vec<(u64, u64, usize)>,
+    target_addr: Option<u64>,
+}
+
+impl VmKey {
+    pub(crate) fn new(
+        binary: &[u8],
+        project: &pap_api::Project,
+        loader: &pap_api::LoaderConfig,
+        libraries: &[Library],
+        target_addr: Option<u64>,
+    ) -> Self {
+        Self {
+            binary_hash: hash_bytes(binary),
+            binary_len: binary.len(),
+            arch: project.arch.clone(),
+            vm: project.vm.clone(),
+            base_address: loader.base_address,
+            stack_address: loader.stack_address,
+            mmio: project
+                .mmio
+                .iter()
+                .map(|region| (region.address, region.size, region.handler.clone()))
+                .collect(),
+            libraries: libraries
+                .iter()
+                .map(|library| {
+                    (
+                        library.loader.base_address,
+                        hash_bytes(library.binary),
+                        library.binary.len(),
+                    )
+                })
+                .collect(),
+            target_addr,
+        }
+    }
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Takes a prepared VM for `key` from this thread's cache, or prepares one
+/// with `build`. Also returns whether the VM came from the cache.
+pub(crate) fn checkout(key: &VmKey, build: impl FnOnce() -> Result<Vm>) -> Result<(Vm, bool)> {
+    let cached = CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let index = cache.iter().position(|(cached, _)| cached == key)?;
+        cache.remove(index).map(|(_, vm)| vm)
+    });
+    match cached {
+        Some(vm) => {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            Ok((vm, true))
+        }
+        None => Ok((build()?, false)),
+    }
+}
+
+/// Returns a VM to this thread's cache for later steps, making room by
+/// dropping the oldest. It must be in the state it was prepared in, before any
+/// harness code ran, e.g. restored from a snapshot taken right after preparing
+/// it.
+pub(crate) fn checkin(key: VmKey, vm: Vm) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.retain(|(cached, _)| *cached != key);
+        if cache.len() == MAX_CACHED_VMS {
+            cache.pop_front();
+        }
+        cache.push_back((key, vm));
+    });
+}
+
+/// How many times a prepared VM was reused
+pub(crate) fn hits() -> usize {
+    HITS.load(Ordering::Relaxed)
+}
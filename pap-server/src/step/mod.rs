@@ -1,10 +1,60 @@
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod grep;
 pub mod hello;
 pub mod icicle;
+pub(crate) mod object_batch;
+pub mod script;
 
-use anyhow::Result;
-use pap_api::{PipelineStatus, StepStatus};
-use std::{collections::HashMap, sync::RwLock};
+use crate::db::retry_transient;
+use anyhow::{anyhow, bail, Result};
+use pap_api::{
+    ArgSchema, Config, ExecutorInfo, OutputKind, PipelineStatus, Step, StepOutput, StepStatus,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, OnceLock, RwLock,
+    },
+    thread,
+};
 use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+
+/// How much a step writes to its log, set by its `log_level` argument. Only
+/// steps whose executor declares that argument may set it, and those log with
+/// [`StepContext::log_at`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Only summaries of the step's work
+    Quiet,
+    /// Progress as the step goes
+    #[default]
+    Normal,
+    /// Also details of each unit of work, such as how every fuzzer input ran
+    Verbose,
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "quiet" => Ok(Self::Quiet),
+            "normal" => Ok(Self::Normal),
+            "verbose" => Ok(Self::Verbose),
+            _ => bail!(
+                "invalid log_level value: {}; expected quiet, normal, or verbose",
+                s
+            ),
+        }
+    }
+}
 
 /// Context provided to a step during execution
 pub struct StepContext<'a> {
@@ -16,6 +66,12 @@ pub struct StepContext<'a> {
     rt_handle: Handle,
     /// Log buffer
     log_buffer: RwLock<Vec<u8>>,
+    /// How the log should be read
+    output_kind: RwLock<OutputKind>,
+    /// Latest statistics reported by the step, as JSON
+    stats: RwLock<Option<String>>,
+    /// How much of what the step logs with [`log_at`](Self::log_at) is kept
+    log_level: LogLevel,
     /// Pipeline context
     context: &'a pap_api::Context,
 }
@@ -27,36 +83,128 @@ impl<'a> StepContext<'a> {
             pipeline_status,
             rt_handle: Handle::current(),
             log_buffer: RwLock::new(Vec::new()),
+            output_kind: RwLock::new(OutputKind::default()),
+            stats: RwLock::new(None),
+            // Submission rejects invalid levels, so this only falls back for
+            // steps stored before the argument was checked
+            log_level: step
+                .config
+                .args
+                .get("log_level")
+                .and_then(|level| level.parse().ok())
+                .unwrap_or_default(),
             context,
         }
     }
 
     pub fn write_object(&self, namespace: &str, key: &[u8], data: &[u8]) -> Result<()> {
-        self.rt_handle
-            .block_on(async { crate::queries::put_object(namespace, key, data).await })
-            .map_err(Into::into)
+        write_step_object(&self.rt_handle, self.status.id, namespace, key, data)
     }
 
     pub fn read_object(&self, namespace: &str, key: &[u8]) -> Result<Vec<u8>> {
-        self.rt_handle
-            .block_on(async { crate::queries::get_object(namespace, key).await })
-            .map_err(Into::into)
+        read_step_object(&self.rt_handle, namespace, key)
+    }
+
+    /// The runtime the step's database calls are driven on
+    pub(crate) fn rt_handle(&self) -> &Handle {
+        &self.rt_handle
     }
 
+    /// List the keys of every object in a namespace
+    pub fn list_objects(&self, namespace: &str) -> Result<Vec<Vec<u8>>> {
+        let namespace = namespace.to_string();
+        block_on_db(&self.rt_handle, async move {
+            crate::queries::get_object_keys(&namespace).await
+        })
+    }
+
+    /// Appends `message` as a line, the same as [`log_line`](Self::log_line)
     pub fn log(&self, message: &str) {
-        self.log_buffer.write().expect("log lock poisoned").extend_from_slice(message.as_bytes());
-        self.log_buffer.write().expect("log lock poisoned").push(b'\n');
+        self.log_line(message);
+    }
+
+    /// Appends `message` to the log, followed by a newline unless it already
+    /// ends with one
+    pub fn log_line(&self, message: &str) {
+        let mut log = self.log_buffer.write().expect("log lock poisoned");
+        log.extend_from_slice(message.as_bytes());
+        if !message.ends_with('\n') {
+            log.push(b'\n');
+        }
+    }
+
+    /// Appends `message` as a line if the step's log level includes `level`,
+    /// e.g. `ctx.log_at(LogLevel::Verbose, ...)` for detail that would usually
+    /// be noise
+    pub fn log_at(&self, level: LogLevel, message: &str) {
+        if self.logs_at(level) {
+            self.log_line(message);
+        }
+    }
+
+    /// Whether messages at `level` are kept, for skipping the work of
+    /// building ones that aren't
+    pub fn logs_at(&self, level: LogLevel) -> bool {
+        level <= self.log_level
+    }
+
+    /// Appends `bytes` to the log exactly as given, e.g. binary output or a
+    /// partial line
+    pub fn log_raw(&self, bytes: &[u8]) {
+        self.log_buffer
+            .write()
+            .expect("log lock poisoned")
+            .extend_from_slice(bytes);
+    }
+
+    /// Formats `args` and appends them as a line, e.g.
+    /// `ctx.log_fmt(format_args!("found {} crashes", count))`
+    pub fn log_fmt(&self, args: std::fmt::Arguments) {
+        match args.as_str() {
+            Some(message) => self.log_line(message),
+            None => self.log_line(&args.to_string()),
+        }
     }
 
     pub(crate) fn get_log(&self) -> Vec<u8> {
         self.log_buffer.read().expect("log lock poisoned").clone()
     }
 
+    /// Declares how the step's log should be read, which is text unless set
+    pub fn set_output_kind(&self, kind: OutputKind) {
+        *self.output_kind.write().expect("output kind lock poisoned") = kind;
+    }
+
+    pub(crate) fn get_output(&self) -> StepOutput {
+        StepOutput {
+            kind: *self.output_kind.read().expect("output kind lock poisoned"),
+            data: self.get_log(),
+        }
+    }
+
+    /// Reports statistics about the step's work, replacing any reported
+    /// before. The latest are stored as JSON with the step once it finishes.
+    pub fn set_stats<T: Serialize>(&self, stats: &T) -> Result<()> {
+        let stats = serde_json::to_string(stats)?;
+        *self.stats.write().expect("stats lock poisoned") = Some(stats);
+        Ok(())
+    }
+
+    pub(crate) fn get_stats(&self) -> Option<String> {
+        self.stats.read().expect("stats lock poisoned").clone()
+    }
+
     // Convenience getters
+    pub fn is_dry_run(&self) -> bool {
+        self.pipeline_status.dry_run
+    }
+
     pub fn is_cancelled(&self) -> bool {
-        self.rt_handle
-            .block_on(async { crate::queries::is_step_cancelled(self.status.id).await })
-            .unwrap_or(false)
+        let step_id = self.status.id;
+        block_on_db(&self.rt_handle, async move {
+            crate::queries::is_step_cancelled(step_id).await
+        })
+        .unwrap_or(false)
     }
 
     pub fn has_arg(&self, name: &str) -> bool {
@@ -67,6 +215,34 @@ impl<'a> StepContext<'a> {
         self.status.config.args.get(name).map(|s| s.as_str())
     }
 
+    /// Get an `Integer` argument, or an error if it isn't a valid integer
+    pub fn get_int_arg(&self, name: &str) -> Result<Option<i64>> {
+        self.get_arg(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid {} value: {}", name, value))
+            })
+            .transpose()
+    }
+
+    /// Get a `Boolean` argument, or an error if it isn't `true` or `false`
+    pub fn get_bool_arg(&self, name: &str) -> Result<Option<bool>> {
+        self.get_arg(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid {} value: {}", name, value))
+            })
+            .transpose()
+    }
+
+    /// Get a `List` argument's values, which is empty if the argument is
+    /// missing
+    pub fn get_list_arg(&self, name: &str) -> Vec<&str> {
+        self.get_arg(name).map(parse_list).unwrap_or_default()
+    }
+
     pub fn has_io(&self, name: &str) -> bool {
         self.status.config.io.contains_key(name)
     }
@@ -75,16 +251,266 @@ impl<'a> StepContext<'a> {
         self.status.config.io.get(name).map(|s| s.as_str())
     }
 
+    /// Get a value from the step's `env`, with environment references already
+    /// resolved by the submitter
+    pub fn get_env(&self, name: &str) -> Option<&str> {
+        self.status.config.env.get(name).map(|s| s.as_str())
+    }
+
+    /// Get the contents of an IO field, resolving `step://<step-name>/output`
+    /// references to the output of that earlier step in the pipeline. Other
+    /// values are returned as-is.
+    pub fn resolve_io(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(value) = self.get_io(name) else {
+            return Ok(None);
+        };
+
+        match parse_step_reference(value)? {
+            Some(step_name) => {
+                let (pipeline_id, step_id) = (self.pipeline_status.id, self.status.id);
+                let step_name = step_name.to_string();
+                block_on_db(&self.rt_handle, async move {
+                    crate::queries::get_prior_step_output(pipeline_id, step_id, &step_name).await
+                })
+                .map(Some)
+            }
+            None => Ok(Some(value.as_bytes().to_vec())),
+        }
+    }
+
     /// Get a file from the context by name
     pub fn get_file(&self, name: &str) -> Option<&[u8]> {
         self.context.files().get(name).map(|v| v.as_slice())
     }
 }
 
+/// Arguments and IO fields a step executor needs, checked before a pipeline
+/// is accepted so that misconfigured steps fail at submission time
+#[derive(Clone, Debug, Default)]
+pub struct StepRequirements {
+    /// Names of arguments that must be present
+    pub args: Vec<String>,
+    /// Names of IO fields that must be present
+    pub io: Vec<String>,
+    /// Names of IO fields that get a namespace of the step's own when unset,
+    /// rather than being required
+    pub default_io: Vec<String>,
+    /// Names of arguments whose value must be the name of a configured project
+    pub project_args: Vec<String>,
+}
+
+impl StepRequirements {
+    pub fn validate(&self, step: &Step, config: &Config) -> Result<()> {
+        for arg in &self.args {
+            if !step.args.contains_key(arg) {
+                bail!("step {} is missing required argument: {}", step.name, arg);
+            }
+        }
+
+        for io in &self.io {
+            if !step.io.contains_key(io) && !self.default_io.contains(io) {
+                bail!("step {} is missing required IO field: {}", step.name, io);
+            }
+        }
+
+        for arg in &self.project_args {
+            if let Some(project) = step.args.get(arg) {
+                if !config.projects.iter().any(|p| &p.name == project) {
+                    bail!("step {} references unknown project: {}", step.name, project);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a `List` argument's comma-separated value into its items
+pub(crate) fn parse_list(value: &str) -> Vec<&str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parses an IO value of the form `step://<step-name>/output`, returning the
+/// referenced step's name, or `None` if the value isn't a step reference
+pub(crate) fn parse_step_reference(value: &str) -> Result<Option<&str>> {
+    let Some(reference) = value.strip_prefix("step://") else {
+        return Ok(None);
+    };
+
+    match reference.rsplit_once('/') {
+        Some((step_name, "output")) if !step_name.is_empty() => Ok(Some(step_name)),
+        _ => bail!(
+            "invalid step reference: {} (expected step://<step-name>/output)",
+            value
+        ),
+    }
+}
+
+/// Stores an object as written by step `step_id`, like
+/// [`StepContext::write_object`] for code that can't borrow the context
+pub(crate) fn write_step_object(
+    handle: &Handle,
+    step_id: u32,
+    namespace: &str,
+    key: &[u8],
+    data: &[u8],
+) -> Result<()> {
+    let (namespace, key, data) = (namespace.to_string(), key.to_vec(), data.to_vec());
+    block_on_db(handle, async move {
+        retry_transient(|| crate::queries::put_object(&namespace, &key, &data, Some(step_id))).await
+    })
+}
+
+/// Reads an object, like [`StepContext::read_object`] for code that can't
+/// borrow the context
+pub(crate) fn read_step_object(handle: &Handle, namespace: &str, key: &[u8]) -> Result<Vec<u8>> {
+    let (namespace, key) = (namespace.to_string(), key.to_vec());
+    block_on_db(handle, async move {
+        Ok(crate::queries::get_object(&namespace, &key).await?)
+    })
+}
+
+/// Runs a database future to completion from synchronous step code.
+///
+/// `Handle::block_on` panics when called from a thread that is driving async
+/// tasks, which is only avoided while the caller sits inside `block_in_place`.
+/// Instead, the future is handed to the runtime's blocking pool, whose threads
+/// may always block on it, and this thread waits for the result, so it doesn't
+/// panic wherever it is called from.
+///
+/// Waiting still blocks the calling thread. From a runtime worker, that is only
+/// fine inside `block_in_place`, as steps run; elsewhere it stalls every task
+/// on that worker until the query finishes.
+pub(crate) fn block_on_db<F, T>(handle: &Handle, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+    let blocking_handle = handle.clone();
+    handle.spawn_blocking(move || {
+        // The receiver only disappears if the caller is gone, so nobody is
+        // left to report a failed send to
+        let _ = tx.send(blocking_handle.block_on(future));
+    });
+    rx.recv()
+        .map_err(|_| anyhow!("database task ended without a result"))?
+}
+
+/// Work sent to a thread that runs pinned steps
+type PinnedWork = Box<dyn FnOnce() + Send>;
+
+/// One of the threads kept for pinned steps, with how much work it has been
+/// sent that hasn't finished yet
+struct PinnedThread {
+    sender: mpsc::Sender<PinnedWork>,
+    queued: AtomicUsize,
+}
+
+/// The threads kept for pinned steps, one per core but at least two, so that
+/// one long step doesn't hold up every other
+fn pinned_threads() -> &'static [PinnedThread] {
+    static PINNED: OnceLock<Vec<PinnedThread>> = OnceLock::new();
+    PINNED.get_or_init(|| {
+        let count = thread::available_parallelism().map_or(2, |n| n.get().max(2));
+        (0..count)
+            .map(|i| {
+                let (sender, rx) = mpsc::channel::<PinnedWork>();
+                thread::Builder::new()
+                    .name(format!("pinned-steps-{}", i))
+                    .spawn(move || rx.into_iter().for_each(|work| work()))
+                    .expect("failed to start a pinned step thread");
+                PinnedThread {
+                    sender,
+                    queued: AtomicUsize::new(0),
+                }
+            })
+            .collect()
+    })
+}
+
+/// Runs `work` on one of the threads kept for executors
+/// [pinned to them](StepExecutor::pinned_to_thread), waiting for it without
+/// blocking, with the caller's runtime entered so that it can reach the
+/// database.
+///
+/// Work goes to the thread with the least of it queued, preferring the first
+/// threads, so steps that run one after another share a thread and whatever it
+/// keeps, while steps that run at once get a thread each until there are more
+/// of them than threads.
+pub(crate) async fn run_pinned<F, T>(work: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let pinned = pinned_threads()
+        .iter()
+        .min_by_key(|pinned| pinned.queued.load(Ordering::Relaxed))
+        .expect("there is always a pinned step thread");
+    pinned.queued.fetch_add(1, Ordering::Relaxed);
+
+    let handle = Handle::current();
+    let queued = &pinned.queued;
+    let (tx, rx) = oneshot::channel();
+    let work: PinnedWork = Box::new(move || {
+        let _runtime = handle.enter();
+        // A panic would end the thread, and with it every later step sent to it
+        let result = panic::catch_unwind(AssertUnwindSafe(work));
+        // Finished before the result is sent, so the caller's next work sees
+        // this thread as free again
+        queued.fetch_sub(1, Ordering::Relaxed);
+        // The receiver only disappears if the caller is gone, so nobody is
+        // left to report a failed send to
+        let _ = tx.send(result);
+    });
+    pinned
+        .sender
+        .send(work)
+        .map_err(|_| anyhow!("a pinned step thread has stopped"))?;
+    rx.await
+        .map_err(|_| anyhow!("a pinned step thread dropped a step"))?
+        .map_err(|_| anyhow!("step panicked"))
+}
+
 /// Trait that must be implemented by step executors
 pub trait StepExecutor: Send + Sync {
     fn name(&self) -> String;
     fn execute(&self, ctx: &mut StepContext) -> Result<()>;
+
+    /// Performs the step's setup without its expensive work, for dry-run
+    /// pipelines. By default nothing is done beyond the up-front validation.
+    fn dry_run(&self, _ctx: &mut StepContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Declares what this executor needs from a step's configuration
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements::default()
+    }
+
+    /// Whether steps of this executor run on a small set of long-lived
+    /// threads, for state kept between steps that can't leave its thread, such
+    /// as prepared VMs. Steps that run one after another usually share a
+    /// thread. By default steps run on any thread.
+    fn pinned_to_thread(&self) -> bool {
+        false
+    }
+
+    /// Describes every argument this executor accepts, for tooling
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        Vec::new()
+    }
+
+    /// Checks a step against the whole submitted context, for problems its
+    /// requirements can't express. Errors reject the pipeline before it's set
+    /// up. By default nothing more is checked.
+    fn validate(&self, _step: &Step, _context: &pap_api::Context) -> Result<()> {
+        Ok(())
+    }
 }
 
 // This function is used to ensure that the StepExecutor trait is object safe
@@ -97,6 +523,51 @@ pub struct StepExecutorRegistry {
 }
 
 impl StepExecutorRegistry {
+    /// Starts a registry with the builtin executors, which custom ones can be
+    /// added to before it's handed to
+    /// [`PipelineServer::new`](crate::server::PipelineServer::new). Registering
+    /// an executor with a builtin's name replaces the builtin.
+    ///
+    /// ```no_run
+    /// use pap_server::server::PipelineServer;
+    /// use pap_server::step::{StepContext, StepExecutor, StepExecutorRegistry};
+    ///
+    /// struct CountExecutor;
+    ///
+    /// impl StepExecutor for CountExecutor {
+    ///     fn name(&self) -> String {
+    ///         "count".to_string()
+    ///     }
+    ///
+    ///     fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+    ///         ctx.log("1, 2, 3");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// # async fn example(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+    /// let mut registry = StepExecutorRegistry::with_builtins();
+    /// registry.register(CountExecutor);
+    /// let server = PipelineServer::new(pool, registry).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+
+        #[cfg(feature = "fetch")]
+        registry.register(fetch::FetchStepExecutor);
+        registry.register(grep::GrepStepExecutor);
+        registry.register(hello::HelloStepExecutor);
+        registry.register(icicle::IcicleFuzzerExecutor);
+        registry.register(icicle::IcicleMinimizeExecutor);
+        registry.register(icicle::IcicleReproduceExecutor);
+        registry.register(icicle::IcicleTriageExecutor);
+        registry.register(script::ScriptStepExecutor);
+
+        registry
+    }
+
     pub fn register<E: StepExecutor + 'static>(&mut self, executor: E) {
         self.executors
             .insert(executor.name().to_string(), Box::new(executor));
@@ -105,13 +576,25 @@ impl StepExecutorRegistry {
     pub fn get(&self, name: &str) -> Option<&dyn StepExecutor> {
         self.executors.get(name).map(|e| e.as_ref())
     }
+
+    /// Describe all registered executors, sorted by name
+    pub fn info(&self) -> Vec<ExecutorInfo> {
+        let mut info: Vec<_> = self
+            .executors
+            .values()
+            .map(|e| ExecutorInfo {
+                name: e.name(),
+                args: e.arg_schema(),
+                io: e.requirements().io,
+            })
+            .collect();
+        info.sort_by(|a, b| a.name.cmp(&b.name));
+        info
+    }
 }
 
+/// A registry of just the builtin executors, the same as
+/// [`StepExecutorRegistry::with_builtins`]
 pub fn builtin_executors() -> StepExecutorRegistry {
-    let mut registry = StepExecutorRegistry::default();
-
-    registry.register(hello::HelloStepExecutor);
-    registry.register(icicle::IcicleFuzzerExecutor);
-
-    registry
+    StepExecutorRegistry::with_builtins()
 }
@@ -0,0 +1,147 @@
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use super::block_on_db;
+use crate::db::retry_transient;
+
+/// Buffers object writes to a namespace and stores them in a single
+/// transaction once enough entries have accumulated or enough time has
+/// passed. Reads see buffered writes. Anything still pending is flushed on
+/// drop if a runtime is available and logged as lost otherwise, so callers
+/// should `flush` explicitly to observe errors.
+///
+/// Reads and writes are retried while the database is locked by another
+/// connection, and a batch that still fails to store stays buffered for the
+/// next flush.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ObjectBatch {
+    namespace: String,
+    /// Step the objects are attributed to, if any
+    step_id: Option<u32>,
+    max_entries: usize,
+    max_age: Duration,
+    #[serde(skip)]
+    pending: RefCell<Vec<(Vec<u8>, Vec<u8>)>>,
+    #[serde(skip, default = "now")]
+    last_flush: Cell<Instant>,
+    #[serde(skip)]
+    flushes: Cell<usize>,
+}
+
+fn now() -> Cell<Instant> {
+    Cell::new(Instant::now())
+}
+
+impl ObjectBatch {
+    pub(crate) fn new(
+        namespace: String,
+        step_id: Option<u32>,
+        max_entries: usize,
+        max_age: Duration,
+    ) -> Self {
+        Self {
+            namespace,
+            step_id,
+            max_entries,
+            max_age,
+            pending: RefCell::new(Vec::new()),
+            last_flush: now(),
+            flushes: Cell::new(0),
+        }
+    }
+
+    /// Namespace the objects are stored in
+    pub(crate) fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub(crate) fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        // Reject oversized values now rather than when the batch is flushed
+        crate::queries::check_object_size(value)?;
+
+        let len = {
+            let mut pending = self.pending.borrow_mut();
+            pending.retain(|(k, _)| k != key);
+            pending.push((key.to_vec(), value.to_vec()));
+            pending.len()
+        };
+
+        if len >= self.max_entries || self.last_flush.get().elapsed() >= self.max_age {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        if let Some((_, value)) = self.pending.borrow().iter().find(|(k, _)| k == key) {
+            return Ok(value.clone());
+        }
+
+        let (namespace, key) = (self.namespace.clone(), key.to_vec());
+        block_on_db(&Handle::current(), async move {
+            retry_transient(|| crate::queries::get_object(&namespace, &key)).await
+        })
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        self.flush_on(&Handle::current())
+    }
+
+    fn flush_on(&self, handle: &Handle) -> Result<()> {
+        let items = self.pending.take();
+        self.last_flush.set(Instant::now());
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let (namespace, step_id) = (self.namespace.clone(), self.step_id);
+        let batch = items.clone();
+        let stored = block_on_db(handle, async move {
+            retry_transient(|| crate::queries::put_objects_batch(&namespace, &batch, step_id)).await
+        });
+        if stored.is_err() {
+            // Nothing is buffered while flushing, so the batch can go back as is
+            *self.pending.borrow_mut() = items;
+        }
+        stored?;
+        self.flushes.set(self.flushes.get() + 1);
+        Ok(())
+    }
+
+    /// Number of transactions used to store objects so far
+    #[cfg(test)]
+    pub(crate) fn flushes(&self) -> usize {
+        self.flushes.get()
+    }
+}
+
+impl Drop for ObjectBatch {
+    fn drop(&mut self) {
+        let pending = self.pending.get_mut().len();
+        if pending == 0 {
+            return;
+        }
+        // Panicking here would abort if the batch is dropped while unwinding
+        match Handle::try_current() {
+            Ok(handle) => {
+                if let Err(e) = self.flush_on(&handle) {
+                    log::error!(
+                        "Failed to flush {} objects to {}: {}",
+                        pending,
+                        self.namespace,
+                        e
+                    );
+                }
+            }
+            Err(_) => log::error!(
+                "Dropped {} unflushed objects for {} outside a runtime",
+                pending,
+                self.namespace
+            ),
+        }
+    }
+}
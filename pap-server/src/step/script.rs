@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::bail;
+use pap_api::{ArgSchema, ArgType};
+use rhai::{Blob, Dynamic, Engine};
+use tokio::runtime::Handle;
+
+use super::{read_step_object, write_step_object, StepContext, StepExecutor, StepRequirements};
+
+/// Most operations a script may run unless `op_limit` is given
+const DEFAULT_SCRIPT_OP_LIMIT: u64 = 1_000_000;
+
+/// Deepest a script's function calls may nest
+const SCRIPT_MAX_CALL_LEVELS: usize = 32;
+
+/// Longest string a script may build, in bytes
+const SCRIPT_MAX_STRING_SIZE: usize = 16 * 1024 * 1024;
+
+/// Most items an array, or bytes a blob, a script builds may hold
+const SCRIPT_MAX_ARRAY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Most entries a map a script builds may hold
+const SCRIPT_MAX_MAP_SIZE: usize = 1024 * 1024;
+
+type RhaiResult<T> = Result<T, Box<rhai::EvalAltResult>>;
+
+/// Runs a rhai script given in the step's `script` argument, for glue logic
+/// that doesn't warrant an executor of its own
+pub struct ScriptStepExecutor;
+
+impl StepExecutor for ScriptStepExecutor {
+    fn name(&self) -> String {
+        "script".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let script = ctx
+            .get_arg("script")
+            .ok_or(anyhow::anyhow!("missing `script` argument"))?
+            .to_string();
+        let op_limit = op_limit(ctx)?;
+
+        let host = ScriptHost::new(ctx);
+        let result = build_engine(host.clone(), op_limit).run(&script);
+
+        // Keep what the script logged even if it failed partway
+        ctx.log_raw(&host.log.lock().expect("script log lock poisoned"));
+        result.map_err(|e| anyhow::anyhow!("script failed: {}", e))
+    }
+
+    fn dry_run(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let script = ctx
+            .get_arg("script")
+            .ok_or(anyhow::anyhow!("missing `script` argument"))?;
+        op_limit(ctx)?;
+
+        Engine::new()
+            .compile(script)
+            .map_err(|e| anyhow::anyhow!("invalid script: {}", e))?;
+        ctx.log("Script compiled successfully");
+        Ok(())
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec!["script".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn arg_schema(&self) -> Vec<ArgSchema> {
+        vec![
+            ArgSchema {
+                name: "script".to_string(),
+                arg_type: ArgType::String,
+                required: true,
+                default: None,
+                description: "Rhai code to run, which may call `log`, `get_arg`, \
+                              `read_object`, and `write_object`"
+                    .to_string(),
+            },
+            ArgSchema {
+                name: "op_limit".to_string(),
+                arg_type: ArgType::Integer,
+                required: false,
+                default: Some(DEFAULT_SCRIPT_OP_LIMIT.to_string()),
+                description: "Most operations the script may run before it's stopped".to_string(),
+            },
+        ]
+    }
+}
+
+fn op_limit(ctx: &StepContext) -> anyhow::Result<u64> {
+    match ctx.get_int_arg("op_limit")? {
+        Some(limit) if limit > 0 => Ok(limit as u64),
+        Some(limit) => bail!("invalid op_limit value: {}", limit),
+        None => Ok(DEFAULT_SCRIPT_OP_LIMIT),
+    }
+}
+
+/// Object keys and values may be given to a script's functions as either
+/// strings or blobs
+fn to_bytes(value: Dynamic) -> RhaiResult<Vec<u8>> {
+    let type_name = value.type_name();
+    if value.is_blob() {
+        return Ok(value.cast::<Blob>());
+    }
+    match value.try_cast::<rhai::ImmutableString>() {
+        Some(s) => Ok(s.as_bytes().to_vec()),
+        None => Err(format!("expected a string or blob, got {}", type_name).into()),
+    }
+}
+
+/// What a script's functions need from the step. The engine's functions must
+/// be 'static, so they share this rather than borrowing the step's context.
+#[derive(Clone)]
+struct ScriptHost {
+    args: Arc<HashMap<String, String>>,
+    rt_handle: Handle,
+    step_id: u32,
+    /// What the script logged, added to the step's log once it finishes
+    log: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ScriptHost {
+    fn new(ctx: &StepContext) -> Self {
+        Self {
+            args: Arc::new(ctx.status.config.args.clone()),
+            rt_handle: ctx.rt_handle().clone(),
+            step_id: ctx.status.id,
+            log: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn log(&self, message: &str) {
+        let mut log = self.log.lock().expect("script log lock poisoned");
+        log.extend_from_slice(message.as_bytes());
+        log.push(b'\n');
+    }
+}
+
+/// Builds an engine whose functions act on the step, and which stops scripts
+/// that run away or build values big enough to exhaust the server's memory
+fn build_engine(host: ScriptHost, op_limit: u64) -> Engine {
+    let mut engine = Engine::new();
+    let (print_host, log_host, arg_host, read_host, write_host) =
+        (host.clone(), host.clone(), host.clone(), host.clone(), host);
+    engine
+        .set_max_operations(op_limit)
+        .set_max_call_levels(SCRIPT_MAX_CALL_LEVELS)
+        .set_max_string_size(SCRIPT_MAX_STRING_SIZE)
+        .set_max_array_size(SCRIPT_MAX_ARRAY_SIZE)
+        .set_max_map_size(SCRIPT_MAX_MAP_SIZE)
+        .on_print(move |message| print_host.log(message))
+        .register_fn("log", move |message: &str| log_host.log(message))
+        .register_fn("get_arg", move |name: &str| -> Dynamic {
+            arg_host
+                .args
+                .get(name)
+                .map_or(Dynamic::UNIT, |value| Dynamic::from(value.clone()))
+        })
+        .register_fn(
+            "read_object",
+            move |namespace: &str, key: Dynamic| -> RhaiResult<Blob> {
+                read_step_object(&read_host.rt_handle, namespace, &to_bytes(key)?)
+                    .map_err(|e| e.to_string().into())
+            },
+        )
+        .register_fn(
+            "write_object",
+            move |namespace: &str, key: Dynamic, value: Dynamic| -> RhaiResult<()> {
+                write_step_object(
+                    &write_host.rt_handle,
+                    write_host.step_id,
+                    namespace,
+                    &to_bytes(key)?,
+                    &to_bytes(value)?,
+                )
+                .map_err(|e| e.to_string().into())
+            },
+        );
+    engine
+}
@@ -0,0 +1,5152 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use pap_api::{
+    load_config, ArgType, Context, ExecutionStatus, OutputKind, PapApi, PapApiClient, PapError,
+    PipelineEvent, PipelineStatus, ServerAddr, StatusEntity, StepOutput, SubmitResult,
+    REDACTED_ENV,
+};
+use sqlx::{Row, SqlitePool};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::compression::set_compression;
+use crate::db::{init_pool, with_pool, PoolConfig};
+use crate::http;
+use crate::integrity::set_integrity_checks;
+use crate::queries;
+use crate::queries::{set_exclusive_namespaces, set_max_object_size, DEFAULT_MAX_OBJECT_SIZE};
+use crate::server::PipelineServer;
+use crate::step::icicle::fuzzer;
+use crate::step::icicle::minimize::minimize_input;
+use crate::step::icicle::triage::TriageReport;
+use crate::step::icicle::vm_cache;
+use crate::step::object_batch::ObjectBatch;
+use crate::step::{
+    builtin_executors, run_pinned, StepContext, StepExecutor, StepExecutorRegistry,
+    StepRequirements,
+};
+
+// The database pool is global, so tests that touch it must not interleave
+static DB_LOCK: Mutex<()> = Mutex::const_new(());
+
+const HELLO_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: greet
+    steps:
+      - name: say-hello
+        call: hello
+        args:
+          name: world
+"#;
+
+async fn setup_db() -> MutexGuard<'static, ()> {
+    let guard = DB_LOCK.lock().await;
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("failed to open database");
+    init_pool(pool).expect("failed to initialize pool");
+    queries::init_tables()
+        .await
+        .expect("failed to create tables");
+    guard
+}
+
+async fn setup_server() -> (MutexGuard<'static, ()>, PipelineServer) {
+    setup_server_with(builtin_executors()).await
+}
+
+async fn setup_server_with(
+    registry: StepExecutorRegistry,
+) -> (MutexGuard<'static, ()>, PipelineServer) {
+    let guard = DB_LOCK.lock().await;
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("failed to open database");
+    let server = PipelineServer::new(pool, registry)
+        .await
+        .expect("failed to create server");
+    (guard, server)
+}
+
+async fn wait_for_pipeline(id: u32) -> ExecutionStatus {
+    wait_for_pipeline_within(id, Duration::from_secs(1)).await
+}
+
+async fn wait_for_pipeline_within(id: u32, timeout: Duration) -> ExecutionStatus {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        let status = queries::get_pipeline_status(id).await.unwrap().status;
+        if !matches!(status, ExecutionStatus::Pending | ExecutionStatus::Running) {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("pipeline {} did not finish", id);
+}
+
+async fn wait_until<F, Fut>(condition: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    wait_until_within(Duration::from_secs(1), condition).await
+}
+
+/// Polls `condition` until it holds or `timeout` passes, returning whether it
+/// held. For state a background task updates shortly after, such as a step
+/// starting or an error being stored.
+async fn wait_until_within<F, Fut>(timeout: Duration, mut condition: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        if condition().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+/// Waits for a failed pipeline's error, which is stored just after its status
+/// flips to failed
+async fn pipeline_error(id: u32) -> Option<PapError> {
+    wait_until(|| async {
+        queries::get_pipeline_status(id)
+            .await
+            .unwrap()
+            .error
+            .is_some()
+    })
+    .await;
+    queries::get_pipeline_status(id).await.unwrap().error
+}
+
+/// Waits for step `index` of a job to start running, returning whether it did
+async fn step_started(job_id: u32, index: usize) -> bool {
+    wait_until(|| async {
+        queries::get_job_status(job_id).await.unwrap().steps[index].status
+            == ExecutionStatus::Running
+    })
+    .await
+}
+
+fn hello_context() -> Context {
+    Context {
+        config: load_config(HELLO_CONFIG.as_bytes()).expect("failed to parse config"),
+        files: HashMap::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_pipeline_progress() {
+    let _guard = setup_db().await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: first
+    steps:
+      - { name: a, call: hello, args: { name: a } }
+      - { name: b, call: hello, args: { name: b } }
+  - name: second
+    steps:
+      - { name: c, call: hello, args: { name: c } }
+      - { name: d, call: hello, args: { name: d } }
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    assert_eq!((pipeline.completed_steps, pipeline.total_steps), (0, 4));
+    assert_eq!(pipeline.progress, 0.0);
+
+    for job_id in &pipeline.jobs {
+        let job = queries::get_job_status(*job_id).await.unwrap();
+        queries::set_step_status(job.steps[0].id, ExecutionStatus::Completed)
+            .await
+            .unwrap();
+    }
+
+    let pipeline = queries::get_pipeline_status(pipeline.id).await.unwrap();
+    assert_eq!((pipeline.completed_steps, pipeline.total_steps), (2, 4));
+    assert_eq!(pipeline.progress, 0.5);
+}
+
+#[tokio::test]
+async fn test_step_timestamps() {
+    let _guard = setup_db().await;
+
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_id = job.steps[0].id;
+
+    let step = queries::get_step_status(step_id).await.unwrap();
+    assert!(step.started_at.is_none());
+    assert!(step.finished_at.is_none());
+    assert!(step.duration.is_none());
+
+    queries::set_step_status(step_id, ExecutionStatus::Running)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    queries::set_step_status(step_id, ExecutionStatus::Completed)
+        .await
+        .unwrap();
+
+    let step = queries::get_step_status(step_id).await.unwrap();
+    let started_at = step.started_at.expect("step has no start time");
+    let finished_at = step.finished_at.expect("step has no finish time");
+    assert!(finished_at > started_at);
+    assert!(step.duration.expect("step has no duration") > Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_pipelines_newest_first() {
+    let _guard = setup_db().await;
+
+    let first = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let second = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    assert!(first.created_at.is_some());
+    assert!(second.created_at.is_some());
+
+    let ids = queries::get_pipeline_ids().await.unwrap();
+    assert_eq!(ids, vec![second.id, first.id]);
+}
+
+#[tokio::test]
+async fn test_get_pipeline_statuses() {
+    let (_guard, server) = setup_server().await;
+
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let pipeline = queries::setup_pipeline(&hello_context(), false)
+            .await
+            .unwrap();
+        ids.push(pipeline.id);
+    }
+    queries::store_error(ids[1], &PapError::Execution("boom".to_string()), None)
+        .await
+        .unwrap();
+
+    // Missing pipelines are skipped and the requested order is kept
+    let statuses = server
+        .get_pipeline_statuses(tarpc::context::current(), vec![ids[2], 999, ids[0], ids[1]])
+        .await
+        .unwrap();
+    let returned: Vec<_> = statuses.iter().map(|s| s.id).collect();
+    assert_eq!(returned, vec![ids[2], ids[0], ids[1]]);
+
+    for status in &statuses {
+        let single = queries::get_pipeline_status(status.id).await.unwrap();
+        assert_eq!(status.jobs, single.jobs);
+        assert_eq!(status.status, single.status);
+        assert_eq!(status.config, single.config);
+    }
+    assert_eq!(statuses[2].status, ExecutionStatus::Failed);
+    assert!(matches!(statuses[2].error, Some(PapError::Execution(_))));
+    assert!(statuses[0].error.is_none());
+}
+
+#[tokio::test]
+async fn test_count_pipelines() {
+    let (_guard, server) = setup_server().await;
+
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let pipeline = queries::setup_pipeline(&hello_context(), false)
+            .await
+            .unwrap();
+        ids.push(pipeline.id);
+    }
+    for id in &ids[..2] {
+        queries::store_error(*id, &PapError::Execution("boom".to_string()), None)
+            .await
+            .unwrap();
+    }
+
+    let count = |status| {
+        server
+            .clone()
+            .count_pipelines(tarpc::context::current(), status)
+    };
+    assert_eq!(count(None).await.unwrap(), 3);
+    assert_eq!(count(Some(ExecutionStatus::Failed)).await.unwrap(), 2);
+    assert_eq!(count(Some(ExecutionStatus::Pending)).await.unwrap(), 1);
+    assert_eq!(count(Some(ExecutionStatus::Running)).await.unwrap(), 0);
+
+    let jobs = server
+        .count_jobs(tarpc::context::current(), None)
+        .await
+        .unwrap();
+    assert_eq!(jobs, 3);
+}
+
+#[tokio::test]
+async fn test_pipelines_by_label() {
+    let _guard = setup_db().await;
+
+    let mut firmware_a = hello_context();
+    firmware_a
+        .config
+        .labels
+        .insert("target".to_string(), "firmwareA".to_string());
+    let mut firmware_b = hello_context();
+    firmware_b
+        .config
+        .labels
+        .insert("target".to_string(), "firmwareB".to_string());
+
+    let a = queries::setup_pipeline(&firmware_a, false).await.unwrap();
+    queries::setup_pipeline(&firmware_b, false).await.unwrap();
+    queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+
+    let ids = queries::get_pipeline_ids_by_label("target", "firmwareA")
+        .await
+        .unwrap();
+    assert_eq!(ids, vec![a.id]);
+
+    // Labels survive the round-trip through the stored config
+    let status = queries::get_pipeline_status(a.id).await.unwrap();
+    assert_eq!(status.config.labels["target"], "firmwareA");
+}
+
+#[tokio::test]
+async fn test_pipeline_jobs() {
+    let (_guard, server) = setup_server().await;
+
+    let first = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let second = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    assert!(!first.jobs.is_empty());
+
+    assert_eq!(
+        queries::get_pipeline_job_ids(first.id).await.unwrap(),
+        first.jobs
+    );
+    assert_eq!(
+        queries::get_pipeline_job_ids(second.id).await.unwrap(),
+        second.jobs
+    );
+    assert!(queries::get_pipeline_job_ids(second.id + 1)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(
+        server
+            .clone()
+            .get_pipeline_jobs(tarpc::context::current(), first.id)
+            .await
+            .unwrap(),
+        first.jobs
+    );
+    // An unknown pipeline is not found, rather than having no jobs
+    assert!(matches!(
+        server
+            .get_pipeline_jobs(tarpc::context::current(), second.id + 1)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_submit_warns_about_unused_project() {
+    let (_guard, server) = setup_server().await;
+
+    let step = r#"
+      - name: say-hello
+        call: hello
+        args:
+          name: world
+"#;
+    let result = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), icicle_context(step, &[0; 4]))
+        .await
+        .unwrap();
+    assert_eq!(
+        result.warnings,
+        vec!["project target is not used by any step".to_string()]
+    );
+    assert_eq!(
+        wait_for_pipeline(result.id).await,
+        ExecutionStatus::Completed
+    );
+
+    let result = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap();
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+
+    // A dry run warns the same way
+    let result = server
+        .dry_run_pipeline(tarpc::context::current(), icicle_context(step, &[0; 4]))
+        .await
+        .unwrap();
+    assert_eq!(
+        result.warnings,
+        vec!["project target is not used by any step".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_resubmit_pipeline() {
+    let (_guard, server) = setup_server().await;
+
+    let original = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    let resubmitted = server
+        .clone()
+        .resubmit_pipeline(tarpc::context::current(), original)
+        .await
+        .unwrap();
+    assert_ne!(original, resubmitted);
+
+    let original = queries::get_pipeline_status(original).await.unwrap();
+    let resubmitted = queries::get_pipeline_status(resubmitted).await.unwrap();
+    assert_eq!(original.config, resubmitted.config);
+}
+
+#[tokio::test]
+async fn test_step_reads_extra_file() {
+    let _guard = setup_db().await;
+
+    let config = load_config(HELLO_CONFIG.as_bytes()).unwrap();
+    let context = Context::builder(config, ".".into())
+        .add_file("seed.bin", b"seed".to_vec())
+        .build()
+        .unwrap();
+
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_context = StepContext::new(&job.steps[0], &pipeline, &context);
+
+    assert_eq!(step_context.get_file("seed.bin"), Some(&b"seed"[..]));
+    assert_eq!(step_context.get_file("missing.bin"), None);
+}
+
+#[tokio::test]
+async fn test_step_log_raw_and_lines() {
+    let _guard = setup_db().await;
+
+    let config = load_config(HELLO_CONFIG.as_bytes()).unwrap();
+    let context = Context::build_with_config(config, ".".into()).unwrap();
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_context = StepContext::new(&job.steps[0], &pipeline, &context);
+
+    // Raw bytes go in untouched, with no newline added
+    step_context.log_raw(&[0x00, 0xff, b'\n', 0x80]);
+    step_context.log_raw(b"partial");
+    step_context.log_raw(b" line\n");
+    // Multi-line messages that end with a newline don't get another
+    step_context.log_line("first\nsecond\n");
+    step_context.log("third");
+    step_context.log_fmt(format_args!("{} crashes", 2));
+
+    assert_eq!(
+        step_context.get_log(),
+        b"\x00\xff\n\x80partial line\nfirst\nsecond\nthird\n2 crashes\n"
+    );
+}
+
+#[tokio::test]
+async fn test_step_config_round_trip() {
+    let _guard = setup_db().await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: scan
+    steps:
+      - name: find-magic
+        call: grep
+        args:
+          pattern: "7f454c46"
+        io:
+          namespace: firmware
+          key: image
+        env:
+          TOKEN: secret
+        cleanup_on_failure: true
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step = &job.steps[0].config;
+    assert_eq!(step, &context.config.jobs[0].steps[0]);
+    assert_eq!(step.args["pattern"], "7f454c46");
+    assert_eq!(step.io["namespace"], "firmware");
+    assert_eq!(step.io["key"], "image");
+    assert_eq!(job.config, context.config.jobs[0]);
+}
+
+const FUZZER_MISSING_INPUT_CONFIG: &str = r#"
+projects:
+  - name: testbin
+    binary: test.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: 0x8000000
+      stack_address: 0x20010000
+    mmio: []
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz-parser
+        call: icicle-fuzzer
+        args:
+          project: testbin
+          function: "0x8074e50"
+          harness: ""
+        io:
+          output: corpus
+          solutions: crashes
+"#;
+
+#[tokio::test]
+async fn test_validate_missing_io() {
+    let (_guard, server) = setup_server().await;
+
+    let context = Context {
+        config: load_config(FUZZER_MISSING_INPUT_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+
+    let err = server.validate(&context).unwrap_err();
+    assert!(err.to_string().contains("input"));
+
+    // The pipeline is rejected at submission rather than failing mid-run
+    let result = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await;
+    assert!(matches!(result, Err(PapError::Configuration(_))));
+    assert!(queries::get_pipeline_ids().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_validate_memory_overlap() {
+    let (_guard, server) = setup_server().await;
+
+    let context = |mmio_address: u64| {
+        let config = format!(
+            r#"
+projects:
+  - name: fw
+    binary: fw.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: 0x8000
+      stack_address: 0x20010000
+    mmio:
+      - address: {}
+        size: 4
+        handler: uart
+      - address: 0x40000004
+        size: 4
+        handler: timer
+jobs:
+  - name: fuzz
+    steps:
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: fw
+          function: "0x8000"
+          harness: 'vm.write_reg("r0", input_addr);'
+        io:
+          input: seeds
+"#,
+            mmio_address
+        );
+        Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+            .add_file("fw.bin", vec![0; 0x100])
+            .build()
+            .unwrap()
+    };
+
+    // Registers of one peripheral may share a page
+    server.validate(&context(0x4000_0000)).unwrap();
+
+    let result = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context(0x8080))
+        .await;
+    match result {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("binary region at 0x8000"), "{}", message);
+            assert!(message.contains("MMIO region at 0x8080"), "{}", message);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(queries::get_pipeline_ids().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_validate_empty_jobs() {
+    let (_guard, server) = setup_server().await;
+
+    let context = |config: &str| Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+
+    let no_jobs = context("projects: []\njobs: []\n");
+    let result = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), no_jobs)
+        .await;
+    assert!(
+        matches!(&result, Err(PapError::Configuration(msg)) if msg.contains("no jobs")),
+        "{:?}",
+        result
+    );
+    assert!(queries::get_pipeline_ids().await.unwrap().is_empty());
+
+    // Allowed when asked for
+    let no_jobs = context("projects: []\njobs: []\nallow_empty: true\n");
+    server.validate(&no_jobs).unwrap();
+}
+
+#[tokio::test]
+async fn test_validate_empty_steps() {
+    let (_guard, server) = setup_server().await;
+
+    let config = format!("{}  - name: idle\n    steps: []\n", HELLO_CONFIG);
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let err = server.validate(&context).unwrap_err();
+    assert!(err.to_string().contains("job idle has no steps"), "{}", err);
+
+    let mut context = context;
+    context.config.allow_empty = true;
+    server.validate(&context).unwrap();
+}
+
+#[tokio::test]
+async fn test_list_executors() {
+    let (_guard, server) = setup_server().await;
+
+    let executors = server.list_executors(tarpc::context::current()).await;
+    let names: Vec<_> = executors.iter().map(|e| e.name.as_str()).collect();
+    let mut expected = vec![
+        "grep",
+        "hello",
+        "icicle-fuzzer",
+        "minimize",
+        "reproduce",
+        "script",
+        "triage",
+    ];
+    if cfg!(feature = "fetch") {
+        expected.insert(0, "fetch");
+    }
+    assert_eq!(names, expected);
+    let executor = |name| executors.iter().find(|e| e.name == name).unwrap();
+
+    let hello = executor("hello");
+    let args: Vec<_> = hello
+        .args
+        .iter()
+        .map(|a| (a.name.as_str(), &a.arg_type, a.required))
+        .collect();
+    assert_eq!(
+        args,
+        vec![
+            ("name", &ArgType::String, true),
+            ("count", &ArgType::Integer, false),
+            ("shout", &ArgType::Boolean, false),
+        ]
+    );
+
+    let fuzzer = executor("icicle-fuzzer");
+    let required: Vec<_> = fuzzer
+        .args
+        .iter()
+        .filter(|a| a.required)
+        .map(|a| a.name.as_str())
+        .collect();
+    assert_eq!(required, vec!["project", "function", "harness"]);
+    assert_eq!(fuzzer.io, vec!["input", "output", "solutions"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dry_run_pipeline() {
+    let (_guard, server) = setup_server().await;
+
+    let id = server
+        .clone()
+        .dry_run_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+    assert!(queries::get_pipeline_status(id).await.unwrap().dry_run);
+
+    // The step body never ran, only the dry run note was logged
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = String::from_utf8(job.steps[0].output.clone().unwrap().data).unwrap();
+    assert!(log.contains("Dry run"));
+    assert!(!log.contains("Hello"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pipeline_logs() {
+    let (_guard, server) = setup_server().await;
+
+    let config = format!(
+        "{}{}",
+        HELLO_CONFIG,
+        r#"      - name: say-hi
+        call: hello
+        args:
+          name: there
+  - name: again
+    steps:
+      - name: say-bye
+        call: hello
+        args:
+          name: everyone
+"#
+    );
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let logs = server
+        .clone()
+        .get_pipeline_logs(tarpc::context::current(), id)
+        .await
+        .unwrap();
+
+    // Every step's log is there, in the order the steps ran
+    let mut step_ids = Vec::new();
+    for job_id in queries::get_pipeline_status(id).await.unwrap().jobs {
+        let job = queries::get_job_status(job_id).await.unwrap();
+        step_ids.extend(job.steps.iter().map(|step| step.id));
+    }
+    assert_eq!(logs.iter().map(|(id, _)| *id).collect::<Vec<_>>(), step_ids);
+    for ((step_id, log), name) in logs.iter().zip(["world", "there", "everyone"]) {
+        let log = String::from_utf8_lossy(log);
+        assert!(log.contains(name), "step {}: {}", step_id, log);
+        assert_eq!(
+            server
+                .clone()
+                .get_step_log(tarpc::context::current(), *step_id)
+                .await
+                .unwrap(),
+            log.as_bytes()
+        );
+    }
+
+    assert!(matches!(
+        server
+            .clone()
+            .get_pipeline_logs(tarpc::context::current(), id + 1)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_find_step_by_name() {
+    let (_guard, server) = setup_server().await;
+
+    let config = format!(
+        "{}{}",
+        HELLO_CONFIG,
+        r#"  - name: again
+    steps:
+      - name: say-hello
+        call: hello
+        args:
+          name: again
+      - name: say-bye
+        call: hello
+        args:
+          name: everyone
+"#
+    );
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let find = |job: Option<&str>, step: &str| {
+        server.clone().find_step(
+            tarpc::context::current(),
+            id,
+            job.map(str::to_string),
+            step.to_string(),
+        )
+    };
+    let log = |step_id| {
+        server
+            .clone()
+            .get_step_log(tarpc::context::current(), step_id)
+    };
+
+    // The name resolves to the same step, and so the same log, as its ID
+    let again = queries::get_pipeline_status(id).await.unwrap().jobs[1];
+    let job = queries::get_job_status(again).await.unwrap();
+    for step in &job.steps {
+        let found = find(Some("again"), &step.config.name).await.unwrap();
+        assert_eq!(found, step.id);
+        assert_eq!(log(found).await.unwrap(), log(step.id).await.unwrap());
+    }
+    assert_eq!(find(None, "say-bye").await.unwrap(), job.steps[1].id);
+    let hello = String::from_utf8(
+        log(find(Some("greet"), "say-hello").await.unwrap())
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(hello.contains("world"), "{}", hello);
+
+    // Both jobs have a say-hello step
+    assert!(matches!(
+        find(None, "say-hello").await,
+        Err(PapError::Configuration(msg)) if msg.contains("greet, again")
+    ));
+    assert!(matches!(
+        find(Some("greet"), "say-bye").await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pipeline_history() {
+    let (_guard, server) = setup_server().await;
+
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let history = server
+        .clone()
+        .get_pipeline_history(tarpc::context::current(), id)
+        .await
+        .unwrap();
+    let transitions: Vec<_> = history
+        .iter()
+        .filter(|change| change.entity_type == StatusEntity::Pipeline)
+        .map(|change| {
+            (
+                change.entity_id,
+                change.old_status.clone(),
+                change.new_status.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            (id, ExecutionStatus::Pending, ExecutionStatus::Running),
+            (id, ExecutionStatus::Running, ExecutionStatus::Completed),
+        ]
+    );
+    // The steps' changes are recorded too
+    assert!(history
+        .iter()
+        .any(|change| change.entity_type == StatusEntity::Step
+            && change.new_status == ExecutionStatus::Completed));
+
+    assert!(matches!(
+        server
+            .clone()
+            .get_pipeline_history(tarpc::context::current(), id + 1)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+/// Sends a request to the HTTP gateway, returning the response status and body
+async fn http_request(
+    router: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: Vec<u8>,
+) -> (axum::http::StatusCode, Vec<u8>) {
+    use tower::ServiceExt;
+
+    let request = axum::http::Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(body))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, body.to_vec())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_http_gateway() {
+    use axum::http::StatusCode;
+
+    let (_guard, server) = setup_server().await;
+    let router = http::router(server.clone());
+
+    let body = serde_json::to_vec(&hello_context()).unwrap();
+    let (status, body) = http_request(&router, "POST", "/pipelines", body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = serde_json::from_slice::<SubmitResult>(&body).unwrap().id;
+
+    let uri = format!("/pipelines/{}", id);
+    let get_pipeline = || async {
+        let (status, body) = http_request(&router, "GET", &uri, vec![]).await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_slice::<PipelineStatus>(&body).unwrap()
+    };
+    let finished = wait_until_within(Duration::from_secs(5), || async {
+        get_pipeline().await.status.is_finished()
+    })
+    .await;
+    assert!(finished, "pipeline did not finish");
+    let pipeline = get_pipeline().await;
+    assert_eq!(pipeline.status, ExecutionStatus::Completed);
+
+    let (status, body) = http_request(
+        &router,
+        "GET",
+        &format!("/jobs/{}", pipeline.jobs[0]),
+        vec![],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let job: pap_api::JobStatus = serde_json::from_slice(&body).unwrap();
+    let step_id = job.steps[0].id;
+
+    let (status, body) = http_request(&router, "GET", &format!("/steps/{}", step_id), vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    let step: pap_api::StepStatus = serde_json::from_slice(&body).unwrap();
+    assert_eq!(step.status, ExecutionStatus::Completed);
+    let (_, log) = http_request(&router, "GET", &format!("/steps/{}/log", step_id), vec![]).await;
+    assert!(String::from_utf8_lossy(&log).contains("Hello, world!"));
+
+    let (status, _) = http_request(&router, "PUT", "/objects/http/key", b"value".to_vec()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, body) = http_request(&router, "GET", "/objects/http/key", vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"value");
+
+    // Keys that aren't UTF-8 are written as hex, as the CLI takes them
+    let hex_uri = "/objects/http/00ff2a?key_hex=true";
+    let (status, _) = http_request(&router, "PUT", hex_uri, b"binary".to_vec()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let value = server
+        .get_object(
+            tarpc::context::current(),
+            "http".to_string(),
+            vec![0x00, 0xff, 0x2a],
+        )
+        .await
+        .unwrap();
+    assert_eq!(value, b"binary");
+    let (status, body) = http_request(&router, "GET", hex_uri, vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"binary");
+    let (status, _) = http_request(&router, "GET", "/objects/http/0g?key_hex=true", vec![]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = http_request(&router, "DELETE", &uri, vec![]).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // Errors come back as JSON with a matching status
+    let (status, body) = http_request(&router, "GET", &uri, vec![]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: PapError = serde_json::from_slice(&body).unwrap();
+    assert!(matches!(error, PapError::NotFound(_)));
+    let (status, _) = http_request(&router, "POST", &format!("{}/cancel", uri), vec![]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_http_error_status_codes() {
+    use axum::http::StatusCode;
+
+    let cases = [
+        (PapError::NotFound(String::new()), StatusCode::NOT_FOUND),
+        (
+            PapError::Configuration(String::new()),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            PapError::QuotaExceeded(String::new()),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            PapError::Timeout(String::new()),
+            StatusCode::GATEWAY_TIMEOUT,
+        ),
+        (PapError::Cancelled(String::new()), StatusCode::CONFLICT),
+        (
+            PapError::Database(String::new()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ];
+    for (err, expected) in cases {
+        assert_eq!(http::status_code(&err), expected, "{:?}", err);
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_existing_and_missing() {
+    let (_guard, server) = setup_server().await;
+
+    let first = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let second = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+
+    server
+        .clone()
+        .cancel_job(tarpc::context::current(), second.jobs[0])
+        .await
+        .unwrap();
+    let job = queries::get_job_status(second.jobs[0]).await.unwrap();
+    assert_eq!(job.status, ExecutionStatus::Cancelled);
+    assert!(job
+        .steps
+        .iter()
+        .all(|step| step.status == ExecutionStatus::Cancelled));
+    // Only the job's own pipeline is touched
+    let job = queries::get_job_status(first.jobs[0]).await.unwrap();
+    assert_eq!(job.status, ExecutionStatus::Pending);
+
+    server
+        .clone()
+        .cancel_pipeline(tarpc::context::current(), first.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        queries::get_pipeline_status(first.id).await.unwrap().status,
+        ExecutionStatus::Cancelled
+    );
+
+    let missing = second.id + 1;
+    assert!(matches!(
+        server
+            .clone()
+            .cancel_pipeline(tarpc::context::current(), missing)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+    assert!(matches!(
+        server
+            .clone()
+            .delete_pipeline(tarpc::context::current(), missing)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+    assert!(matches!(
+        server
+            .clone()
+            .cancel_job(tarpc::context::current(), *second.jobs.last().unwrap() + 1)
+            .await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_finished_pipeline() {
+    let (_guard, server) = setup_server().await;
+
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+    let job_id = queries::get_pipeline_status(id).await.unwrap().jobs[0];
+
+    assert!(matches!(
+        server
+            .clone()
+            .cancel_pipeline(tarpc::context::current(), id)
+            .await,
+        Err(PapError::Configuration(_))
+    ));
+    assert!(matches!(
+        server
+            .clone()
+            .cancel_job(tarpc::context::current(), job_id)
+            .await,
+        Err(PapError::Configuration(_))
+    ));
+
+    // Nothing that finished is marked cancelled after the fact
+    assert_eq!(
+        queries::get_pipeline_status(id).await.unwrap().status,
+        ExecutionStatus::Completed
+    );
+    let job = queries::get_job_status(job_id).await.unwrap();
+    assert_eq!(job.status, ExecutionStatus::Completed);
+    assert!(job
+        .steps
+        .iter()
+        .all(|step| step.status == ExecutionStatus::Completed));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_finished_pipeline_forgotten() {
+    let (_guard, server) = setup_server().await;
+
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+    // The task drops its own entry once it has recorded the result
+    assert!(wait_until(|| async { server.running_pipelines().await == 0 }).await);
+}
+
+/// Runs a hello step with extra arguments, returning its log
+async fn hello_log(server: &PipelineServer, args: &str) -> (ExecutionStatus, String) {
+    let config = HELLO_CONFIG.replace("name: world", &format!("name: world\n{}", args));
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    let status = wait_for_pipeline(id).await;
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = queries::get_step_log(job.steps[0].id).await.unwrap();
+    (status, String::from_utf8(log).unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hello_typed_args() {
+    let (_guard, server) = setup_server().await;
+
+    let cases = [
+        ("", "Hello, world!\n"),
+        (
+            "          count: \"3\"",
+            "Hello, world!\nHello, world!\nHello, world!\n",
+        ),
+        ("          shout: \"true\"", "HELLO, WORLD!\n"),
+        (
+            "          count: \"2\"\n          shout: \"true\"",
+            "HELLO, WORLD!\nHELLO, WORLD!\n",
+        ),
+        ("          count: \"0\"\n          shout: \"false\"", ""),
+    ];
+    for (args, expected) in cases {
+        let (status, log) = hello_log(&server, args).await;
+        assert_eq!(status, ExecutionStatus::Completed, "{}", args);
+        assert_eq!(log, expected, "{}", args);
+    }
+
+    // Values that don't match the argument's type fail the step
+    let (status, _) = hello_log(&server, "          count: many").await;
+    assert_eq!(status, ExecutionStatus::Failed);
+}
+
+struct EnvEchoExecutor;
+
+impl StepExecutor for EnvEchoExecutor {
+    fn name(&self) -> String {
+        "env-echo".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let token = ctx.get_env("TOKEN").unwrap_or_default().to_string();
+        ctx.log(&token);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_env_redacted_from_statuses() {
+    use axum::http::StatusCode;
+
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(EnvEchoExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: echo
+    steps:
+      - name: token
+        call: env-echo
+        args: {}
+        env:
+          TOKEN: secret
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    // The step itself sees the real value
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_id = job.steps[0].id;
+    let log = queries::get_step_log(step_id).await.unwrap();
+    assert_eq!(String::from_utf8(log).unwrap().trim(), "secret");
+
+    let pipeline = server
+        .clone()
+        .get_pipeline(tarpc::context::current(), id)
+        .await
+        .unwrap();
+    assert_eq!(pipeline.config.jobs[0].steps[0].env["TOKEN"], REDACTED_ENV);
+    let pipelines = server
+        .clone()
+        .get_pipeline_statuses(tarpc::context::current(), vec![id])
+        .await
+        .unwrap();
+    assert_eq!(
+        pipelines[0].config.jobs[0].steps[0].env["TOKEN"],
+        REDACTED_ENV
+    );
+    let job = server
+        .clone()
+        .get_job(tarpc::context::current(), pipeline.jobs[0])
+        .await
+        .unwrap();
+    assert_eq!(job.config.steps[0].env["TOKEN"], REDACTED_ENV);
+    assert_eq!(job.steps[0].config.env["TOKEN"], REDACTED_ENV);
+    let step = server
+        .clone()
+        .get_step(tarpc::context::current(), step_id)
+        .await
+        .unwrap();
+    assert_eq!(step.config.env["TOKEN"], REDACTED_ENV);
+
+    let router = http::router(server);
+    for uri in [
+        format!("/pipelines/{}", id),
+        format!("/jobs/{}", pipeline.jobs[0]),
+        format!("/steps/{}", step_id),
+    ] {
+        let (status, body) = http_request(&router, "GET", &uri, vec![]).await;
+        assert_eq!(status, StatusCode::OK);
+        let body = String::from_utf8(body).unwrap();
+        assert!(!body.contains("secret"), "{}: {}", uri, body);
+        assert!(body.contains(REDACTED_ENV), "{}: {}", uri, body);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_object_writes_with_wal() {
+    let _guard = DB_LOCK.lock().await;
+
+    let path = std::env::temp_dir().join(format!("pap-wal-test-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let pool = PoolConfig::default()
+        .connect(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .unwrap();
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(journal_mode, "wal");
+    init_pool(pool).unwrap();
+    queries::init_tables().await.unwrap();
+
+    let writers: Vec<_> = (0..8u8)
+        .map(|writer| {
+            tokio::spawn(async move {
+                for i in 0..50u8 {
+                    queries::put_object("wal-test", &[writer, i], &[i; 64], None).await?;
+                }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap().unwrap();
+    }
+
+    assert_eq!(
+        queries::get_object("wal-test", &[7, 49]).await.unwrap(),
+        vec![49; 64]
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_overwritten_object_changes_owner() {
+    let _guard = setup_db().await;
+
+    let mut steps = Vec::new();
+    for _ in 0..2 {
+        let pipeline = queries::setup_pipeline(&hello_context(), false)
+            .await
+            .unwrap();
+        let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+            .bind(pipeline.id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap();
+        steps.push((pipeline.id, step));
+    }
+    let (first, first_step) = steps[0];
+    let (second, second_step) = steps[1];
+
+    queries::put_object("shared", b"key", b"first", Some(first_step))
+        .await
+        .unwrap();
+    queries::put_object("shared", b"key", b"second", Some(second_step))
+        .await
+        .unwrap();
+    assert_eq!(
+        queries::get_object("shared", b"key").await.unwrap(),
+        b"second"
+    );
+
+    // The key's bytes now count against the pipeline that overwrote it
+    let object_bytes = |id: u32| async move {
+        sqlx::query_scalar::<_, i64>("SELECT object_bytes FROM pipelines WHERE id = ?")
+            .bind(id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap()
+    };
+    assert_eq!(object_bytes(first).await, 0);
+    assert_eq!(object_bytes(second).await, b"second".len() as i64);
+
+    // Deleting the pipeline that first wrote the key leaves the data a later
+    // pipeline wrote there
+    queries::delete_pipeline(first).await.unwrap();
+    assert_eq!(
+        queries::get_object("shared", b"key").await.unwrap(),
+        b"second"
+    );
+
+    // Cleaning up after the step that overwrote the key removes it
+    queries::delete_step_objects(second_step).await.unwrap();
+    assert!(queries::get_object("shared", b"key").await.is_err());
+    assert_eq!(object_bytes(second).await, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_object_batch_retries_locked_database() {
+    use sqlx::Connection;
+
+    let _guard = DB_LOCK.lock().await;
+
+    let path = std::env::temp_dir().join(format!("pap-lock-test-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = format!("sqlite://{}?mode=rwc", path.display());
+    // Fail at once on a locked database rather than waiting for it
+    let pool = PoolConfig {
+        busy_timeout: Duration::ZERO,
+        ..PoolConfig::default()
+    }
+    .connect(&url)
+    .await
+    .unwrap();
+    init_pool(pool).unwrap();
+    queries::init_tables().await.unwrap();
+
+    // Another connection holds the write lock for a moment
+    let mut locker = sqlx::SqliteConnection::connect(&url).await.unwrap();
+    sqlx::query("BEGIN IMMEDIATE")
+        .execute(&mut locker)
+        .await
+        .unwrap();
+    let err = queries::put_object("locked", b"direct", b"value", None)
+        .await
+        .unwrap_err();
+    assert!(crate::db::is_transient(&err), "{}", err);
+    // Running out of connections is not retried
+    assert!(!crate::db::is_transient(&sqlx::Error::PoolTimedOut.into()));
+
+    let unlock = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        sqlx::query("COMMIT").execute(&mut locker).await.unwrap();
+    });
+    let flushes = tokio::task::spawn_blocking(|| {
+        let batch = ObjectBatch::new("locked".to_string(), None, 16, Duration::from_secs(60));
+        batch.put(b"key", b"value").unwrap();
+        batch.flush().unwrap();
+        batch.flushes()
+    })
+    .await
+    .unwrap();
+    unlock.await.unwrap();
+
+    assert_eq!(flushes, 1);
+    assert_eq!(
+        queries::get_object("locked", b"key").await.unwrap(),
+        b"value"
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_writes_to_same_key() {
+    let _guard = setup_db().await;
+
+    let writers: Vec<_> = (1..=2u8)
+        .map(|writer| {
+            tokio::spawn(async move {
+                for i in 0..50u8 {
+                    queries::put_object("shared", b"key", &[writer, i], None).await?;
+                }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap().unwrap();
+    }
+
+    // Whichever writer committed last holds the key with its final value
+    let value = queries::get_object("shared", b"key").await.unwrap();
+    assert!(value == [1, 49] || value == [2, 49], "got {:?}", value);
+
+    queries::put_object("shared", b"key", b"last", None)
+        .await
+        .unwrap();
+    assert_eq!(
+        queries::get_object("shared", b"key").await.unwrap(),
+        b"last"
+    );
+}
+
+#[tokio::test]
+async fn test_exclusive_namespaces() {
+    let _guard = setup_db().await;
+
+    let mut steps = Vec::new();
+    for _ in 0..2 {
+        let pipeline = queries::setup_pipeline(&hello_context(), false)
+            .await
+            .unwrap();
+        let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+            .bind(pipeline.id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap();
+        steps.push((pipeline.id, step));
+    }
+    let (first, first_step) = steps[0];
+    let (_, second_step) = steps[1];
+
+    // Shared by default
+    queries::put_object("corpus", b"a", b"first", Some(first_step))
+        .await
+        .unwrap();
+    queries::put_object("corpus", b"b", b"second", Some(second_step))
+        .await
+        .unwrap();
+
+    set_exclusive_namespaces(true);
+    let rejected = queries::put_object("corpus", b"rejected", b"second", Some(second_step)).await;
+    let unowned = queries::put_object("other", b"c", b"second", Some(second_step)).await;
+    queries::set_pipeline_status(first, ExecutionStatus::Completed)
+        .await
+        .unwrap();
+    let finished = queries::put_object("corpus", b"c", b"second", Some(second_step)).await;
+    set_exclusive_namespaces(false);
+
+    let err = rejected.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<PapError>(),
+            Some(PapError::Configuration(_))
+        ),
+        "got {:?}",
+        err
+    );
+    unowned.unwrap();
+    finished.unwrap();
+    assert!(!queries::object_exists("corpus", b"rejected").await.unwrap());
+    assert_eq!(
+        queries::get_object("corpus", b"c").await.unwrap(),
+        b"second"
+    );
+}
+
+#[tokio::test]
+async fn test_exclusive_namespaces_same_key() {
+    let _guard = setup_db().await;
+
+    let mut steps = Vec::new();
+    for _ in 0..2 {
+        let pipeline = queries::setup_pipeline(&hello_context(), false)
+            .await
+            .unwrap();
+        let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+            .bind(pipeline.id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap();
+        steps.push(step);
+    }
+
+    // Overwriting the only key the first pipeline wrote must not take the
+    // namespace over from it
+    set_exclusive_namespaces(true);
+    let owned = queries::put_object("solo", b"key", b"first", Some(steps[0])).await;
+    let single = queries::put_object("solo", b"key", b"second", Some(steps[1])).await;
+    let batch = queries::put_objects_batch(
+        "solo",
+        &[(b"key".to_vec(), b"second".to_vec())],
+        Some(steps[1]),
+    )
+    .await;
+    set_exclusive_namespaces(false);
+
+    owned.unwrap();
+    for result in [single, batch] {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<PapError>(),
+                Some(PapError::Configuration(_))
+            ),
+            "got {:?}",
+            err
+        );
+    }
+    assert_eq!(queries::get_object("solo", b"key").await.unwrap(), b"first");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_exclusive_namespaces_concurrent_writers() {
+    let _guard = DB_LOCK.lock().await;
+
+    // Writers only contend for the lock with a database on disk
+    let path = std::env::temp_dir().join(format!("pap-exclusive-test-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = format!("sqlite://{}?mode=rwc", path.display());
+    init_pool(PoolConfig::default().connect(&url).await.unwrap()).unwrap();
+    queries::init_tables().await.unwrap();
+
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+        .bind(pipeline.id)
+        .fetch_one(&with_pool().unwrap())
+        .await
+        .unwrap();
+
+    // Parallel writers from one pipeline share its namespace without the
+    // ownership check failing their transactions as busy
+    set_exclusive_namespaces(true);
+    let writers: Vec<_> = (1..=4u8)
+        .map(|writer| {
+            tokio::spawn(async move {
+                for i in 0..25u8 {
+                    queries::put_object("parallel", &[writer, i], &[i], Some(step)).await?;
+                }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    let results = futures::future::join_all(writers).await;
+    set_exclusive_namespaces(false);
+
+    for result in results {
+        result.unwrap().unwrap();
+    }
+    assert_eq!(
+        queries::get_object_keys("parallel").await.unwrap().len(),
+        100
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_object_batch_coalesces_writes() {
+    let _guard = setup_db().await;
+
+    let flushes = tokio::task::spawn_blocking(|| {
+        let batch = ObjectBatch::new("batch-test".to_string(), None, 16, Duration::from_secs(60));
+        for i in 0..100u8 {
+            batch.put(&[i], &[i; 8]).unwrap();
+        }
+
+        // Buffered entries are visible before they are flushed
+        assert_eq!(batch.get(&[99]).unwrap(), vec![99; 8]);
+
+        batch.flush().unwrap();
+        batch.flushes()
+    })
+    .await
+    .unwrap();
+
+    assert!(flushes <= 7, "expected few transactions, got {}", flushes);
+    for i in 0..100u8 {
+        assert_eq!(
+            queries::get_object("batch-test", &[i]).await.unwrap(),
+            vec![i; 8]
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_object_batch_flushes_on_drop() {
+    let _guard = setup_db().await;
+
+    tokio::task::spawn_blocking(|| {
+        let batch = ObjectBatch::new("dropped".to_string(), None, 16, Duration::from_secs(60));
+        batch.put(b"key", b"value").unwrap();
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        queries::get_object("dropped", b"key").await.unwrap(),
+        b"value"
+    );
+
+    // Without a runtime to flush on, the writes are lost but nothing panics
+    std::thread::spawn(|| {
+        let batch = ObjectBatch::new("dropped".to_string(), None, 16, Duration::from_secs(60));
+        batch.put(b"lost", b"value").unwrap();
+    })
+    .join()
+    .unwrap();
+    assert!(matches!(
+        queries::get_object("dropped", b"lost").await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+/// Writes and reads back many objects through the step context
+struct ObjectStressExecutor;
+
+impl StepExecutor for ObjectStressExecutor {
+    fn name(&self) -> String {
+        "object-stress".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let namespace = format!("stress-{}", ctx.status.id);
+        for i in 0..500u32 {
+            ctx.write_object(&namespace, &i.to_be_bytes(), &i.to_le_bytes())?;
+        }
+        for i in 0..500u32 {
+            anyhow::ensure!(ctx.read_object(&namespace, &i.to_be_bytes())? == i.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+const OBJECT_STRESS_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: stress
+    steps:
+      - name: write-objects
+        call: object-stress
+        args: {}
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_step_object_writes_stress() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(ObjectStressExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        let context = Context {
+            config: load_config(OBJECT_STRESS_CONFIG.as_bytes()).unwrap(),
+            files: HashMap::new(),
+        };
+        ids.push(
+            server
+                .clone()
+                .submit_pipeline(tarpc::context::current(), context)
+                .await
+                .unwrap()
+                .id,
+        );
+    }
+
+    // Thousands of round trips to the database take a while on a busy machine
+    for id in ids {
+        assert_eq!(
+            wait_for_pipeline_within(id, Duration::from_secs(10)).await,
+            ExecutionStatus::Completed
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_write_object_outside_block_in_place() {
+    let _guard = setup_db().await;
+
+    let context = hello_context();
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_context = StepContext::new(&job.steps[0], &pipeline, &context);
+
+    // Called straight from an async task on a single-threaded runtime
+    step_context
+        .write_object("direct", b"key", b"value")
+        .unwrap();
+    assert_eq!(
+        step_context.read_object("direct", b"key").unwrap(),
+        b"value"
+    );
+    assert!(!step_context.is_cancelled());
+}
+
+struct FailingWriterExecutor;
+
+impl StepExecutor for FailingWriterExecutor {
+    fn name(&self) -> String {
+        "failing-writer".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        ctx.write_object("partial", &ctx.status.id.to_be_bytes(), b"partial")?;
+        ctx.write_object("partial", b"existing", b"partial")?;
+        anyhow::bail!("failed after writing an object")
+    }
+}
+
+/// Runs a step that writes an object and then fails, returning whether the
+/// object is still stored afterwards
+async fn failing_step_keeps_object(cleanup_on_failure: bool) -> bool {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(FailingWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = format!(
+        r#"
+projects: []
+jobs:
+  - name: fail
+    steps:
+      - name: write-then-fail
+        call: failing-writer
+        args: {{}}
+        cleanup_on_failure: {}
+"#,
+        cleanup_on_failure
+    );
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let step_id = job.steps[0].id;
+    match queries::get_object("partial", &step_id.to_be_bytes()).await {
+        Ok(_) => true,
+        Err(PapError::NotFound(_)) => false,
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_step_objects_cleaned_up() {
+    assert!(!failing_step_keeps_object(true).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_step_objects_retained() {
+    assert!(failing_step_keeps_object(false).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_step_overwrites_cleaned_up() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(FailingWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+    queries::put_object("partial", b"existing", b"before", None)
+        .await
+        .unwrap();
+
+    let config = r#"
+projects: []
+jobs:
+  - name: fail
+    steps:
+      - name: write-then-fail
+        call: failing-writer
+        args: {}
+        cleanup_on_failure: true
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    // The key the step overwrote isn't left half-written
+    assert!(matches!(
+        queries::get_object("partial", b"existing").await,
+        Err(PapError::NotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_script_step() {
+    let (_guard, server) = setup_server().await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: glue
+    steps:
+      - name: note
+        call: script
+        args:
+          greeting: hi
+          script: |
+            let greeting = get_arg("greeting");
+            write_object("notes", "greeting", greeting + " there");
+            log("wrote " + read_object("notes", "greeting").as_string());
+            if get_arg("missing") == () {
+                print("no missing arg");
+            }
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline(id).await,
+        ExecutionStatus::Completed,
+        "{:?}",
+        pipeline_error(id).await
+    );
+
+    assert_eq!(
+        queries::get_object("notes", b"greeting").await.unwrap(),
+        b"hi there"
+    );
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(
+        job.steps[0].output.as_ref().unwrap().data,
+        b"wrote hi there\nno missing arg\n"
+    );
+
+    // Runaway scripts are stopped
+    let config = r#"
+projects: []
+jobs:
+  - name: spin
+    steps:
+      - name: forever
+        call: script
+        args:
+          op_limit: "1000"
+          script: "loop {}"
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(message.contains("script failed"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_script_step_size_limits() {
+    let (_guard, server) = setup_server().await;
+
+    // Doubling a string outgrows any memory in a few dozen operations, well
+    // within the operation limit
+    let config = r#"
+projects: []
+jobs:
+  - name: grow
+    steps:
+      - name: double
+        call: script
+        args:
+          script: |
+            let s = "x";
+            loop { s += s; }
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(message.contains("script failed"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+/// Logs its `text` argument reversed
+struct ReverseExecutor;
+
+impl StepExecutor for ReverseExecutor {
+    fn name(&self) -> String {
+        "reverse".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let text = ctx
+            .get_arg("text")
+            .ok_or_else(|| anyhow::anyhow!("missing `text` argument"))?;
+        ctx.log(&text.chars().rev().collect::<String>());
+        Ok(())
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            args: vec!["text".to_string()],
+            ..Default::default()
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_custom_executor_with_builtins() {
+    let mut registry = StepExecutorRegistry::with_builtins();
+    registry.register(ReverseExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let executors = server
+        .clone()
+        .list_executors(tarpc::context::current())
+        .await;
+    assert!(executors.iter().any(|info| info.name == "reverse"));
+    assert!(executors.iter().any(|info| info.name == "hello"));
+
+    let config = r#"
+projects: []
+jobs:
+  - name: words
+    steps:
+      - name: greet
+        call: hello
+        args:
+          name: world
+      - name: backwards
+        call: reverse
+        args:
+          text: stressed
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(job.steps[1].output.as_ref().unwrap().data, b"desserts\n");
+}
+
+/// Reports what it found as JSON
+struct JsonOutputExecutor;
+
+impl StepExecutor for JsonOutputExecutor {
+    fn name(&self) -> String {
+        "json-output".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        ctx.set_output_kind(OutputKind::Json);
+        ctx.log_raw(br#"{"found":2}"#);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_step_output_kind() {
+    let mut registry = builtin_executors();
+    registry.register(JsonOutputExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: report
+    steps:
+      - name: greet
+        call: hello
+        args:
+          name: world
+      - name: summarize
+        call: json-output
+        args: {}
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(
+        job.steps[0].output,
+        Some(StepOutput {
+            kind: OutputKind::Text,
+            data: b"Hello, world!\n".to_vec(),
+        })
+    );
+    assert_eq!(
+        job.steps[1].output,
+        Some(StepOutput {
+            kind: OutputKind::Json,
+            data: br#"{"found":2}"#.to_vec(),
+        })
+    );
+    let step = queries::get_step_status(job.steps[1].id).await.unwrap();
+    assert_eq!(step.output.unwrap().kind, OutputKind::Json);
+}
+
+/// Writes an object keyed by its step ID to its `output` namespace
+struct OutputWriterExecutor;
+
+impl StepExecutor for OutputWriterExecutor {
+    fn name(&self) -> String {
+        "output-writer".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let namespace = ctx
+            .get_io("output")
+            .ok_or_else(|| anyhow::anyhow!("missing output namespace"))?;
+        ctx.write_object(namespace, &ctx.status.id.to_be_bytes(), b"corpus")
+    }
+
+    fn requirements(&self) -> StepRequirements {
+        StepRequirements {
+            io: vec!["output".to_string()],
+            default_io: vec!["output".to_string()],
+            ..Default::default()
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_default_io_namespaces() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(OutputWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: write
+    steps:
+      - name: first
+        call: output-writer
+        args: {}
+      - name: second
+        call: output-writer
+        args: {}
+      - name: shared
+        call: output-writer
+        args: {}
+        io:
+          output: shared
+"#;
+    let context = Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let namespaces: Vec<_> = job
+        .steps
+        .iter()
+        .map(|step| step.config.io["output"].clone())
+        .collect();
+    for (step, namespace) in job.steps.iter().zip(&namespaces).take(2) {
+        assert_eq!(
+            namespace,
+            &queries::default_io_namespace(id, job.id, step.id, "output")
+        );
+        // Each step only sees its own corpus
+        let keys = queries::get_object_keys(namespace).await.unwrap();
+        assert_eq!(keys, vec![step.id.to_be_bytes().to_vec()]);
+    }
+    assert_ne!(namespaces[0], namespaces[1]);
+    // Explicit namespaces are kept
+    assert_eq!(namespaces[2], "shared");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_export_config() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(OutputWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+labels:
+  team: firmware
+timeout_secs: 60
+projects: []
+jobs:
+  - name: write
+    steps:
+      - name: first
+        call: output-writer
+        args:
+          note: "exported as is"
+        env:
+          TOKEN: secret
+      - name: shared
+        call: output-writer
+        args: {}
+        io:
+          output: shared
+"#;
+    let config = load_config(config.as_bytes()).unwrap();
+    let context = Context::builder(config.clone(), ".".into())
+        .build()
+        .unwrap();
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let yaml = server
+        .clone()
+        .export_config(tarpc::context::current(), id)
+        .await
+        .unwrap();
+    let exported = load_config(yaml.as_bytes()).unwrap();
+
+    // The only differences are the namespace the server gave the unset output
+    // and the redacted env
+    assert!(!yaml.contains("secret"), "{}", yaml);
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let mut expected = config;
+    expected.redact_env();
+    expected.jobs[0].steps[0].io.insert(
+        "output".to_string(),
+        queries::default_io_namespace(id, job.id, job.steps[0].id, "output"),
+    );
+    assert_eq!(exported, expected);
+
+    let result = server.export_config(tarpc::context::current(), 999).await;
+    assert!(matches!(result, Err(PapError::NotFound(_))), "{:?}", result);
+}
+
+struct WaitForCancelExecutor;
+
+impl StepExecutor for WaitForCancelExecutor {
+    fn name(&self) -> String {
+        "wait-for-cancel".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        // Bounded so a missed cancellation fails the test instead of hanging it
+        for _ in 0..500 {
+            if ctx.is_cancelled() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        anyhow::bail!("step was never cancelled")
+    }
+}
+
+const WAIT_FOR_CANCEL_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: wait
+    steps:
+      - name: wait-for-cancel
+        call: wait-for-cancel
+        args: {}
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancelled_pipeline_error() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(WaitForCancelExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let context = Context {
+        config: load_config(WAIT_FOR_CANCEL_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+
+    // Cancel only once the step is running, so the cancellation isn't
+    // overwritten as the pipeline starts
+    let job_id = queries::get_pipeline_status(id).await.unwrap().jobs[0];
+    assert!(step_started(job_id, 0).await);
+    server
+        .clone()
+        .cancel_pipeline(tarpc::context::current(), id)
+        .await
+        .unwrap();
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Cancelled);
+
+    // Give the executor a moment to notice and the server to record why
+    let error = pipeline_error(id).await;
+    assert!(matches!(error, Some(PapError::Cancelled(_))), "{:?}", error);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(job.steps[0].status, ExecutionStatus::Cancelled);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_all_pipelines() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(WaitForCancelExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    // A finished pipeline is left alone
+    let finished = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    queries::set_pipeline_status(finished.id, ExecutionStatus::Completed)
+        .await
+        .unwrap();
+
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let context = Context {
+            config: load_config(WAIT_FOR_CANCEL_CONFIG.as_bytes()).unwrap(),
+            files: HashMap::new(),
+        };
+        let id = server
+            .clone()
+            .submit_pipeline(tarpc::context::current(), context)
+            .await
+            .unwrap()
+            .id;
+        ids.push(id);
+    }
+    for &id in &ids {
+        let job_id = queries::get_pipeline_status(id).await.unwrap().jobs[0];
+        assert!(step_started(job_id, 0).await);
+    }
+
+    let count = server
+        .clone()
+        .cancel_all_pipelines(tarpc::context::current())
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    // Their tasks were stopped and are no longer tracked
+    assert_eq!(server.running_pipelines().await, 0);
+    for id in ids {
+        assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Cancelled);
+        assert!(matches!(
+            pipeline_error(id).await,
+            Some(PapError::Cancelled(_))
+        ));
+    }
+    assert_eq!(
+        queries::get_pipeline_status(finished.id)
+            .await
+            .unwrap()
+            .status,
+        ExecutionStatus::Completed
+    );
+
+    // Nothing is left to cancel
+    let count = server
+        .cancel_all_pipelines(tarpc::context::current())
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn test_cancel_keeps_completed_steps() {
+    let _guard = setup_db().await;
+
+    // Two pipelines part way through, each with its first step completed
+    let mut pipelines = Vec::new();
+    for _ in 0..2 {
+        let context = Context {
+            config: load_config(SECOND_STEP_FAILS_CONFIG.as_bytes()).unwrap(),
+            files: HashMap::new(),
+        };
+        let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+        queries::set_pipeline_status(pipeline.id, ExecutionStatus::Running)
+            .await
+            .unwrap();
+        let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+        queries::set_step_status(job.steps[0].id, ExecutionStatus::Completed)
+            .await
+            .unwrap();
+        queries::set_step_status(job.steps[1].id, ExecutionStatus::Running)
+            .await
+            .unwrap();
+        pipelines.push(job.id);
+    }
+
+    queries::cancel_job(pipelines[0]).await.unwrap();
+    assert_eq!(queries::cancel_all_pipelines().await.unwrap().len(), 2);
+    for job_id in pipelines {
+        let job = queries::get_job_status(job_id).await.unwrap();
+        assert_eq!(job.status, ExecutionStatus::Cancelled);
+        assert_eq!(job.steps[0].status, ExecutionStatus::Completed);
+        assert_eq!(job.steps[1].status, ExecutionStatus::Cancelled);
+    }
+}
+
+const SECOND_STEP_FAILS_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: two-steps
+    steps:
+      - name: say-hello
+        call: hello
+        args:
+          name: world
+      - name: write-then-fail
+        call: failing-writer
+        args: {}
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_error_records_failed_step() {
+    let mut registry = builtin_executors();
+    registry.register(FailingWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let context = Context {
+        config: load_config(SECOND_STEP_FAILS_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    // The status flips to failed just before the error is stored
+    pipeline_error(id).await;
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(pipeline.failed_job, Some(job.id));
+    assert_eq!(pipeline.failed_step, Some(job.steps[1].id));
+    match pipeline.error {
+        Some(PapError::Execution(message)) => assert!(message.contains("write-then-fail")),
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_failed_pipeline() {
+    let mut registry = builtin_executors();
+    registry.register(FailingWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let context = Context {
+        config: load_config(SECOND_STEP_FAILS_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    // The status flips to failed just before the error is stored
+    assert!(pipeline_error(id).await.is_some());
+
+    server
+        .clone()
+        .delete_pipeline(tarpc::context::current(), id)
+        .await
+        .unwrap();
+
+    let count = |table: &'static str| async move {
+        let query = format!("SELECT COUNT(*) FROM {} WHERE pipeline_id = ?", table);
+        sqlx::query_scalar::<_, i64>(&query)
+            .bind(id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap()
+    };
+    assert_eq!(count("global_errors").await, 0);
+    assert_eq!(count("objects").await, 0);
+    assert_eq!(count("steps").await, 0);
+    assert_eq!(count("jobs").await, 0);
+}
+
+/// Counts its runs, failing the first `failures` of them
+struct CountingExecutor {
+    name: &'static str,
+    runs: Arc<AtomicUsize>,
+    failures: usize,
+}
+
+impl StepExecutor for CountingExecutor {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn execute(&self, _ctx: &mut StepContext) -> anyhow::Result<()> {
+        if self.runs.fetch_add(1, Ordering::SeqCst) < self.failures {
+            anyhow::bail!("failing run of {}", self.name);
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_resume_pipeline() {
+    let (first_runs, second_runs) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(CountingExecutor {
+        name: "first",
+        runs: first_runs.clone(),
+        failures: 0,
+    });
+    registry.register(CountingExecutor {
+        name: "second",
+        runs: second_runs.clone(),
+        failures: 1,
+    });
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: two-steps
+    steps:
+      - name: first
+        call: first
+        args: {}
+      - name: second
+        call: second
+        args: {}
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    // Only a pipeline that has finished failing can be resumed
+    let resumed = wait_until(|| async {
+        server
+            .clone()
+            .resume_pipeline(tarpc::context::current(), id)
+            .await
+            .is_ok()
+    })
+    .await;
+    assert!(resumed);
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    assert_eq!(first_runs.load(Ordering::SeqCst), 1);
+    assert_eq!(second_runs.load(Ordering::SeqCst), 2);
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    assert!(pipeline.error.is_none());
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(job.status, ExecutionStatus::Completed);
+    assert!(job
+        .steps
+        .iter()
+        .all(|step| step.status == ExecutionStatus::Completed));
+
+    // There is nothing left to resume
+    let err = server
+        .clone()
+        .resume_pipeline(tarpc::context::current(), id)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, PapError::Configuration(_)), "{:?}", err);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_resume_cancelled_pipeline() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(CountingExecutor {
+        name: "first",
+        runs: runs.clone(),
+        failures: 0,
+    });
+    registry.register(WaitForCancelExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: two-steps
+    steps:
+      - name: first
+        call: first
+        args: {}
+      - name: wait-for-cancel
+        call: wait-for-cancel
+        args: {}
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    let job_id = queries::get_pipeline_status(id).await.unwrap().jobs[0];
+
+    // Cancels the pipeline once its second step is running
+    let cancel_while_waiting = || async {
+        assert!(step_started(job_id, 1).await);
+        server
+            .clone()
+            .cancel_pipeline(tarpc::context::current(), id)
+            .await
+            .unwrap();
+        assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Cancelled);
+    };
+
+    cancel_while_waiting().await;
+    let job = queries::get_job_status(job_id).await.unwrap();
+    assert_eq!(job.steps[0].status, ExecutionStatus::Completed);
+    assert_eq!(job.steps[1].status, ExecutionStatus::Cancelled);
+
+    let resumed = wait_until(|| async {
+        server
+            .clone()
+            .resume_pipeline(tarpc::context::current(), id)
+            .await
+            .is_ok()
+    })
+    .await;
+    assert!(resumed);
+    cancel_while_waiting().await;
+
+    // Only the step that was cancelled ran again
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    let job = queries::get_job_status(job_id).await.unwrap();
+    assert_eq!(job.steps[0].status, ExecutionStatus::Completed);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_step() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(WaitForCancelExecutor);
+    registry.register(CountingExecutor {
+        name: "after",
+        runs: runs.clone(),
+        failures: 0,
+    });
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+jobs:
+  - name: two-steps
+    steps:
+      - name: wait-for-cancel
+        call: wait-for-cancel
+        args: {}
+      - name: after
+        call: after
+        args: {}
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+
+    let job_id = queries::get_pipeline_status(id).await.unwrap().jobs[0];
+    assert!(step_started(job_id, 0).await);
+    let job = queries::get_job_status(job_id).await.unwrap();
+    server
+        .clone()
+        .cancel_step(tarpc::context::current(), job.steps[0].id)
+        .await
+        .unwrap();
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    let job = queries::get_job_status(job_id).await.unwrap();
+    assert_eq!(job.status, ExecutionStatus::Completed);
+    assert_eq!(job.steps[0].status, ExecutionStatus::Cancelled);
+    assert_eq!(job.steps[1].status, ExecutionStatus::Completed);
+
+    // A finished step can't be cancelled
+    let err = server
+        .clone()
+        .cancel_step(tarpc::context::current(), job.steps[1].id)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, PapError::Configuration(_)), "{:?}", err);
+}
+
+/// Runs a step until it is stopped by a pipeline or job timeout of a second,
+/// followed by steps and a job that never get to run
+async fn run_past_timeout(pipeline_timeout: bool) -> (PipelineStatus, Option<PapError>) {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(WaitForCancelExecutor);
+    registry.register(CountingExecutor {
+        name: "after",
+        runs: runs.clone(),
+        failures: 0,
+    });
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let (pipeline_timeout, job_timeout) = if pipeline_timeout {
+        ("timeout_secs: 1", "")
+    } else {
+        ("", "timeout_secs: 1")
+    };
+    let config = format!(
+        r#"
+{}
+projects: []
+jobs:
+  - name: slow
+    {}
+    steps:
+      - name: wait-for-cancel
+        call: wait-for-cancel
+        args: {{}}
+      - name: after
+        call: after
+        args: {{}}
+  - name: next
+    steps:
+      - name: after
+        call: after
+        args: {{}}
+"#,
+        pipeline_timeout, job_timeout
+    );
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(4)).await,
+        ExecutionStatus::Failed
+    );
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+    let error = pipeline_error(id).await;
+    (queries::get_pipeline_status(id).await.unwrap(), error)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pipeline_timeout() {
+    let (pipeline, error) = run_past_timeout(true).await;
+    match error {
+        Some(PapError::Timeout(message)) => {
+            assert!(message.contains("ran for over 1s"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+
+    // The running step failed, and nothing after it was left pending
+    let slow = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(slow.status, ExecutionStatus::Failed);
+    assert_eq!(slow.steps[0].status, ExecutionStatus::Failed);
+    assert_eq!(slow.steps[1].status, ExecutionStatus::Cancelled);
+    assert_eq!(pipeline.failed_step, Some(slow.steps[0].id));
+
+    let next = queries::get_job_status(pipeline.jobs[1]).await.unwrap();
+    assert_eq!(next.status, ExecutionStatus::Cancelled);
+    assert_eq!(next.steps[0].status, ExecutionStatus::Cancelled);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_job_timeout() {
+    let (pipeline, error) = run_past_timeout(false).await;
+    match error {
+        Some(PapError::Timeout(message)) => {
+            assert!(message.contains("job slow ran for over 1s"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+
+    let next = queries::get_job_status(pipeline.jobs[1]).await.unwrap();
+    assert_eq!(next.status, ExecutionStatus::Cancelled);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_execute_skips_cancelled_step() {
+    let mut registry = builtin_executors();
+    registry.register(FailingWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let context = Context {
+        config: load_config(SECOND_STEP_FAILS_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let pipeline = server.setup_pipeline(&context, false).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    // The failing step never runs, so the pipeline completes
+    queries::set_step_status(job.steps[1].id, ExecutionStatus::Cancelled)
+        .await
+        .unwrap();
+
+    server.execute_blocking(&pipeline).await;
+
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(job.steps[0].status, ExecutionStatus::Completed);
+    assert_eq!(job.steps[1].status, ExecutionStatus::Cancelled);
+    assert!(job.steps[1].started_at.is_none());
+    let pipeline = queries::get_pipeline_status(pipeline.id).await.unwrap();
+    assert_eq!(pipeline.status, ExecutionStatus::Completed);
+}
+
+#[tokio::test]
+async fn test_steps_by_job_use_index() {
+    let _guard = setup_db().await;
+
+    let query = "EXPLAIN QUERY PLAN SELECT id, name FROM steps WHERE job_id = ?";
+    let rows = sqlx::query(query)
+        .bind(1)
+        .fetch_all(&with_pool().unwrap())
+        .await
+        .unwrap();
+    let plan: Vec<String> = rows.iter().map(|row| row.get(3)).collect();
+    let uses_index = |detail: &String| detail.contains("USING INDEX idx_steps_job_id");
+    assert!(plan.iter().any(uses_index), "{:?}", plan);
+}
+
+#[tokio::test]
+async fn test_list_namespaces() {
+    let (_guard, server) = setup_server().await;
+
+    for namespace in ["results", "corpus"] {
+        server
+            .clone()
+            .put_object(
+                tarpc::context::current(),
+                namespace.to_string(),
+                b"key".to_vec(),
+                b"value".to_vec(),
+            )
+            .await
+            .unwrap();
+    }
+    // A namespace with several objects is listed once
+    queries::put_object("corpus", b"other", b"value", None)
+        .await
+        .unwrap();
+
+    let namespaces = server
+        .list_namespaces(tarpc::context::current())
+        .await
+        .unwrap();
+    assert_eq!(namespaces, vec!["corpus", "results"]);
+}
+
+#[tokio::test]
+async fn test_compressed_round_trip() {
+    let _guard = setup_db().await;
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let step_id = queries::get_job_status(pipeline.jobs[0])
+        .await
+        .unwrap()
+        .steps[0]
+        .id;
+    let data = b"[Stats] corpus: 12, objectives: 0\n".repeat(100);
+
+    set_compression(true);
+    queries::put_object("compressed", b"key", &data, None)
+        .await
+        .unwrap();
+    let output = StepOutput {
+        kind: OutputKind::Text,
+        data: data.clone(),
+    };
+    queries::set_step_output(step_id, &output).await.unwrap();
+    set_compression(false);
+
+    // Repetitive data is stored in less space than it takes up
+    let stored: Vec<u8> =
+        sqlx::query_scalar("SELECT value FROM objects WHERE namespace = 'compressed'")
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap();
+    assert!(stored.len() < data.len());
+
+    // Compressed data reads back regardless of the current setting
+    assert_eq!(
+        queries::get_object("compressed", b"key").await.unwrap(),
+        data
+    );
+    assert_eq!(queries::get_step_log(step_id).await.unwrap(), data);
+    let step = queries::get_step_status(step_id).await.unwrap();
+    assert_eq!(step.output, Some(output));
+}
+
+#[tokio::test]
+async fn test_uncompressed_rows_read_with_compression() {
+    let _guard = setup_db().await;
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let step_id = queries::get_job_status(pipeline.jobs[0])
+        .await
+        .unwrap()
+        .steps[0]
+        .id;
+
+    // Rows as written before compression existed
+    sqlx::query("INSERT INTO objects (namespace, key, value) VALUES ('legacy', ?, ?)")
+        .bind(b"key".as_slice())
+        .bind(b"raw value".as_slice())
+        .execute(&with_pool().unwrap())
+        .await
+        .unwrap();
+    sqlx::query("UPDATE steps SET log_data = ? WHERE id = ?")
+        .bind(b"raw log".as_slice())
+        .bind(step_id)
+        .execute(&with_pool().unwrap())
+        .await
+        .unwrap();
+
+    set_compression(true);
+    let value = queries::get_object("legacy", b"key").await;
+    let log = queries::get_step_log(step_id).await;
+    set_compression(false);
+
+    assert_eq!(value.unwrap(), b"raw value");
+    assert_eq!(log.unwrap(), b"raw log");
+}
+
+#[tokio::test]
+async fn test_object_checksum_mismatch() {
+    let _guard = setup_db().await;
+
+    set_integrity_checks(true);
+    queries::put_object("checked", b"key", b"original", None)
+        .await
+        .unwrap();
+    let intact = queries::get_object("checked", b"key").await;
+    sqlx::query("UPDATE objects SET value = ? WHERE namespace = 'checked'")
+        .bind(b"tampered".as_slice())
+        .execute(&with_pool().unwrap())
+        .await
+        .unwrap();
+    let tampered = queries::get_object("checked", b"key").await;
+    set_integrity_checks(false);
+
+    assert_eq!(intact.unwrap(), b"original");
+    match tampered {
+        Err(PapError::Database(message)) => {
+            assert!(message.starts_with("checksum mismatch"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_get_step_log_missing_and_empty() {
+    let (_guard, server) = setup_server().await;
+
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    let step_id = queries::get_job_status(pipeline.jobs[0])
+        .await
+        .unwrap()
+        .steps[0]
+        .id;
+    let get_log = |id| server.clone().get_step_log(tarpc::context::current(), id);
+
+    let result = get_log(step_id + 1).await;
+    assert!(matches!(result, Err(PapError::NotFound(_))), "{:?}", result);
+
+    // A step that hasn't run has no log yet
+    assert_eq!(get_log(step_id).await.unwrap(), b"");
+
+    let output = StepOutput {
+        kind: OutputKind::Text,
+        data: b"Hello, world!\n".to_vec(),
+    };
+    queries::set_step_output(step_id, &output).await.unwrap();
+    assert_eq!(get_log(step_id).await.unwrap(), b"Hello, world!\n");
+}
+
+#[tokio::test]
+async fn test_object_exists() {
+    let (_guard, server) = setup_server().await;
+
+    let exists = |namespace: &str, key: &[u8]| {
+        server.clone().object_exists(
+            tarpc::context::current(),
+            namespace.to_string(),
+            key.to_vec(),
+        )
+    };
+    assert!(!exists("corpus", b"seed").await.unwrap());
+
+    server
+        .clone()
+        .put_object(
+            tarpc::context::current(),
+            "corpus".to_string(),
+            b"seed".to_vec(),
+            b"value".to_vec(),
+        )
+        .await
+        .unwrap();
+    assert!(exists("corpus", b"seed").await.unwrap());
+    // Only the exact namespace and key match
+    assert!(!exists("corpus", b"see").await.unwrap());
+    assert!(!exists("other", b"seed").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_max_object_size() {
+    let (_guard, server) = setup_server().await;
+
+    set_max_object_size(16);
+    let put = |value: Vec<u8>| {
+        server.clone().put_object(
+            tarpc::context::current(),
+            "sized".to_string(),
+            b"key".to_vec(),
+            value,
+        )
+    };
+    let oversized = put(vec![0; 17]).await;
+    let at_limit = put(vec![0; 16]).await;
+    let batch = ObjectBatch::new("sized".to_string(), None, 4, Duration::from_secs(60));
+    let batched = batch.put(b"batched", &[0; 17]);
+    set_max_object_size(DEFAULT_MAX_OBJECT_SIZE);
+
+    match oversized {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("exceeds max size"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    at_limit.unwrap();
+    assert!(batched.is_err());
+    assert_eq!(
+        queries::get_object("sized", b"key").await.unwrap(),
+        vec![0; 16]
+    );
+}
+
+struct QuotaWriterExecutor;
+
+impl StepExecutor for QuotaWriterExecutor {
+    fn name(&self) -> String {
+        "quota-writer".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        for i in 0..10u32 {
+            ctx.write_object("quota", &i.to_be_bytes(), &[0; 100])?;
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_object_quota() {
+    let mut registry = StepExecutorRegistry::default();
+    registry.register(QuotaWriterExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let config = r#"
+projects: []
+object_quota: 450
+jobs:
+  - name: fill
+    steps:
+      - name: write-objects
+        call: quota-writer
+        args: {}
+"#;
+    let context = Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    // Writes stop at the one that would exceed the quota
+    assert_eq!(queries::get_object_keys("quota").await.unwrap().len(), 4);
+
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => assert!(message.contains("Quota exceeded")),
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test]
+async fn test_object_quota_running_total() {
+    let _guard = setup_db().await;
+
+    let mut context = hello_context();
+    context.config.object_quota = Some(250);
+    let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+    let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+        .bind(pipeline.id)
+        .fetch_one(&with_pool().unwrap())
+        .await
+        .unwrap();
+    let used = || async {
+        sqlx::query_scalar::<_, i64>("SELECT object_bytes FROM pipelines WHERE id = ?")
+            .bind(pipeline.id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap()
+    };
+
+    queries::put_object("total", b"a", &[1; 100], Some(step))
+        .await
+        .unwrap();
+    queries::put_object("total", b"b", &[2; 100], Some(step))
+        .await
+        .unwrap();
+    assert_eq!(used().await, 200);
+
+    // Overwriting a key only counts its new size
+    queries::put_object("total", b"a", &[3; 10], Some(step))
+        .await
+        .unwrap();
+    assert_eq!(used().await, 110);
+    queries::put_object("total", b"c", &[4; 100], Some(step))
+        .await
+        .unwrap();
+    assert!(queries::put_object("total", b"d", &[5; 100], Some(step))
+        .await
+        .is_err());
+    assert_eq!(used().await, 210);
+
+    queries::delete_step_objects(step).await.unwrap();
+    assert_eq!(used().await, 0);
+}
+
+#[tokio::test]
+async fn test_object_quota_overwriting_other_keys() {
+    let _guard = setup_db().await;
+
+    let mut steps = Vec::new();
+    for quota in [None, Some(50)] {
+        let mut context = hello_context();
+        context.config.object_quota = quota;
+        let pipeline = queries::setup_pipeline(&context, false).await.unwrap();
+        let step: u32 = sqlx::query_scalar("SELECT id FROM steps WHERE pipeline_id = ?")
+            .bind(pipeline.id)
+            .fetch_one(&with_pool().unwrap())
+            .await
+            .unwrap();
+        steps.push(step);
+    }
+    let (unlimited, limited) = (steps[0], steps[1]);
+
+    // Keys another pipeline wrote, or that no pipeline did, are charged to
+    // the pipeline overwriting them
+    queries::put_object("big", b"theirs", &[1; 100], Some(unlimited))
+        .await
+        .unwrap();
+    queries::put_object("big", b"unowned", &[2; 100], None)
+        .await
+        .unwrap();
+    for key in [&b"theirs"[..], b"unowned"] {
+        let err = queries::put_object("big", key, &[3; 100], Some(limited))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<PapError>(),
+                Some(PapError::QuotaExceeded(_))
+            ),
+            "got {:?}",
+            err
+        );
+    }
+    assert_eq!(
+        queries::get_object("big", b"theirs").await.unwrap(),
+        vec![1; 100]
+    );
+    assert_eq!(
+        queries::get_object("big", b"unowned").await.unwrap(),
+        vec![2; 100]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pipeline_events() {
+    let (_guard, server) = setup_server().await;
+
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    // The last event is published just after the status changes
+    let records = || async {
+        server
+            .clone()
+            .events(tarpc::context::current(), None)
+            .await
+            .unwrap()
+    };
+    let finished = wait_until(|| async {
+        matches!(
+            records().await.last().map(|r| &r.event),
+            Some(PipelineEvent::PipelineFinished { .. })
+        )
+    })
+    .await;
+    assert!(finished);
+    let records = records().await;
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let (job_id, step_id) = (job.id, job.steps[0].id);
+    let events: Vec<_> = records.iter().map(|r| r.event.clone()).collect();
+    assert_eq!(
+        events,
+        vec![
+            PipelineEvent::PipelineSubmitted { pipeline_id: id },
+            PipelineEvent::JobStarted {
+                pipeline_id: id,
+                job_id
+            },
+            PipelineEvent::StepStarted {
+                pipeline_id: id,
+                job_id,
+                step_id
+            },
+            PipelineEvent::StepFinished {
+                pipeline_id: id,
+                job_id,
+                step_id,
+                status: ExecutionStatus::Completed
+            },
+            PipelineEvent::JobFinished {
+                pipeline_id: id,
+                job_id,
+                status: ExecutionStatus::Completed
+            },
+            PipelineEvent::PipelineFinished {
+                pipeline_id: id,
+                status: ExecutionStatus::Completed
+            },
+        ]
+    );
+    assert!(records.windows(2).all(|w| w[1].id == w[0].id + 1));
+
+    // Asking for what follows an event skips it and everything before it
+    let newer = server
+        .events(tarpc::context::current(), Some(records[3].id))
+        .await
+        .unwrap();
+    assert_eq!(newer, records[4..]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_run_pipeline() {
+    let _guard = DB_LOCK.lock().await;
+
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    let config = load_config(HELLO_CONFIG.as_bytes()).unwrap();
+    let status = crate::run_pipeline(config, ".".into(), pool).await.unwrap();
+    assert_eq!(status.status, ExecutionStatus::Completed);
+    assert!(status.error.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_spawn_pipeline_events() {
+    let _guard = DB_LOCK.lock().await;
+
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    let config = load_config(HELLO_CONFIG.as_bytes()).unwrap();
+    let run = crate::spawn_pipeline(config, ".".into(), pool).await;
+    let mut run = run.unwrap();
+
+    let mut events = Vec::new();
+    while let Some(event) = run.next_event().await {
+        events.push(event);
+    }
+    assert!(matches!(events[0], PipelineEvent::JobStarted { .. }));
+    assert!(matches!(
+        events.last(),
+        Some(PipelineEvent::PipelineFinished {
+            status: ExecutionStatus::Completed,
+            ..
+        })
+    ));
+
+    let status = run.wait().await.unwrap();
+    assert_eq!(status.status, ExecutionStatus::Completed);
+}
+
+#[tokio::test]
+async fn test_health() {
+    let (_guard, server) = setup_server().await;
+
+    let health = server.health(tarpc::context::current()).await.unwrap();
+    assert!(health.db_ok);
+    assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(health.active_pipelines, 0);
+}
+
+#[tokio::test]
+async fn test_api_version() {
+    let (_guard, server) = setup_server().await;
+
+    let version = server.api_version(tarpc::context::current()).await.unwrap();
+    assert!(pap_api::check_api_version(version).is_ok());
+}
+
+#[tokio::test]
+async fn test_invalid_status_error() {
+    let (_guard, server) = setup_server().await;
+
+    let pipeline = queries::setup_pipeline(&hello_context(), false)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE pipelines SET execution_status = 'Bogus' WHERE id = ?")
+        .bind(pipeline.id)
+        .execute(&crate::db::with_pool().unwrap())
+        .await
+        .unwrap();
+
+    match server
+        .get_pipeline(tarpc::context::current(), pipeline.id)
+        .await
+    {
+        Err(PapError::Database(message)) => assert_eq!(
+            message,
+            format!("invalid status 'Bogus' for pipeline {}", pipeline.id)
+        ),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+const GREP_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: scan
+    steps:
+      - name: find-marker
+        call: grep
+        args:
+          pattern: "AB+C"
+        io:
+          namespace: dumps
+          key: memory
+          output: matches
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_grep_step() {
+    let (_guard, server) = setup_server().await;
+
+    queries::put_object("dumps", b"memory", b"xxABCyyABBBC\0ABzC", None)
+        .await
+        .unwrap();
+
+    let context = Context {
+        config: load_config(GREP_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let matches = queries::get_object("matches", b"memory").await.unwrap();
+    assert_eq!(matches, b"0x2\n0x7\n");
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = String::from_utf8(job.steps[0].output.clone().unwrap().data).unwrap();
+    assert!(log.starts_with("Found 2 matches"), "{}", log);
+}
+
+/// Serves one canned HTTP response per connection and returns the base URL
+#[cfg(feature = "fetch")]
+async fn mock_http_server(content_type: &'static str, body: &'static [u8]) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await;
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                content_type,
+                body.len()
+            );
+            let _ = stream.write_all(header.as_bytes()).await;
+            let _ = stream.write_all(body).await;
+        }
+    });
+    format!("http://{}", addr)
+}
+
+#[cfg(feature = "fetch")]
+fn fetch_context(url: &str, extra_args: &str) -> Context {
+    let config = format!(
+        r#"
+projects: []
+jobs:
+  - name: import
+    steps:
+      - name: get-seed
+        call: fetch
+        args:
+          url: "{}"
+          key: corpus{}
+        io:
+          output: seeds
+"#,
+        url, extra_args
+    );
+    Context {
+        config: load_config(config.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    }
+}
+
+#[cfg(feature = "fetch")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_fetch_step() {
+    let (_guard, server) = setup_server().await;
+    let url = mock_http_server("application/octet-stream", b"\x00seed\xff").await;
+
+    // Bodies over the limit or with an unexpected type aren't stored
+    let limits = [
+        "\n          max_size: 3",
+        "\n          content_type: \"text/plain\"",
+    ];
+    for extra_args in limits {
+        let id = server
+            .clone()
+            .submit_pipeline(tarpc::context::current(), fetch_context(&url, extra_args))
+            .await
+            .unwrap()
+            .id;
+        assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+        assert!(queries::get_object("seeds", b"corpus").await.is_err());
+    }
+
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            fetch_context(&format!("{}/seed.bin", url), ""),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+    assert_eq!(
+        queries::get_object("seeds", b"corpus").await.unwrap(),
+        b"\x00seed\xff"
+    );
+}
+
+#[cfg(feature = "fetch")]
+#[tokio::test]
+async fn test_fetch_step_rejects_scheme() {
+    let (_guard, server) = setup_server().await;
+
+    let result = server
+        .submit_pipeline(
+            tarpc::context::current(),
+            fetch_context("file:///etc/passwd", ""),
+        )
+        .await;
+    match result {
+        Err(PapError::Configuration(message)) => {
+            assert!(message.contains("unsupported url scheme"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+struct EchoInputExecutor;
+
+impl StepExecutor for EchoInputExecutor {
+    fn name(&self) -> String {
+        "echo-input".to_string()
+    }
+
+    fn execute(&self, ctx: &mut StepContext) -> anyhow::Result<()> {
+        let input = ctx
+            .resolve_io("input")?
+            .ok_or_else(|| anyhow::anyhow!("missing `input` IO"))?;
+        let input = String::from_utf8_lossy(&input);
+        ctx.log(&format!("Input: {}", input.trim_end()));
+        Ok(())
+    }
+}
+
+const STEP_REFERENCE_CONFIG: &str = r#"
+projects: []
+jobs:
+  - name: chain
+    steps:
+      - name: say-hello
+        call: hello
+        args:
+          name: world
+      - name: echo
+        call: echo-input
+        args: {}
+        io:
+          input: step://say-hello/output
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_step_reads_previous_output() {
+    let mut registry = builtin_executors();
+    registry.register(EchoInputExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    let context = Context {
+        config: load_config(STEP_REFERENCE_CONFIG.as_bytes()).unwrap(),
+        files: HashMap::new(),
+    };
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    assert_eq!(
+        job.steps[1].output.as_ref().map(|output| &output.data[..]),
+        Some(&b"Input: Hello, world!\n"[..])
+    );
+}
+
+#[tokio::test]
+async fn test_validate_forward_step_reference() {
+    let mut registry = builtin_executors();
+    registry.register(EchoInputExecutor);
+    let (_guard, server) = setup_server_with(registry).await;
+
+    // Swap the steps so the reference points at a step that runs later
+    let mut config = load_config(STEP_REFERENCE_CONFIG.as_bytes()).unwrap();
+    config.jobs[0].steps.reverse();
+    let context = Context {
+        config,
+        files: HashMap::new(),
+    };
+    let result = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await;
+    assert!(matches!(result, Err(PapError::Configuration(_))));
+}
+
+#[test]
+fn test_minimize_input() {
+    // Stands in for a harness that crashes on a magic value anywhere in the input
+    let crashes = |input: &[u8]| input.windows(4).any(|w| w == b"BOOM");
+
+    let mut input = vec![b'A'; 100];
+    input.splice(40..40, *b"BOOM");
+    assert!(crashes(&input));
+
+    let minimized = minimize_input(&input, crashes);
+    assert_eq!(minimized, b"BOOM");
+}
+
+#[test]
+fn test_minimize_input_zeroes_bytes() {
+    // Crashes on long enough inputs starting with a marker byte
+    let crashes = |input: &[u8]| input.len() >= 8 && input[0] == 0xff;
+
+    let minimized = minimize_input(&[0xff; 32], crashes);
+    assert_eq!(minimized, [0xff, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_triage_report_groups_sites() {
+    let registers = |pc: u64| [("pc".to_string(), pc)].into_iter().collect();
+    let read_fault = "UnhandledException(ReadUnmapped)".to_string();
+
+    let mut report = TriageReport::default();
+    report.add(&[0], read_fault.clone(), 0x8000, registers(0x8000));
+    report.add(&[1], "Halt".to_string(), 0x8100, registers(0x8100));
+    report.add(&[2], read_fault.clone(), 0x8000, registers(0x8000));
+
+    assert_eq!(report.crashes, 3);
+    assert_eq!(report.sites.len(), 2);
+    assert_eq!(report.sites[0].exit, read_fault);
+    assert_eq!(report.sites[0].inputs, vec!["00", "02"]);
+    assert_eq!(report.sites[1].pc, 0x8100);
+    assert_eq!(report.sites[1].inputs, vec!["01"]);
+}
+
+/// Thumb code for `if (input[0] == 'A') { target: } return;`, with the input
+/// pointer in r0 and the target at offset 6
+const GUARDED_TARGET_CODE: &[u8] = &[
+    0x01, 0x78, // ldrb r1, [r0]
+    0x41, 0x29, // cmp r1, #0x41
+    0x00, 0xd1, // bne ret
+    0x00, 0xbf, // target: nop
+    0x70, 0x47, // ret: bx lr
+];
+
+/// Where icicle test code is loaded
+const ICICLE_CODE_BASE: u64 = 0x8000;
+
+/// Builds a context running the given step YAML against `code`, loaded as a
+/// Cortex-M project whose harness passes the input pointer in r0
+fn icicle_context(step: &str, code: &[u8]) -> Context {
+    let config = format!(
+        r#"
+projects:
+  - name: target
+    binary: target.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: {}
+      stack_address: 536936448
+    mmio: []
+jobs:
+  - name: run
+    steps:
+{}"#,
+        ICICLE_CODE_BASE, step
+    );
+    Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .add_file("target.bin", code.to_vec())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_target_reached() {
+    let (_guard, server) = setup_server().await;
+
+    let step = format!(
+        r#"
+      - name: reach-target
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          target_address: "{:#x}"
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: reached
+"#,
+        ICICLE_CODE_BASE,
+        ICICLE_CODE_BASE + 6
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, GUARDED_TARGET_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    // Every stored solution must have passed the guard
+    let solutions = queries::get_object_keys("reached").await.unwrap();
+    assert!(!solutions.is_empty());
+    for key in solutions {
+        let input = queries::get_object("reached", &key).await.unwrap();
+        assert_eq!(input[0], b'A');
+    }
+}
+
+/// Thumb code for a function that always reads from unmapped memory
+const CRASHING_CODE: &[u8] = &[
+    0x00, 0x22, // movs r2, #0
+    0x11, 0x68, // ldr r1, [r2]
+    0x70, 0x47, // bx lr
+];
+
+/// Thumb code that spins forever on inputs starting with a byte of 0x80 or
+/// more, and crashes on the rest
+const HANGING_CODE: &[u8] = &[
+    0x01, 0x78, // ldrb r1, [r0]
+    0x80, 0x29, // cmp r1, #0x80
+    0x02, 0xd2, // bcs spin
+    0x00, 0x22, // movs r2, #0
+    0x11, 0x68, // ldr r1, [r2]
+    0x70, 0x47, // bx lr
+    0xfe, 0xe7, // spin: b spin
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_reproducer() {
+    let (_guard, server) = setup_server().await;
+
+    let steps = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+          reproducers: repro
+      - name: replay
+        call: reproduce
+        io:
+          reproducers: repro
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&steps, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let keys = queries::get_object_keys("repro").await.unwrap();
+    assert!(!keys.is_empty());
+    let reproducer: serde_json::Value =
+        serde_json::from_slice(&queries::get_object("repro", &keys[0]).await.unwrap()).unwrap();
+    assert_eq!(reproducer["exit"], "UnhandledException(ReadUnmapped)");
+    assert_eq!(reproducer["project"]["name"], "target");
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = queries::get_step_log(job.steps[1].id).await.unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains(&format!("Reproduced {} crashes", keys.len())),
+        "{}",
+        log
+    );
+
+    // Replaying against a different binary of the same name fails clearly
+    let replay = r#"
+      - name: replay
+        call: reproduce
+        io:
+          reproducers: repro
+"#;
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(replay, HANGING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Failed
+    );
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(message.contains("was recorded against"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+
+    // A reproducer that no longer matches how the input crashes fails the step
+    let mut tampered = reproducer;
+    tampered["pc"] = serde_json::json!(0x1234);
+    queries::put_object(
+        "repro",
+        &keys[0],
+        &serde_json::to_vec(&tampered).unwrap(),
+        None,
+    )
+    .await
+    .unwrap();
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(replay, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Failed
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_instruction_limit() {
+    let (_guard, server) = setup_server().await;
+
+    // About half the generated inputs spin, and must time out for the
+    // fuzzer to get on to the crashing ones
+    let step = format!(
+        r#"
+      - name: hang
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          instruction_limit: "1000"
+          stop_on_solution: "true"
+          verbose: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, HANGING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = queries::get_step_log(job.steps[0].id).await.unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("Timeout for input"), "{}", log);
+    assert!(log.contains("InstructionLimit"), "{}", log);
+}
+
+/// Thumb code that writes to a new stack page after another, without end, on
+/// inputs starting with a byte of 0x80 or more, and crashes on the rest
+const ALLOCATING_CODE: &[u8] = &[
+    0x01, 0x78, // ldrb r1, [r0]
+    0x80, 0x29, // cmp r1, #0x80
+    0x02, 0xd2, // bcs grow
+    0x00, 0x22, // movs r2, #0
+    0x11, 0x68, // ldr r1, [r2]
+    0x70, 0x47, // bx lr
+    0x69, 0x46, // grow: mov r1, sp
+    0x01, 0x22, // movs r2, #1
+    0x12, 0x03, // lsls r2, r2, #12
+    0x89, 0x1a, // loop: subs r1, r1, r2
+    0x09, 0x60, // str r1, [r1]
+    0xfc, 0xe7, // b loop
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_memory_limit() {
+    let (_guard, server) = setup_server().await;
+
+    // The stack region is far larger than the limit, so the inputs that
+    // keep writing to it run out of memory before they run off its end
+    let step = format!(
+        r#"
+      - name: grow
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          memory_limit: "1048576"
+          stop_on_solution: "true"
+          verbose: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, ALLOCATING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = queries::get_step_log(job.steps[0].id).await.unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("Oom for input"), "{}", log);
+    assert!(log.contains("OutOfMemory"), "{}", log);
+}
+
+#[test]
+fn test_icicle_vm_config() {
+    let config = load_config(
+        r#"
+projects:
+  - name: fast
+    binary: target.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+    vm:
+      enable_jit: true
+  - name: default
+    binary: target.bin
+    arch: thumbv7m-none-eabi
+    mmio: []
+jobs: []
+"#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    // Only the options that are set change
+    let fast = fuzzer::vm_config(&config.projects[0]);
+    assert!(fast.enable_jit);
+    assert!(!fast.enable_jit_mem);
+    assert!(!fast.enable_recompilation);
+    assert!(!fast.enable_shadow_stack);
+
+    let default = fuzzer::vm_config(&config.projects[1]);
+    assert!(!default.enable_jit);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_verbose_crash_log() {
+    let (_guard, server) = setup_server().await;
+
+    // Every input crashes, so the first run yields a solution and stops
+    let step = format!(
+        r#"
+      - name: crash
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+          verbose: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = server
+        .get_step_log(tarpc::context::current(), job.steps[0].id)
+        .await
+        .unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("Crash for input of"), "{}", log);
+    assert!(log.contains("UnhandledException ReadUnmapped"), "{}", log);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_log_level() {
+    let (_guard, server) = setup_server().await;
+
+    let step = |level: &str| {
+        format!(
+            r#"
+      - name: crash
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+          log_level: {}
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+            ICICLE_CODE_BASE, level
+        )
+    };
+
+    let mut logs = Vec::new();
+    for level in ["quiet", "verbose"] {
+        let id = server
+            .clone()
+            .submit_pipeline(
+                tarpc::context::current(),
+                icicle_context(&step(level), CRASHING_CODE),
+            )
+            .await
+            .unwrap()
+            .id;
+        assert_eq!(
+            wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+            ExecutionStatus::Completed
+        );
+        let pipeline = queries::get_pipeline_status(id).await.unwrap();
+        let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+        logs.push(String::from_utf8(job.steps[0].output.clone().unwrap().data).unwrap());
+    }
+    let (quiet, verbose) = (&logs[0], &logs[1]);
+
+    // Both summarize the run, but only verbose describes each input
+    assert!(quiet.contains("Ran "), "{}", quiet);
+    assert!(!quiet.contains("Crash for input of"), "{}", quiet);
+    assert!(verbose.contains("Crash for input of"), "{}", verbose);
+    assert!(
+        quiet.len() * 2 < verbose.len(),
+        "quiet:\n{}\nverbose:\n{}",
+        quiet,
+        verbose
+    );
+
+    let result = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step("loud"), CRASHING_CODE),
+        )
+        .await;
+    assert!(
+        matches!(&result, Err(PapError::Configuration(msg)) if msg.contains("log_level")),
+        "{:?}",
+        result
+    );
+
+    // The deprecated flag can't be combined with the level it stands for
+    let both = step("quiet").replace("log_level:", "verbose: \"true\"\n          log_level:");
+    let result = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&both, CRASHING_CODE),
+        )
+        .await;
+    assert!(
+        matches!(&result, Err(PapError::Configuration(msg)) if msg.contains("verbose")),
+        "{:?}",
+        result
+    );
+
+    // Executors that don't log by level reject the argument rather than
+    // ignoring it
+    let mut context = hello_context();
+    context.config.jobs[0].steps[0]
+        .args
+        .insert("log_level".to_string(), "quiet".to_string());
+    let result = server
+        .submit_pipeline(tarpc::context::current(), context)
+        .await;
+    assert!(
+        matches!(&result, Err(PapError::Configuration(msg)) if msg.contains("log_level")),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_persistent_mode() {
+    let (_guard, server) = setup_server().await;
+
+    // Most inputs return without reaching the target, so most runs skip the
+    // restore
+    let step = format!(
+        r#"
+      - name: persistent
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          target_address: "{:#x}"
+          stop_on_solution: "true"
+          persistent_iters: "8"
+        io:
+          input: seeds
+          output: corpus
+          solutions: reached
+"#,
+        ICICLE_CODE_BASE,
+        ICICLE_CODE_BASE + 6
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, GUARDED_TARGET_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = queries::get_step_log(job.steps[0].id).await.unwrap();
+    let log = String::from_utf8(log).unwrap();
+    let counts = log
+        .lines()
+        .find_map(|line| {
+            let line = line.strip_prefix("Ran ")?;
+            let (runs, rest) = line.split_once(" inputs with ")?;
+            let restores = rest.strip_suffix(" snapshot restores")?;
+            Some((runs.parse::<u64>().ok()?, restores.parse::<u64>().ok()?))
+        })
+        .unwrap_or_else(|| panic!("no run counts logged: {}", log));
+    let (runs, restores) = counts;
+    assert!(restores < runs / 2, "{} restores, {} runs", restores, runs);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_shared_vm_cache() {
+    let (_guard, server) = setup_server().await;
+
+    // Both steps prepare identical VMs, so the second reuses the first's, and
+    // each must still find the crash on its own
+    let fuzz_step = |name: &str| {
+        format!(
+            r#"
+      - name: {}
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: {}-crashes
+"#,
+            name, ICICLE_CODE_BASE, name
+        )
+    };
+    let steps = fuzz_step("first") + &fuzz_step("second");
+
+    let hits = vm_cache::hits();
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&steps, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+    for namespace in ["first-crashes", "second-crashes"] {
+        let solutions = queries::get_object_keys(namespace).await.unwrap();
+        assert!(!solutions.is_empty(), "no solutions in {}", namespace);
+    }
+    assert!(vm_cache::hits() > hits);
+}
+
+#[tokio::test]
+async fn test_run_pinned_after_panic() {
+    // Fuzzing tests also run pinned work, which would take the first thread
+    let _guard = DB_LOCK.lock().await;
+
+    let result = run_pinned(|| panic!("pinned work panicked")).await;
+    assert!(result.is_err());
+
+    // The thread outlives the panic, so later work still shares it
+    let first = run_pinned(|| std::thread::current().id()).await.unwrap();
+    let second = run_pinned(|| std::thread::current().id()).await.unwrap();
+    assert_eq!(first, second);
+    assert_ne!(first, std::thread::current().id());
+}
+
+#[tokio::test]
+async fn test_run_pinned_concurrently() {
+    let _guard = DB_LOCK.lock().await;
+
+    // Each piece of work waits for the other to start, which only happens if
+    // they run on threads of their own
+    let started = Arc::new(std::sync::Barrier::new(2));
+    let work = |started: Arc<std::sync::Barrier>| {
+        run_pinned(move || {
+            started.wait();
+            std::thread::current().id()
+        })
+    };
+    let (first, second) = tokio::time::timeout(
+        Duration::from_secs(10),
+        futures::future::join(work(started.clone()), work(started)),
+    )
+    .await
+    .expect("pinned work ran one at a time");
+    assert_ne!(first.unwrap(), second.unwrap());
+}
+
+#[test]
+fn test_icicle_vm_cache_per_thread() {
+    let config = load_config(
+        r#"
+projects:
+  - name: target
+    binary: target.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: 4096
+      stack_address: 536936448
+    mmio: []
+jobs: []
+"#
+        .as_bytes(),
+    )
+    .unwrap();
+    let project = &config.projects[0];
+    let loader = project.loader.as_ref().unwrap();
+    let key = vm_cache::VmKey::new(CRASHING_CODE, project, loader, &[], None);
+    let build = || -> anyhow::Result<_> { Ok(icicle_vm::build(&fuzzer::vm_config(project))?) };
+
+    let (vm, cached) = vm_cache::checkout(&key, build).unwrap();
+    assert!(!cached);
+    vm_cache::checkin(key.clone(), vm);
+
+    // Another thread can't see this thread's VMs
+    let other = std::thread::scope(|scope| {
+        scope
+            .spawn(|| vm_cache::checkout(&key, build).unwrap().1)
+            .join()
+            .unwrap()
+    });
+    assert!(!other);
+
+    let hits = vm_cache::hits();
+    let (_vm, cached) = vm_cache::checkout(&key, build).unwrap();
+    assert!(cached);
+    assert!(vm_cache::hits() > hits);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_fuzz_stats() {
+    let (_guard, server) = setup_server().await;
+
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let stats = job.steps[0].stats.as_ref().expect("no stats recorded");
+    let stats: serde_json::Value = serde_json::from_str(stats).unwrap();
+    assert!(stats["executions"].as_u64().unwrap() > 0, "{}", stats);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_solution_keys() {
+    let (_guard, server) = setup_server().await;
+
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let stats = job.steps[0].stats.as_ref().expect("no stats recorded");
+    let stats: serde_json::Value = serde_json::from_str(stats).unwrap();
+    let listed: Vec<_> = stats["solution_keys"]
+        .as_array()
+        .unwrap_or_else(|| panic!("no solution keys: {}", stats))
+        .iter()
+        .map(|key| key.as_str().unwrap().to_string())
+        .collect();
+
+    // Every listed key can be fetched, and every stored solution is listed
+    let stored: Vec<_> = queries::get_object_keys("crashes")
+        .await
+        .unwrap()
+        .iter()
+        .map(|key| key.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        .collect();
+    assert!(!listed.is_empty(), "{}", stats);
+    assert_eq!(listed, stored);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_fuzz_checkpoint() {
+    let (_guard, server) = setup_server().await;
+
+    // Each run has namespaces of its own besides the checkpoint's
+    let step = |run: &str| {
+        format!(
+            r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+          checkpoint_interval: "1"
+        io:
+          input: seeds
+          output: {}-corpus
+          solutions: {}-crashes
+          checkpoint: checkpoints
+"#,
+            ICICLE_CODE_BASE, run, run
+        )
+    };
+    let run = |server: PipelineServer, name: &str| {
+        let step = step(name);
+        async move {
+            let id = server
+                .submit_pipeline(
+                    tarpc::context::current(),
+                    icicle_context(&step, CRASHING_CODE),
+                )
+                .await
+                .unwrap()
+                .id;
+            assert_eq!(
+                wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+                ExecutionStatus::Completed
+            );
+            let pipeline = queries::get_pipeline_status(id).await.unwrap();
+            let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+            let stats = job.steps[0].stats.as_ref().expect("no stats recorded");
+            let stats: serde_json::Value = serde_json::from_str(stats).unwrap();
+            (
+                String::from_utf8(job.steps[0].output.clone().unwrap().data).unwrap(),
+                stats["solution_keys"].as_array().unwrap().clone(),
+            )
+        }
+    };
+
+    let (log, first_keys) = run(server.clone(), "first").await;
+    assert!(!log.contains("Resuming from checkpoint"), "{}", log);
+    assert!(!first_keys.is_empty());
+
+    let checkpoint = queries::get_object("checkpoints", fuzzer::CHECKPOINT_KEY)
+        .await
+        .unwrap();
+    fuzzer::load_checkpoint(&checkpoint).unwrap();
+    let first_solutions = queries::get_object_keys("first-crashes").await.unwrap();
+
+    // A later run picks up the stored state, keeps going until it finds a
+    // solution of its own, and only lists the solutions it found itself
+    let (log, keys) = run(server, "second").await;
+    assert!(log.contains("Resuming from checkpoint"), "{}", log);
+    assert!(!keys.is_empty(), "{}", log);
+    assert!(
+        keys.iter().all(|key| !first_keys.contains(key)),
+        "{:?} {:?}",
+        first_keys,
+        keys
+    );
+
+    // It writes to its own namespaces rather than the first run's
+    assert_eq!(
+        queries::get_object_keys("first-crashes").await.unwrap(),
+        first_solutions
+    );
+    assert!(!queries::get_object_keys("second-corpus")
+        .await
+        .unwrap()
+        .is_empty());
+    let solutions: Vec<_> = queries::get_object_keys("second-crashes")
+        .await
+        .unwrap()
+        .iter()
+        .map(|key| key.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        .collect();
+    for key in &keys {
+        assert!(solutions.contains(&key.as_str().unwrap().to_string()));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_fuzz_scheduler() {
+    let (_guard, server) = setup_server().await;
+
+    let step = |scheduler: &str| {
+        format!(
+            r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+          scheduler: {}
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+            ICICLE_CODE_BASE, scheduler
+        )
+    };
+
+    for scheduler in ["weighted", "power"] {
+        let id = server
+            .clone()
+            .submit_pipeline(
+                tarpc::context::current(),
+                icicle_context(&step(scheduler), CRASHING_CODE),
+            )
+            .await
+            .unwrap()
+            .id;
+        assert_eq!(
+            wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+            ExecutionStatus::Completed,
+            "{}: {:?}",
+            scheduler,
+            pipeline_error(id).await
+        );
+    }
+
+    let id = server
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step("random"), CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(
+                message.contains("invalid scheduler value: random"),
+                "{}",
+                message
+            )
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_fuzz_dictionary() {
+    let (_guard, server) = setup_server().await;
+    queries::put_object("tokens", b"magic", b"\x7fELF", None)
+        .await
+        .unwrap();
+    queries::put_object("tokens", b"header", b"HDR:", None)
+        .await
+        .unwrap();
+    // Empty objects aren't tokens
+    queries::put_object("tokens", b"empty", b"", None)
+        .await
+        .unwrap();
+
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          stop_on_solution: "true"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+          dictionary: tokens
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(60)).await,
+        ExecutionStatus::Completed,
+        "{:?}",
+        pipeline_error(id).await
+    );
+
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let log = String::from_utf8(job.steps[0].output.clone().unwrap().data).unwrap();
+    assert!(log.contains("Loaded 2 dictionary tokens"), "{}", log);
+}
+
+/// Thumb code for a function that returns immediately
+const RETURN_CODE: &[u8] = &[
+    0x70, 0x47, // bx lr
+];
+
+fn triage_step(return_addr: u64) -> String {
+    format!(
+        r#"
+      - name: triage
+        call: triage
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr);'
+          return_addr: "{:#x}"
+        io:
+          solutions: candidates
+          output: triage
+"#,
+        ICICLE_CODE_BASE, return_addr
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_custom_return_addr() {
+    let (_guard, server) = setup_server().await;
+    queries::put_object("candidates", &[0], &[0x41; 16], None)
+        .await
+        .unwrap();
+
+    let context = icicle_context(&triage_step(0x2000), RETURN_CODE);
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(10)).await,
+        ExecutionStatus::Completed
+    );
+
+    // Returning to the configured address is a normal completion, not a crash
+    let report = queries::get_object("triage", b"report").await.unwrap();
+    let parsed: serde_json::Value = serde_json::from_slice(&report).unwrap();
+    assert_eq!(parsed["crashes"], 0);
+    assert_eq!(parsed["skipped"], serde_json::json!(["00"]));
+
+    // The report is also the step's output
+    let pipeline = queries::get_pipeline_status(id).await.unwrap();
+    let job = queries::get_job_status(pipeline.jobs[0]).await.unwrap();
+    let output = job.steps[0].output.clone().unwrap();
+    assert_eq!(output.kind, OutputKind::Json);
+    assert_eq!(output.data, report);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_invalid_harness() {
+    let (_guard, server) = setup_server().await;
+
+    // The harness is missing its closing parenthesis
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", input_addr;'
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(30)).await,
+        ExecutionStatus::Failed
+    );
+
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(message.contains("harness script failed"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_harness_op_limit() {
+    let (_guard, server) = setup_server().await;
+
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'loop {{ vm.write_reg("r0", input_addr); }}'
+          harness_op_limit: "1000"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(30)).await,
+        ExecutionStatus::Failed
+    );
+
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(message.contains("operations"), "{}", message)
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_harness_unmapped_read() {
+    let (_guard, server) = setup_server().await;
+
+    let step = format!(
+        r#"
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: 'vm.write_reg("r0", vm.read_mem_u32(0x10));'
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE
+    );
+    let id = server
+        .clone()
+        .submit_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(30)).await,
+        ExecutionStatus::Failed
+    );
+
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(
+                message.contains("failed to read memory at 0x10"),
+                "{}",
+                message
+            )
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+/// Dry runs an icicle step with a multi-line harness script and any other
+/// `args` lines, returning the error if the script fails
+async fn dry_run_harness(server: &PipelineServer, script: &str, args: &str) -> Result<(), String> {
+    let script: String = script
+        .lines()
+        .map(|line| format!("            {}\n", line.trim()))
+        .collect();
+    let step = format!(
+        r#"
+      - name: harness
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: |
+{}{}
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE, script, args
+    );
+    let id = server
+        .clone()
+        .dry_run_pipeline(
+            tarpc::context::current(),
+            icicle_context(&step, CRASHING_CODE),
+        )
+        .await
+        .unwrap()
+        .id;
+    match wait_for_pipeline_within(id, Duration::from_secs(30)).await {
+        ExecutionStatus::Completed => Ok(()),
+        _ => Err(format!("{:?}", pipeline_error(id).await)),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_harness_memory() {
+    let (_guard, server) = setup_server().await;
+
+    // Writes to the stack, then reads it back at every width. The target is
+    // little endian.
+    let script = r#"
+        let data = blob();
+        for byte in [0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11] {
+            data.push(byte);
+        }
+        vm.write_mem(0x20000000, data);
+        if vm.read_mem_u8(0x20000000) != 0x88 { throw "read_mem_u8"; }
+        if vm.read_mem_u16(0x20000000) != 0x7788 { throw "read_mem_u16"; }
+        if vm.read_mem_u32(0x20000000) != 0x55667788 { throw "read_mem_u32"; }
+        if vm.read_mem_u64(0x20000000) != 0x1122334455667788 { throw "read_mem_u64"; }
+        vm.write_reg("r0", 0x20000000);
+    "#;
+    dry_run_harness(&server, script, "").await.unwrap();
+
+    // Unmapped memory can't be written
+    let script = r#"
+        let data = blob(4, 0);
+        vm.write_mem(0x10, data);
+    "#;
+    let error = dry_run_harness(&server, script, "").await.unwrap_err();
+    assert!(
+        error.contains("failed to write memory at 0x10"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_harness_registers() {
+    let (_guard, server) = setup_server().await;
+
+    // The function's address is in pc before the script runs
+    let script = format!(
+        r#"
+        if vm.read_reg("pc") != {} {{ throw "initial pc"; }}
+        vm.write_reg("r1", 0x1234);
+        if vm.read_reg("r1") != 0x1234 {{ throw "read_reg"; }}
+        vm.set_pc({});
+        if vm.read_reg("pc") != {} {{ throw "set_pc"; }}
+    "#,
+        ICICLE_CODE_BASE,
+        ICICLE_CODE_BASE + 4,
+        ICICLE_CODE_BASE + 4
+    );
+    dry_run_harness(&server, &script, "").await.unwrap();
+
+    let error = dry_run_harness(&server, r#"vm.read_reg("r99");"#, "")
+        .await
+        .unwrap_err();
+    assert!(error.contains("unknown register: r99"), "{}", error);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_harness_input_scope() {
+    let (_guard, server) = setup_server().await;
+
+    // Dry runs set up an 8 byte input
+    let script = r#"
+        if input_addr != 0x42000000 { throw "input_addr is " + input_addr; }
+        if input_len != 8 { throw "input_len is " + input_len; }
+        vm.write_reg("r0", input_addr);
+    "#;
+    let args = r#"          input_addr: "0x42000000""#;
+    dry_run_harness(&server, script, args).await.unwrap();
+}
+
+/// Where the library project is loaded in `library_context`
+const LIBRARY_BASE: u64 = 0x10000;
+
+/// A context whose project calls into a separately loaded library project
+fn library_context(harness: &str, libraries: &str) -> Context {
+    let config = format!(
+        r#"
+projects:
+  - name: target
+    binary: target.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: {}
+      stack_address: 536936448
+    mmio: []
+  - name: lib
+    binary: lib.bin
+    arch: thumbv7m-none-eabi
+    loader:
+      base_address: {}
+      stack_address: 536936448
+    mmio: []
+jobs:
+  - name: run
+    steps:
+      - name: fuzz
+        call: icicle-fuzzer
+        args:
+          project: target
+          function: "{:#x}"
+          harness: '{}'
+          libraries: "{}"
+        io:
+          input: seeds
+          output: corpus
+          solutions: crashes
+"#,
+        ICICLE_CODE_BASE, LIBRARY_BASE, ICICLE_CODE_BASE, harness, libraries
+    );
+    Context::builder(load_config(config.as_bytes()).unwrap(), ".".into())
+        .add_file("target.bin", CRASHING_CODE.to_vec())
+        .add_file("lib.bin", vec![0xde, 0xad, 0xbe, 0xef])
+        .build()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_icicle_libraries() {
+    let (_guard, server) = setup_server().await;
+
+    // The harness fails unless both binaries are mapped where configured
+    let harness = format!(
+        "if vm.read_mem_u8({:#x}) != {:#x} {{ throw \"target\"; }} \
+         if vm.read_mem_u32({:#x}) != 0xefbeadde {{ throw \"library\"; }}",
+        ICICLE_CODE_BASE, CRASHING_CODE[0], LIBRARY_BASE
+    );
+    let id = server
+        .clone()
+        .dry_run_pipeline(tarpc::context::current(), library_context(&harness, "lib"))
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(
+        wait_for_pipeline_within(id, Duration::from_secs(30)).await,
+        ExecutionStatus::Completed,
+        "{:?}",
+        pipeline_error(id).await
+    );
+
+    let id = server
+        .clone()
+        .dry_run_pipeline(
+            tarpc::context::current(),
+            library_context("", "lib, missing"),
+        )
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(
+                message.contains("library project not found: missing"),
+                "{}",
+                message
+            )
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_return_addr_overlapping_binary() {
+    let (_guard, server) = setup_server().await;
+
+    let context = icicle_context(&triage_step(ICICLE_CODE_BASE), RETURN_CODE);
+    let id = server
+        .clone()
+        .submit_pipeline(tarpc::context::current(), context)
+        .await
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Failed);
+
+    match pipeline_error(id).await {
+        Some(PapError::Execution(message)) => {
+            assert!(
+                message.contains("overlaps the binary region"),
+                "{}",
+                message
+            )
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[tokio::test]
+async fn test_serve_channels_limit() {
+    let active = AtomicUsize::new(0);
+    let most_active = AtomicUsize::new(0);
+    let served = AtomicUsize::new(0);
+
+    let channels = futures::stream::iter(0..6);
+    crate::server::serve_channels(channels, 2, |_| async {
+        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+        most_active.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        active.fetch_sub(1, Ordering::SeqCst);
+        served.fetch_add(1, Ordering::SeqCst);
+    })
+    .await;
+
+    assert_eq!(served.load(Ordering::SeqCst), 6);
+    assert_eq!(most_active.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unix_socket_round_trip() {
+    let (_guard, server) = setup_server().await;
+
+    let path = std::env::temp_dir().join(format!("pap-server-test-{}.sock", std::process::id()));
+    let addr = ServerAddr::Unix(path.clone());
+    let second = server.clone();
+    let serving = tokio::spawn(async move { crate::server::serve(&addr, server, 4).await });
+    assert!(wait_until(|| async { path.exists() }).await);
+
+    let transport =
+        tarpc::serde_transport::unix::connect(&path, tarpc::tokio_serde::formats::Json::default)
+            .await
+            .unwrap();
+    let client = PapApiClient::new(tarpc::client::Config::default(), transport).spawn();
+    let id = client
+        .submit_pipeline(tarpc::context::current(), hello_context())
+        .await
+        .unwrap()
+        .unwrap()
+        .id;
+    assert_eq!(wait_for_pipeline(id).await, ExecutionStatus::Completed);
+
+    let pipeline = client
+        .get_pipeline(tarpc::context::current(), id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(pipeline.id, id);
+    assert_eq!(pipeline.status, ExecutionStatus::Completed);
+
+    // A socket a server is still listening on is left alone
+    let addr = ServerAddr::Unix(path.clone());
+    let err = crate::server::serve(&addr, second.clone(), 4)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("another server"), "{}", err);
+
+    // Once that server is gone, its socket is replaced
+    serving.abort();
+    let _ = serving.await;
+    assert!(path.exists());
+    let serving = tokio::spawn(async move { crate::server::serve(&addr, second, 4).await });
+    let connected =
+        wait_until(|| async { tokio::net::UnixStream::connect(&path).await.is_ok() }).await;
+    assert!(connected);
+
+    serving.abort();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_request_deadline() {
+    let _guard = DB_LOCK.lock().await;
+
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let server = PipelineServer::new(pool.clone(), builtin_executors())
+        .await
+        .unwrap();
+
+    // Holding the only connection stalls every query until it's released
+    let conn = pool.acquire().await.unwrap();
+    let mut ctx = tarpc::context::current();
+    ctx.deadline = std::time::Instant::now() + Duration::from_millis(50);
+    let started = std::time::Instant::now();
+    let result = server.clone().get_pipeline_logs(ctx, 1).await;
+    assert!(matches!(result, Err(PapError::Timeout(_))), "{:?}", result);
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // A deadline that has already passed fails without running the query
+    let mut ctx = tarpc::context::current();
+    ctx.deadline = std::time::Instant::now();
+    let result = server.clone().get_pipeline(ctx, 1).await;
+    assert!(matches!(result, Err(PapError::Timeout(_))), "{:?}", result);
+
+    drop(conn);
+    let result = server.get_pipeline_logs(tarpc::context::current(), 1).await;
+    assert!(matches!(result, Err(PapError::NotFound(_))), "{:?}", result);
+}